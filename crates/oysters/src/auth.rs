@@ -0,0 +1,266 @@
+//! Token authentication and per-key access control for the HTTP API.
+//!
+//! Requests authenticate with `Authorization: Bearer {token}` (or
+//! `X-Api-Key: {token}`). Each token is granted [`Access`] to the keys matching
+//! its rules; the admin routes (see [`ADMIN_ROUTES`](crate::config::ADMIN_ROUTES))
+//! need [`Access::Admin`] on every key (`*`) of every namespace.
+use crate::config::{AclRule, TokenConfig};
+use crate::set::WithQuery;
+use crate::watch::WatchQuery;
+use axum::body::{Body, Bytes};
+use axum::extract::{FromRequest, MatchedPath, Path, Query, Request, State};
+use axum::http::{Method, StatusCode, header};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use serde::{Deserialize, Serialize};
+
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::Arc;
+
+/// What a token is allowed to do. Every level includes the levels before it.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Access {
+    /// Read keys.
+    Read,
+    /// Read, insert, change and remove keys.
+    Write,
+    /// Everything, including the admin routes (with a `*` rule).
+    Admin,
+}
+
+/// The configured tokens.
+#[derive(Clone, Debug)]
+pub struct Auth(pub Vec<TokenConfig>);
+
+/// Compare two tokens without bailing out at the first difference.
+fn same(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// If the pattern `outer` matches every key the pattern `inner` matches.
+///
+/// Patterns can start or end with `*` (see
+/// [`Oyster::filter`](oysters_core::Oyster::filter)). Keys are patterns which
+/// only match themselves.
+fn covers(outer: &str, inner: &str) -> bool {
+    if outer == "*" {
+        return true;
+    }
+
+    if let Some(prefix) = outer.strip_suffix('*') {
+        // `a:*` covers `a:b`, `a:b:*`, and `a:*`
+        return inner.starts_with(prefix) && !inner.starts_with('*');
+    }
+
+    if let Some(suffix) = outer.strip_prefix('*') {
+        // `*:a` covers `b:a`, `*b:a`, and `*:a`
+        return inner.ends_with(suffix) && !inner.ends_with('*');
+    }
+
+    outer == inner
+}
+
+impl Auth {
+    /// Find the token sent with a request.
+    pub(crate) fn token(&self, request: &Request) -> Option<&TokenConfig> {
+        let headers = request.headers();
+        let given = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|x| x.to_str().ok())
+            .and_then(|x| x.strip_prefix("Bearer "))
+            .or_else(|| headers.get("x-api-key").and_then(|x| x.to_str().ok()))?;
+
+        self.0
+            .iter()
+            .find(|x| same(x.token.as_bytes(), given.as_bytes()))
+    }
+}
+
+impl TokenConfig {
+    /// The name of the token (or a hash of it if it doesn't have one).
+    pub fn name(&self) -> String {
+        match self.name {
+            Some(ref name) => name.clone(),
+            None => {
+                let mut hasher = DefaultHasher::new();
+                self.token.hash(&mut hasher);
+                format!("{:016x}", hasher.finish())
+            }
+        }
+    }
+
+    /// If the token has (at least) `access` to every key matching `pattern` in
+    /// the given `namespace` (`""` for the default map, or `None` for every
+    /// namespace).
+    pub fn allows(&self, access: Access, namespace: Option<&str>, pattern: &str) -> bool {
+        self.rules.iter().any(
+            |AclRule {
+                 pattern: p,
+                 access: a,
+                 namespace: n,
+             }| {
+                *a >= access && (n.is_none() || n.as_deref() == namespace) && covers(p, pattern)
+            },
+        )
+    }
+}
+
+/// Normalize a pattern (see [`Event::matches`](oysters_core::events::Event::matches))
+/// so it can be checked against rules: patterns without a leading `*` match by
+/// prefix, wherever the `*` is.
+fn normalize(pattern: &str) -> String {
+    if pattern.starts_with('*') {
+        format!("*{}", pattern.replace('*', ""))
+    } else {
+        format!("{}*", pattern.replace('*', ""))
+    }
+}
+
+/// Check the token sent with a request has access to what it's asking for.
+pub async fn authorize(
+    State(auth): State<Arc<Auth>>,
+    matched: MatchedPath,
+    params: Option<Path<HashMap<String, String>>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(token) = auth.token(&request) else {
+        return (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, "Bearer")],
+        )
+            .into_response();
+    };
+
+    let mut params = params.map(|Path(x)| x).unwrap_or_default();
+    let key = params.remove("key");
+    let writes = !matches!(*request.method(), Method::GET | Method::HEAD);
+
+    // routes in a namespace are the same as the routes outside of one
+    let (route, namespace) = match matched.as_str().strip_prefix("/ns/{name}") {
+        Some(route) => (route, params.remove("name")),
+        None => (matched.as_str(), None),
+    };
+
+    let (access, pattern, request) = match (route, key) {
+        ("/_filter" | "/_filter/keys" | "/_filter/json", _) => {
+            // the pattern is the body, so it has to be read (and put back), as
+            // long as it's within the body limit (413 otherwise)
+            let (parts, body) = request.into_parts();
+            let body =
+                match Bytes::from_request(Request::from_parts(parts.clone(), body), &()).await {
+                    Ok(body) => body,
+                    Err(e) => return e.into_response(),
+                };
+
+            (
+                Access::Read,
+                normalize(&String::from_utf8_lossy(&body)),
+                Request::from_parts(parts, Body::from(body)),
+            )
+        }
+        ("/_watch", _) => match Query::<WatchQuery>::try_from_uri(request.uri()) {
+            Ok(Query(query)) => (Access::Read, normalize(&query.pattern), request),
+            Err(_) => return StatusCode::BAD_REQUEST.into_response(),
+        },
+        // channels are checked as if they were keys in the default namespace
+        ("/_publish/{channel}", _) => (
+            Access::Write,
+            params.remove("channel").unwrap_or_default(),
+            request,
+        ),
+        ("/_subscribe/{channel}", _) => (
+            Access::Read,
+            params.remove("channel").unwrap_or_default(),
+            request,
+        ),
+        // every set is read, not just the one in the path
+        ("/_set/{key}/union" | "/_set/{key}/intersect", Some(key)) => {
+            let Ok(Query(query)) = Query::<WithQuery>::try_from_uri(request.uri()) else {
+                return StatusCode::BAD_REQUEST.into_response();
+            };
+
+            let namespace = namespace.as_deref().unwrap_or_default();
+            if !query
+                .keys()
+                .all(|x| token.allows(Access::Read, Some(namespace), x))
+            {
+                return StatusCode::FORBIDDEN.into_response();
+            }
+
+            (Access::Read, key, request)
+        }
+        ("/_incr/{key}" | "/_decr/{key}", Some(key)) => (Access::Write, key, request),
+        (_, Some(key)) if writes => (Access::Write, key, request),
+        (_, Some(key)) => (Access::Read, key, request),
+        // everything else is an admin route
+        _ => (Access::Admin, "*".to_string(), request),
+    };
+
+    // admin routes need access to every namespace
+    let namespace = match access {
+        Access::Admin => None,
+        _ => Some(namespace.unwrap_or_default()),
+    };
+
+    if !token.allows(access, namespace.as_deref(), &pattern) {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Access, covers};
+    use crate::config::{AclRule, TokenConfig};
+
+    #[test]
+    fn rules() {
+        assert!(covers("*", "a"));
+        assert!(covers("a:*", "a:b"));
+        assert!(covers("a:*", "a:b:*"));
+        assert!(!covers("a:*", "b:a"));
+        assert!(!covers("a:*", "*"));
+        assert!(!covers("a:*", "*:b"));
+        assert!(covers("*:a", "b:a"));
+        assert!(!covers("*:a", "b:*"));
+        assert!(covers("a", "a"));
+        assert!(!covers("a", "a:*"));
+
+        let token = TokenConfig {
+            token: "x".to_string(),
+            name: None,
+            rules: vec![
+                AclRule {
+                    pattern: "billing:*".to_string(),
+                    access: Access::Read,
+                    namespace: None,
+                },
+                AclRule {
+                    pattern: "cache:*".to_string(),
+                    access: Access::Write,
+                    namespace: None,
+                },
+                AclRule {
+                    pattern: "*".to_string(),
+                    access: Access::Write,
+                    namespace: Some("team".to_string()),
+                },
+            ],
+        };
+
+        assert!(token.allows(Access::Read, Some(""), "billing:1"));
+        assert!(!token.allows(Access::Write, Some(""), "billing:1"));
+        assert!(token.allows(Access::Read, Some(""), "cache:*"));
+        assert!(token.allows(Access::Write, Some(""), "cache:1"));
+        assert!(!token.allows(Access::Read, Some(""), "other"));
+        assert!(!token.allows(Access::Admin, None, "*"));
+        assert!(token.allows(Access::Write, Some("team"), "other"));
+        assert!(token.allows(Access::Write, Some("other"), "cache:1"));
+        assert!(!token.allows(Access::Read, Some("other"), "other"));
+        assert!(!token.allows(Access::Read, None, "other"));
+    }
+}
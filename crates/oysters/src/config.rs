@@ -1,4 +1,7 @@
 //! Application config manager
+use crate::auth::Access;
+use crate::namespaces::NamespaceConfig;
+use oysters_core::compression::{Codec, Compression};
 use pathbufd::PathBufD;
 use serde::{Deserialize, Serialize};
 use std::fs;
@@ -7,23 +10,372 @@ use std::io::Result;
 /// Configuration file
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct Config {
+    /// The address the `*port` options bind to. Only the loopback interface is
+    /// used by default, so nothing is exposed to the network unless a listener
+    /// asks for it.
+    #[serde(default = "default_address")]
+    pub address: String,
     /// The port to serve the server on.
     #[serde(default = "default_port")]
     pub port: u16,
+    /// Serve the HTTP API over TCP (on `port`).
+    #[serde(default = "default_tcp")]
+    pub tcp: bool,
+    /// Serve the HTTP API over TLS on `address:port`.
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+    /// Serve the HTTP API on a Unix socket (in addition to TCP, unless `tcp` is
+    /// disabled).
+    #[serde(default)]
+    pub unix: Option<UnixSocketConfig>,
+    /// The port to serve the Redis (RESP) protocol on. The RESP listener is
+    /// disabled if this isn't set.
+    #[serde(default)]
+    pub resp_port: Option<u16>,
+    /// The port to serve the memcached text protocol on. The memcached listener
+    /// is disabled if this isn't set.
+    #[serde(default)]
+    pub memcached_port: Option<u16>,
+    /// The port to serve the gRPC API on. The gRPC listener is disabled if this
+    /// isn't set.
+    #[cfg(feature = "grpc")]
+    #[serde(default)]
+    pub grpc_port: Option<u16>,
+    /// Extra listeners (in addition to the ones created by the `*port` options).
+    #[serde(default)]
+    pub listeners: Vec<ListenerConfig>,
+    /// The tokens allowed to use the HTTP API. Anyone can use the HTTP API if
+    /// this is empty. Other protocols don't check tokens, so the server won't
+    /// start with any of their listeners if this isn't empty.
+    #[serde(default)]
+    pub tokens: Vec<TokenConfig>,
+    /// Namespaces created when the server starts (in addition to the ones
+    /// created through the API).
+    #[serde(default)]
+    pub namespaces: Vec<NamespaceConfig>,
+    /// How many requests clients can make through the HTTP API.
+    #[serde(default)]
+    pub rate_limits: Vec<RateLimitConfig>,
+    /// How long (in milliseconds) to keep serving after being asked to stop
+    /// (`SIGTERM`), while `/_ready` says the server is shutting down.
+    #[serde(default)]
+    pub shutdown_delay: u64,
+    /// How values are compressed (in memory, and in the dump file).
+    #[serde(default)]
+    pub compression: CompressionConfig,
+}
+
+fn default_address() -> String {
+    "127.0.0.1".to_string()
 }
 
 fn default_port() -> u16 {
     5072
 }
 
+fn default_tcp() -> bool {
+    true
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
+            address: default_address(),
             port: default_port(),
+            tcp: default_tcp(),
+            tls: None,
+            unix: None,
+            resp_port: None,
+            memcached_port: None,
+            #[cfg(feature = "grpc")]
+            grpc_port: None,
+            listeners: Vec::new(),
+            tokens: Vec::new(),
+            namespaces: Vec::new(),
+            rate_limits: Vec::new(),
+            shutdown_delay: 0,
+            compression: CompressionConfig::default(),
+        }
+    }
+}
+
+/// The protocol served by a listener.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Protocol {
+    #[default]
+    Http,
+    Resp,
+    Memcached,
+    #[cfg(feature = "grpc")]
+    Grpc,
+}
+
+impl std::fmt::Display for Protocol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Http => "http",
+            Self::Resp => "resp",
+            Self::Memcached => "memcached",
+            #[cfg(feature = "grpc")]
+            Self::Grpc => "grpc",
+        })
+    }
+}
+
+/// A group of HTTP routes.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RouteGroup {
+    /// Reading and writing keys (`/{key}`, `/_full`, `/_filter`, `/_incr`, `/_decr`,
+    /// `/_list`, `/_hash`, `/_set`, `/_sorted`, `/_json`, `/_watch`), and
+    /// channels (`/_publish`, `/_subscribe`).
+    Data,
+    /// Managing the server (see [`ADMIN_ROUTES`]).
+    Admin,
+}
+
+/// The first segment of every admin route.
+pub const ADMIN_ROUTES: [&str; 6] = ["_dump", "_scan", "_ns", "_usage", "_metrics", "_info"];
+
+impl RouteGroup {
+    /// The group of the route matching `path` (e.g. `/_dump` or `/ns/{name}/{key}`).
+    pub fn of(path: &str) -> Self {
+        match path.split('/').nth(1) {
+            Some(x) if ADMIN_ROUTES.contains(&x) => Self::Admin,
+            _ => Self::Data,
         }
     }
 }
 
+pub fn default_routes() -> Vec<RouteGroup> {
+    vec![RouteGroup::Data, RouteGroup::Admin]
+}
+
+/// A single TCP listener
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct ListenerConfig {
+    /// The address to bind to (e.g. `127.0.0.1`, `::1`, or `0.0.0.0`).
+    #[serde(default = "default_address")]
+    pub address: String,
+    /// The port to bind to.
+    pub port: u16,
+    /// The protocol to serve.
+    #[serde(default)]
+    pub protocol: Protocol,
+    /// The route groups this listener exposes (HTTP only). Every group is
+    /// exposed by default.
+    #[serde(default = "default_routes")]
+    pub routes: Vec<RouteGroup>,
+    /// Serve over TLS (HTTP only, the server refuses to start if this is set on
+    /// any other listener).
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+}
+
+/// TLS configuration
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct TlsConfig {
+    /// The path to the certificate chain (PEM).
+    pub cert: String,
+    /// The path to the certificate's private key (PEM).
+    pub key: String,
+    /// The path to a CA bundle (PEM). Clients must present a certificate signed
+    /// by one of these CAs (mutual TLS) if this is set.
+    #[serde(default)]
+    pub client_ca: Option<String>,
+}
+
+impl ListenerConfig {
+    /// Bind the listener.
+    pub async fn bind(&self) -> Result<tokio::net::TcpListener> {
+        tokio::net::TcpListener::bind((self.address.as_str(), self.port)).await
+    }
+}
+
+impl Config {
+    /// Every TCP listener, including the ones created by the `*port` options.
+    pub fn listeners(&self) -> Vec<ListenerConfig> {
+        let shorthand = |port: u16, protocol: Protocol| ListenerConfig {
+            address: self.address.clone(),
+            port,
+            protocol,
+            routes: default_routes(),
+            tls: None,
+        };
+
+        let mut listeners = Vec::new();
+
+        if self.tcp {
+            listeners.push(ListenerConfig {
+                tls: self.tls.clone(),
+                ..shorthand(self.port, Protocol::Http)
+            });
+        }
+
+        if let Some(port) = self.resp_port {
+            listeners.push(shorthand(port, Protocol::Resp));
+        }
+
+        if let Some(port) = self.memcached_port {
+            listeners.push(shorthand(port, Protocol::Memcached));
+        }
+
+        #[cfg(feature = "grpc")]
+        if let Some(port) = self.grpc_port {
+            listeners.push(shorthand(port, Protocol::Grpc));
+        }
+
+        listeners.extend(self.listeners.iter().cloned());
+        listeners
+    }
+}
+
+/// An API token (see [`crate::auth`])
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct TokenConfig {
+    /// The token, sent as `Authorization: Bearer {token}`.
+    pub token: String,
+    /// A name for the token (used for rate limits and usage counters instead of
+    /// the token itself).
+    #[serde(default)]
+    pub name: Option<String>,
+    /// What the token is allowed to do.
+    #[serde(default)]
+    pub rules: Vec<AclRule>,
+}
+
+/// A rule granting a token access to the keys matching a pattern
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct AclRule {
+    /// The keys the rule applies to (e.g. `billing:*`, or `*` for every key).
+    pub pattern: String,
+    /// What the token can do with the keys.
+    pub access: Access,
+    /// The namespace the rule applies to (every namespace if not set).
+    #[serde(default)]
+    pub namespace: Option<String>,
+}
+
+/// A token bucket limiting how many requests each client can make (see
+/// [`crate::ratelimit`])
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct RateLimitConfig {
+    /// How many requests a client can make every second.
+    pub rate: f64,
+    /// How many requests a client can make at once (`rate` if not set).
+    #[serde(default)]
+    pub burst: Option<f64>,
+    /// The route groups the limit applies to.
+    #[serde(default = "default_routes")]
+    pub routes: Vec<RouteGroup>,
+    /// The name of the token the limit applies to (every client if not set).
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+/// Unix socket listener configuration
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct UnixSocketConfig {
+    /// The path of the socket (e.g. `/run/oysters.sock`).
+    pub path: String,
+    /// The permissions of the socket file (e.g. `0o660`).
+    #[serde(default = "default_unix_permissions")]
+    pub permissions: u32,
+}
+
+fn default_unix_permissions() -> u32 {
+    0o660
+}
+
+#[cfg(unix)]
+impl UnixSocketConfig {
+    /// Bind the socket, replacing a socket left behind by a previous run.
+    pub fn bind(&self) -> Result<tokio::net::UnixListener> {
+        use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+
+        if let Ok(metadata) = fs::symlink_metadata(&self.path)
+            && metadata.file_type().is_socket()
+        {
+            fs::remove_file(&self.path)?;
+        }
+
+        let listener = tokio::net::UnixListener::bind(&self.path)?;
+        fs::set_permissions(&self.path, fs::Permissions::from_mode(self.permissions))?;
+        Ok(listener)
+    }
+}
+
+/// The compression algorithm used for values.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CodecName {
+    #[default]
+    None,
+    Lz4,
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+/// Value compression configuration
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct CompressionConfig {
+    /// The codec used to compress values.
+    #[serde(default)]
+    pub codec: CodecName,
+    /// The compression level (zstd only).
+    #[serde(default = "default_compression_level")]
+    pub level: i32,
+    /// Values smaller than this (in bytes) are never compressed.
+    #[serde(default = "default_compression_threshold")]
+    pub threshold: usize,
+    /// The path to a trained zstd dictionary (zstd only).
+    ///
+    /// Dictionaries can be trained with `zstd --train`. Values compressed with a
+    /// dictionary can't be read without it, so don't lose it!
+    #[serde(default)]
+    pub dictionary: Option<String>,
+}
+
+fn default_compression_level() -> i32 {
+    3
+}
+
+fn default_compression_threshold() -> usize {
+    64
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            codec: CodecName::default(),
+            level: default_compression_level(),
+            threshold: default_compression_threshold(),
+            dictionary: None,
+        }
+    }
+}
+
+impl CompressionConfig {
+    /// Build the [`Compression`] described by this config.
+    pub fn build(&self) -> Result<Compression> {
+        let codec = match self.codec {
+            CodecName::None => Codec::None,
+            CodecName::Lz4 => Codec::Lz4,
+            #[cfg(feature = "zstd")]
+            CodecName::Zstd => match self.dictionary {
+                Some(ref path) => Codec::ZstdDict {
+                    level: self.level,
+                    dictionary: std::sync::Arc::new(fs::read(path)?),
+                },
+                None => Codec::Zstd { level: self.level },
+            },
+        };
+
+        Ok(Compression::new(codec, self.threshold))
+    }
+}
+
 impl Config {
     /// Read configuration file into [`Config`]
     pub fn read(contents: String) -> Self {
@@ -54,3 +406,58 @@ impl Config {
         )
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{Config, Protocol, RouteGroup};
+
+    #[test]
+    fn listeners() {
+        let config = Config::read(
+            r#"
+            resp_port = 6379
+
+            [[listeners]]
+            address = "0.0.0.0"
+            port = 8080
+            routes = ["data"]
+            "#
+            .to_string(),
+        );
+
+        let listeners = config.listeners();
+        assert_eq!(listeners.len(), 3);
+
+        // shorthands stay on loopback
+        assert_eq!(listeners[0].address, "127.0.0.1");
+        assert_eq!(listeners[0].port, 5072);
+        assert_eq!(listeners[1].protocol, Protocol::Resp);
+
+        assert_eq!(listeners[2].address, "0.0.0.0");
+        assert_eq!(listeners[2].protocol, Protocol::Http);
+        assert_eq!(listeners[2].routes, [RouteGroup::Data]);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn unix_socket() {
+        use super::UnixSocketConfig;
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = std::env::temp_dir().join(format!("oysters-{}.sock", std::process::id()));
+        let config = UnixSocketConfig {
+            path: path.to_str().unwrap().to_string(),
+            permissions: 0o600,
+        };
+
+        let listener = config.bind().unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        // the socket left behind is replaced
+        drop(listener);
+        config.bind().unwrap();
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
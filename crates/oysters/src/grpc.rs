@@ -0,0 +1,300 @@
+//! gRPC API (see `oysters_core/proto/oysters.proto`).
+//!
+//! Every operation the HTTP API exposes is available, plus streaming versions of
+//! [`Oyster::filter`](oysters_core::Oyster::filter) (`ScanStream`) and keyspace
+//! events (`Watch`).
+use crate::MapState;
+use oysters_core::grpc::oysters_server::{Oysters, OystersServer};
+use oysters_core::grpc::{Empty, Entries, Entry, Event, Key, Keys, Pattern, Pearl, Value};
+
+use std::io::ErrorKind;
+use std::pin::Pin;
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use tokio_stream::wrappers::{BroadcastStream, TcpListenerStream};
+use tokio_stream::{Stream, StreamExt};
+use tonic::{Request, Response, Status};
+use tracing::warn;
+
+type ResponseStream<T> = Pin<Box<dyn Stream<Item = Result<T, Status>> + Send>>;
+
+/// The gRPC service.
+pub struct Service(MapState);
+
+/// Serve the gRPC API on the given `listener`.
+pub async fn serve(listener: TcpListener, map: MapState) {
+    if let Err(e) = tonic::transport::Server::builder()
+        .add_service(OystersServer::new(Service(map)))
+        .serve_with_incoming(TcpListenerStream::new(listener))
+        .await
+    {
+        warn!("grpc server stopped: {e}");
+    }
+}
+
+fn not_found(key: &str) -> Status {
+    Status::not_found(format!("key {key} does not exist"))
+}
+
+impl Service {
+    /// Get every entry whose key matches `pattern`.
+    async fn entries(&self, pattern: &str) -> Vec<Entry> {
+        self.0
+            .read()
+            .await
+            .filter(pattern)
+            .into_iter()
+            .map(|(key, pearl)| Entry {
+                key,
+                value: pearl.0,
+                descriptor: Some(pearl.1.into()),
+            })
+            .collect()
+    }
+
+    /// Increment or decrement a key. Fails if the key isn't a number.
+    async fn count(&self, key: String, decr: bool) -> Result<Response<Empty>, Status> {
+        let mut writer = self.0.write().await;
+        let counted = if decr {
+            writer.decr(key.clone())
+        } else {
+            writer.incr(key.clone())
+        };
+
+        match counted {
+            Ok(_) => Ok(Response::new(Empty {})),
+            Err(e) => Err(match e.kind() {
+                ErrorKind::NotFound => not_found(&key),
+                ErrorKind::InvalidData => {
+                    Status::invalid_argument(format!("value of {key} is not an integer"))
+                }
+                _ => Status::out_of_range(e.to_string()),
+            }),
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl Oysters for Service {
+    async fn get(&self, request: Request<Key>) -> Result<Response<Value>, Status> {
+        let key = request.into_inner().key;
+        let mut writer = self.0.write().await;
+        let value = writer.get(&key).ok_or_else(|| not_found(&key))?;
+        writer.update_resource_descriptor(&key);
+        Ok(Response::new(Value { value }))
+    }
+
+    async fn get_full(&self, request: Request<Key>) -> Result<Response<Pearl>, Status> {
+        let key = request.into_inner().key;
+        match self.0.read().await.get_full(&key) {
+            Some(pearl) => Ok(Response::new(pearl.into())),
+            None => Err(not_found(&key)),
+        }
+    }
+
+    async fn insert(&self, request: Request<Entry>) -> Result<Response<Empty>, Status> {
+        let entry = request.into_inner();
+        self.0.write().await.insert(entry.key, entry.value);
+        Ok(Response::new(Empty {}))
+    }
+
+    async fn remove(&self, request: Request<Key>) -> Result<Response<Empty>, Status> {
+        self.0.write().await.remove(&request.into_inner().key);
+        Ok(Response::new(Empty {}))
+    }
+
+    async fn incr(&self, request: Request<Key>) -> Result<Response<Empty>, Status> {
+        self.count(request.into_inner().key, false).await
+    }
+
+    async fn decr(&self, request: Request<Key>) -> Result<Response<Empty>, Status> {
+        self.count(request.into_inner().key, true).await
+    }
+
+    async fn filter(&self, request: Request<Pattern>) -> Result<Response<Entries>, Status> {
+        let entries = self.entries(&request.into_inner().pattern).await;
+        Ok(Response::new(Entries { entries }))
+    }
+
+    async fn filter_keys(&self, request: Request<Pattern>) -> Result<Response<Keys>, Status> {
+        let keys = self
+            .0
+            .read()
+            .await
+            .filter_keys(&request.into_inner().pattern)
+            .into_iter()
+            .map(|x| x.to_owned())
+            .collect();
+
+        Ok(Response::new(Keys { keys }))
+    }
+
+    async fn dump(&self, _: Request<Empty>) -> Result<Response<Empty>, Status> {
+        self.0
+            .read()
+            .await
+            .dump()
+            .map_err(|e| Status::internal(e.to_string()))?;
+
+        Ok(Response::new(Empty {}))
+    }
+
+    async fn scan(&self, _: Request<Empty>) -> Result<Response<Empty>, Status> {
+        self.0.write().await.scan_sync();
+        Ok(Response::new(Empty {}))
+    }
+
+    type ScanStreamStream = ResponseStream<Entry>;
+
+    async fn scan_stream(
+        &self,
+        request: Request<Pattern>,
+    ) -> Result<Response<Self::ScanStreamStream>, Status> {
+        // only the keys are collected up front, each entry is read as it's sent
+        let keys: Vec<String> = self
+            .0
+            .read()
+            .await
+            .filter_keys(&request.into_inner().pattern)
+            .into_iter()
+            .map(|x| x.to_owned())
+            .collect();
+
+        let map = self.0.clone();
+        let entries = tokio_stream::iter(keys).then(move |key| {
+            let map = map.clone();
+            async move {
+                let pearl = map.read().await.get_full(&key)?;
+                Some(Ok(Entry {
+                    key,
+                    value: pearl.0,
+                    descriptor: Some(pearl.1.into()),
+                }))
+            }
+        });
+
+        Ok(Response::new(Box::pin(entries.filter_map(|x| x))))
+    }
+
+    type WatchStream = ResponseStream<Event>;
+
+    async fn watch(
+        &self,
+        request: Request<Pattern>,
+    ) -> Result<Response<Self::WatchStream>, Status> {
+        let pattern = request.into_inner().pattern;
+        let events = BroadcastStream::new(self.0.read().await.subscribe());
+
+        Ok(Response::new(Box::pin(events.filter_map(
+            move |event| match event {
+                Ok(event) if event.matches(&pattern) => Some(Ok(event.into())),
+                Ok(_) => None,
+                Err(BroadcastStreamRecvError::Lagged(n)) => {
+                    warn!("grpc watcher fell behind and missed {n} events");
+                    None
+                }
+            },
+        ))))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use oysters_core::Oyster;
+    use oysters_core::grpc::event::Kind;
+    use oysters_core::grpc::oysters_client::OystersClient;
+    use oysters_core::grpc::resource_descriptor::Kind as ValueKind;
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+
+    #[tokio::test]
+    async fn grpc() {
+        let map: MapState = Arc::new(RwLock::new(Oyster::new()));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener, map.clone()));
+
+        let mut client = OystersClient::connect(format!("http://{addr}"))
+            .await
+            .unwrap();
+
+        let mut events = client
+            .watch(Pattern {
+                pattern: "a:*".to_string(),
+            })
+            .await
+            .unwrap()
+            .into_inner();
+
+        let entry = |key: &str, value: &[u8]| Entry {
+            key: key.to_string(),
+            value: value.to_vec(),
+            descriptor: None,
+        };
+        let key = |key: &str| Key {
+            key: key.to_string(),
+        };
+
+        client.insert(entry("a:1", b"1")).await.unwrap();
+        client
+            .insert(entry("b:1", &[0, 159, 146, 150]))
+            .await
+            .unwrap();
+        client.incr(key("a:1")).await.unwrap();
+
+        let value = client.get(key("a:1")).await.unwrap().into_inner();
+        assert_eq!(value.value, b"2");
+
+        let value = client.get(key("b:1")).await.unwrap().into_inner();
+        assert_eq!(value.value, [0, 159, 146, 150]);
+
+        let status = client.get(key("c:1")).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::NotFound);
+
+        let status = client.incr(key("b:1")).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+
+        let pearl = client.get_full(key("a:1")).await.unwrap().into_inner();
+        let descriptor = pearl.descriptor.unwrap();
+        assert!(descriptor.cas > 0);
+        assert_eq!(descriptor.kind(), ValueKind::String);
+
+        map.write()
+            .await
+            .set_add("set".to_string(), vec![b"a".to_vec()]);
+        let pearl = client.get_full(key("set")).await.unwrap().into_inner();
+        assert_eq!(pearl.descriptor.unwrap().kind(), ValueKind::Set);
+
+        let keys = client
+            .filter_keys(Pattern {
+                pattern: "*:1".to_string(),
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(keys.keys.len(), 2);
+
+        let entries: Vec<Entry> = client
+            .scan_stream(Pattern {
+                pattern: "b:*".to_string(),
+            })
+            .await
+            .unwrap()
+            .into_inner()
+            .map(|x| x.unwrap())
+            .collect()
+            .await;
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].key, "b:1");
+
+        client.remove(key("a:1")).await.unwrap();
+
+        // only changes to `a:*` are sent
+        for kind in [Kind::Insert, Kind::Insert, Kind::Remove] {
+            let event = events.next().await.unwrap().unwrap();
+            assert_eq!(event.kind(), kind);
+            assert_eq!(event.key, "a:1");
+        }
+    }
+}
@@ -0,0 +1,117 @@
+//! Hashes (`/_hash/{key}/{field}`), see [`oysters_core::hash`].
+//!
+//! `HEAD /_hash/{key}/{field}` checks if a field exists without reading it.
+use crate::{KeyPath, MapState, content_type, metrics, wrong_kind};
+use axum::body::Bytes;
+use axum::extract::{Path, Query};
+use axum::http::{StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use oysters_core::ValueKind;
+use serde::Deserialize;
+
+use std::collections::BTreeMap;
+
+/// The key and field a route is about.
+#[derive(Deserialize)]
+pub struct FieldPath {
+    key: String,
+    field: String,
+}
+
+#[derive(Deserialize)]
+pub struct IncrQuery {
+    /// What to add to the field (`1` by default).
+    #[serde(default = "default_by")]
+    by: i64,
+}
+
+fn default_by() -> i64 {
+    1
+}
+
+/// Get every field of a hash. Values are sent as strings (invalid UTF-8 is
+/// replaced).
+pub async fn get_all(
+    Path(KeyPath { key }): Path<KeyPath>,
+    Extension(map): Extension<MapState>,
+) -> Response {
+    match metrics::read(&map).await.hash_get_all(&key) {
+        Some(fields) => Json(
+            fields
+                .into_iter()
+                .map(|(field, value)| (field, String::from_utf8_lossy(&value).to_string()))
+                .collect::<BTreeMap<_, _>>(),
+        )
+        .into_response(),
+        None => wrong_kind(ValueKind::Hash),
+    }
+}
+
+/// Get the value of a field.
+pub async fn get(
+    Path(FieldPath { key, field }): Path<FieldPath>,
+    Extension(map): Extension<MapState>,
+) -> Response {
+    let reader = metrics::read(&map).await;
+    if reader.hash_len(&key).is_none() {
+        return wrong_kind(ValueKind::Hash);
+    }
+
+    match reader.hash_get(&key, &field) {
+        Some(value) => (
+            StatusCode::OK,
+            [(header::CONTENT_TYPE, content_type(&value))],
+            value,
+        )
+            .into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+/// Set the value of a field (the body). Responds with `201 Created` if the field
+/// is new.
+pub async fn set(
+    Path(FieldPath { key, field }): Path<FieldPath>,
+    Extension(map): Extension<MapState>,
+    value: Bytes,
+) -> Response {
+    match metrics::write(&map)
+        .await
+        .hash_set(key, field, value.to_vec())
+    {
+        Some(true) => StatusCode::CREATED.into_response(),
+        Some(false) => StatusCode::OK.into_response(),
+        None => wrong_kind(ValueKind::Hash),
+    }
+}
+
+/// Remove a field.
+pub async fn remove(
+    Path(FieldPath { key, field }): Path<FieldPath>,
+    Extension(map): Extension<MapState>,
+) -> Response {
+    match metrics::write(&map).await.hash_remove(&key, &field) {
+        Some(true) => StatusCode::OK.into_response(),
+        Some(false) => StatusCode::NOT_FOUND.into_response(),
+        None => wrong_kind(ValueKind::Hash),
+    }
+}
+
+/// Add to the value of a field (`?by=`, `1` by default). Responds with the new
+/// value.
+pub async fn incr(
+    Path(FieldPath { key, field }): Path<FieldPath>,
+    Extension(map): Extension<MapState>,
+    Query(IncrQuery { by }): Query<IncrQuery>,
+) -> Response {
+    let mut writer = metrics::write(&map).await;
+    if writer.hash_len(&key).is_none() {
+        return wrong_kind(ValueKind::Hash);
+    }
+
+    match writer.hash_incr(key, field, by) {
+        Some(value) => value.to_string().into_response(),
+        None => (StatusCode::BAD_REQUEST, "field isn't a number").into_response(),
+    }
+}
@@ -0,0 +1,121 @@
+//! Health (`GET /_health`) and readiness (`GET /_ready`) checks.
+//!
+//! The server is alive as soon as it's listening, but it's only ready once every
+//! map has been restored from the dump file, the dump file can be written, and
+//! the server isn't shutting down. Neither check needs a token.
+use axum::Json;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+#[cfg(feature = "persistance")]
+use oysters_core::persistance::PATH;
+use serde::Serialize;
+
+#[cfg(feature = "persistance")]
+use std::fs::OpenOptions;
+#[cfg(feature = "persistance")]
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{LazyLock, Mutex};
+
+/// The health of the server.
+pub static HEALTH: LazyLock<Health> = LazyLock::new(Health::default);
+
+/// What the server is doing.
+#[derive(Debug, Default)]
+pub struct Health {
+    restored: AtomicBool,
+    restore_error: Mutex<Option<String>>,
+    shutting_down: AtomicBool,
+}
+
+impl Health {
+    /// Record how restoring the maps went.
+    pub fn restored(&self, result: Result<(), String>) {
+        *self.restore_error.lock().unwrap() = result.err();
+        self.restored.store(true, Ordering::Relaxed);
+    }
+
+    /// Record that the server is shutting down.
+    pub fn shutting_down(&self) {
+        self.shutting_down.store(true, Ordering::Relaxed);
+    }
+}
+
+/// The state of the server, as reported by `GET /_ready`.
+#[derive(Serialize, Debug)]
+pub struct Readiness {
+    pub ready: bool,
+    /// If every map has been restored (even if that failed).
+    pub restored: bool,
+    /// Why restoring failed.
+    pub restore_error: Option<String>,
+    /// If the dump file can be written.
+    pub writable: bool,
+    pub shutting_down: bool,
+}
+
+/// If the dump file can be written (or created, if it doesn't exist yet). The
+/// file is never created here.
+#[cfg(feature = "persistance")]
+fn writable() -> bool {
+    let path: &Path = PATH.as_ref();
+    if path.exists() {
+        return OpenOptions::new().write(true).open(path).is_ok();
+    }
+
+    path.parent()
+        .and_then(|x| x.metadata().ok())
+        .is_some_and(|x| x.is_dir() && !x.permissions().readonly())
+}
+
+/// Nothing is dumped without the `persistance` feature.
+#[cfg(not(feature = "persistance"))]
+fn writable() -> bool {
+    true
+}
+
+/// Check the server is alive.
+pub async fn health() -> &'static str {
+    "ok"
+}
+
+/// Check the server is ready to take requests.
+pub async fn ready() -> impl IntoResponse {
+    let restored = HEALTH.restored.load(Ordering::Relaxed);
+    let restore_error = HEALTH.restore_error.lock().unwrap().clone();
+    let writable = writable();
+    let shutting_down = HEALTH.shutting_down.load(Ordering::Relaxed);
+
+    let ready = restored && restore_error.is_none() && writable && !shutting_down;
+    (
+        if ready {
+            StatusCode::OK
+        } else {
+            StatusCode::SERVICE_UNAVAILABLE
+        },
+        Json(Readiness {
+            ready,
+            restored,
+            restore_error,
+            writable,
+            shutting_down,
+        }),
+    )
+}
+
+/// Wait until the server is asked to stop (`Ctrl+C`, or `SIGTERM`).
+pub async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        let mut terminate = signal(SignalKind::terminate()).expect("failed to listen for SIGTERM");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = terminate.recv() => {}
+        }
+    }
+
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+}
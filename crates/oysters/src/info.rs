@@ -0,0 +1,113 @@
+//! Information about the server (`GET /_info`).
+use crate::config::{CodecName, Config};
+use crate::namespaces::NamespacesState;
+use axum::extract::Query;
+use axum::{Extension, Json};
+use oysters_core::metrics::Stats;
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+use std::time::Instant;
+
+/// What the server was started with (see [`init`]).
+static STARTED: OnceLock<(Instant, ConfigSummary)> = OnceLock::new();
+
+/// The parts of the config worth knowing about a running server.
+#[derive(Clone, Serialize, Debug)]
+pub struct ConfigSummary {
+    /// Every TCP listener (e.g. `http 127.0.0.1:5072 (tls)`).
+    pub listeners: Vec<String>,
+    /// The path of the unix socket.
+    pub unix: Option<String>,
+    /// The number of tokens (`0` if anyone can use the HTTP API).
+    pub tokens: usize,
+    /// The number of rate limits.
+    pub rate_limits: usize,
+    /// The codec values are compressed with.
+    pub compression: CodecName,
+}
+
+impl From<&Config> for ConfigSummary {
+    fn from(config: &Config) -> Self {
+        Self {
+            listeners: config
+                .listeners()
+                .iter()
+                .map(|x| {
+                    format!(
+                        "{} {}:{}{}",
+                        x.protocol,
+                        x.address,
+                        x.port,
+                        if x.tls.is_some() { " (tls)" } else { "" }
+                    )
+                })
+                .collect(),
+            unix: config.unix.as_ref().map(|x| x.path.clone()),
+            tokens: config.tokens.len(),
+            rate_limits: config.rate_limits.len(),
+            compression: config.compression.codec,
+        }
+    }
+}
+
+/// Remember when the server started (and with what config).
+pub fn init(config: &Config) {
+    let _ = STARTED.set((Instant::now(), config.into()));
+}
+
+/// Information about the server.
+#[derive(Serialize, Debug)]
+pub struct Info {
+    /// The version of the server.
+    pub version: &'static str,
+    /// How long the server has been running (in seconds).
+    pub uptime_secs: u64,
+    /// Where items are kept (see [`Stats::backend`]).
+    pub backend: String,
+    /// A summary of the config the server was started with.
+    pub config: Option<ConfigSummary>,
+    /// The number of keys in every namespace.
+    pub keys: usize,
+    /// Approximately how much memory (in bytes) the keys in every namespace use.
+    pub memory_bytes: usize,
+    /// Every namespace (see [`crate::namespaces::Namespaces::named`]).
+    pub namespaces: BTreeMap<String, Stats>,
+}
+
+#[derive(Deserialize)]
+pub struct InfoQuery {
+    /// How many key prefixes to list for each namespace.
+    #[serde(default = "default_top")]
+    top: usize,
+}
+
+fn default_top() -> usize {
+    10
+}
+
+/// Get information about the server.
+pub async fn info(
+    Extension(namespaces): Extension<NamespacesState>,
+    Query(InfoQuery { top }): Query<InfoQuery>,
+) -> Json<Info> {
+    let mut stats = BTreeMap::new();
+    for (name, map) in namespaces.named().await {
+        stats.insert(name, crate::metrics::read(&map).await.stats(top));
+    }
+
+    Json(Info {
+        version: env!("CARGO_PKG_VERSION"),
+        uptime_secs: STARTED.get().map_or(0, |x| x.0.elapsed().as_secs()),
+        backend: stats
+            .values()
+            .next()
+            .map(|x| x.backend.clone())
+            .unwrap_or_default(),
+        config: STARTED.get().map(|x| x.1.clone()),
+        keys: stats.values().map(|x| x.keys).sum(),
+        memory_bytes: stats.values().map(|x| x.memory_bytes).sum(),
+        namespaces: stats,
+    })
+}
@@ -0,0 +1,171 @@
+//! JSON documents (`/_json/{key}`), see [`oysters_core::json`].
+//!
+//! Every route works on a path of the document (`?path=`, the whole document by
+//! default). Documents can also be filtered by a value in them
+//! (`POST /_filter/json`), which works like `/_filter/keys`.
+use crate::{KeyPath, Map, MapState, metrics, wrong_kind};
+use axum::extract::{Path, Query};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use oysters_core::ValueKind;
+use oysters_core::json::{Comparison, JsonPath};
+use serde::Deserialize;
+use serde_json::Value;
+
+#[derive(Deserialize)]
+pub struct PathQuery {
+    /// The path of the value (the whole document by default).
+    #[serde(default = "default_path")]
+    path: String,
+}
+
+#[derive(Deserialize)]
+pub struct IncrQuery {
+    /// The path of the number.
+    #[serde(default = "default_path")]
+    path: String,
+    /// What to add to the number (`1` by default).
+    #[serde(default = "default_by")]
+    by: f64,
+}
+
+#[derive(Deserialize)]
+pub struct FilterQuery {
+    /// The path of the value to compare.
+    path: String,
+    /// How to compare it.
+    op: Comparison,
+    /// What to compare it to (as JSON).
+    value: String,
+}
+
+fn default_path() -> String {
+    "$".to_string()
+}
+
+fn default_by() -> f64 {
+    1.0
+}
+
+/// The response sent when the path a request is about isn't valid.
+fn invalid_path() -> Response {
+    (StatusCode::BAD_REQUEST, "invalid path").into_response()
+}
+
+/// If `key` holds something which isn't a document.
+fn holds_other(map: &Map, key: &String) -> bool {
+    map.get_full(key)
+        .is_some_and(|x| x.1.kind != ValueKind::Json)
+}
+
+/// Get the value at a path of a document.
+pub async fn get(
+    Path(KeyPath { key }): Path<KeyPath>,
+    Extension(map): Extension<MapState>,
+    Query(PathQuery { path }): Query<PathQuery>,
+) -> Response {
+    let Some(path) = JsonPath::parse(&path) else {
+        return invalid_path();
+    };
+
+    let reader = metrics::read(&map).await;
+    if holds_other(&reader, &key) {
+        return wrong_kind(ValueKind::Json);
+    }
+
+    match reader.json_get(&key, &path) {
+        Some(value) => Json(value).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+/// Set the value at a path of a document (the body, as JSON). Responds with
+/// `201 Created` if the value is new.
+pub async fn set(
+    Path(KeyPath { key }): Path<KeyPath>,
+    Extension(map): Extension<MapState>,
+    Query(PathQuery { path }): Query<PathQuery>,
+    body: String,
+) -> Response {
+    let Some(path) = JsonPath::parse(&path) else {
+        return invalid_path();
+    };
+
+    let Ok(value) = serde_json::from_str::<Value>(&body) else {
+        return (StatusCode::BAD_REQUEST, "body isn't valid JSON").into_response();
+    };
+
+    let mut writer = metrics::write(&map).await;
+    if !path.is_root() && holds_other(&writer, &key) {
+        return wrong_kind(ValueKind::Json);
+    }
+
+    match writer.json_set(key, &path, value) {
+        Some(true) => StatusCode::CREATED.into_response(),
+        Some(false) => StatusCode::OK.into_response(),
+        None => (StatusCode::BAD_REQUEST, "path can't be set").into_response(),
+    }
+}
+
+/// Remove the value at a path of a document (or the whole document).
+pub async fn remove(
+    Path(KeyPath { key }): Path<KeyPath>,
+    Extension(map): Extension<MapState>,
+    Query(PathQuery { path }): Query<PathQuery>,
+) -> Response {
+    let Some(path) = JsonPath::parse(&path) else {
+        return invalid_path();
+    };
+
+    match metrics::write(&map).await.json_remove(&key, &path) {
+        Some(true) => StatusCode::OK.into_response(),
+        Some(false) => StatusCode::NOT_FOUND.into_response(),
+        None => wrong_kind(ValueKind::Json),
+    }
+}
+
+/// Add to the number at a path of a document (`?by=`, `1` by default). Responds
+/// with the new number.
+pub async fn incr(
+    Path(KeyPath { key }): Path<KeyPath>,
+    Extension(map): Extension<MapState>,
+    Query(IncrQuery { path, by }): Query<IncrQuery>,
+) -> Response {
+    let Some(path) = JsonPath::parse(&path) else {
+        return invalid_path();
+    };
+
+    let mut writer = metrics::write(&map).await;
+    if holds_other(&writer, &key) {
+        return wrong_kind(ValueKind::Json);
+    }
+
+    match writer.json_incr(key, &path, by) {
+        Some(value) => Json(value).into_response(),
+        None => (StatusCode::BAD_REQUEST, "value isn't a number").into_response(),
+    }
+}
+
+/// Get the keys of the documents matching a pattern (the body) where the value
+/// at `path` compares to `value` (e.g. `?path=$.age&op=gt&value=30`).
+pub async fn filter(
+    Extension(map): Extension<MapState>,
+    Query(FilterQuery { path, op, value }): Query<FilterQuery>,
+    pattern: String,
+) -> Response {
+    let Some(path) = JsonPath::parse(&path) else {
+        return invalid_path();
+    };
+
+    let Ok(value) = serde_json::from_str::<Value>(&value) else {
+        return (StatusCode::BAD_REQUEST, "value isn't valid JSON").into_response();
+    };
+
+    Json(
+        metrics::read(&map)
+            .await
+            .json_filter_keys(&pattern, &path, op, &value),
+    )
+    .into_response()
+}
@@ -0,0 +1,167 @@
+//! Lists (`/_list/{key}`), see [`oysters_core::list`].
+//!
+//! Popping can wait for a value to be pushed (`?timeout={ms}`), so a list works
+//! as a work queue: workers long-poll `POST /_list/{key}/pop`, and get the next
+//! value as soon as it's pushed.
+use crate::{KeyPath, MapState, content_type, metrics, wrong_kind};
+use axum::body::Bytes;
+use axum::extract::{Path, Query};
+use axum::http::{StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use oysters_core::ValueKind;
+use oysters_core::events::EventKind;
+use oysters_core::list::End;
+use serde::Deserialize;
+
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::{Instant, timeout_at};
+
+/// The longest (in milliseconds) a pop waits for a value.
+pub const MAX_POP_TIMEOUT: u64 = 300_000; // 5 minutes
+
+#[derive(Deserialize)]
+pub struct EndQuery {
+    /// The end of the list (the back by default).
+    #[serde(default = "default_end")]
+    end: End,
+}
+
+#[derive(Deserialize)]
+pub struct PopQuery {
+    /// The end of the list (the front by default).
+    #[serde(default = "default_pop_end")]
+    end: End,
+    /// How long (in milliseconds) to wait for a value if the list is empty (at
+    /// most [`MAX_POP_TIMEOUT`]).
+    #[serde(default)]
+    timeout: u64,
+}
+
+#[derive(Deserialize)]
+pub struct SpanQuery {
+    /// The position of the first value.
+    #[serde(default)]
+    pub start: isize,
+    /// The position of the last value.
+    #[serde(default = "default_stop")]
+    pub stop: isize,
+}
+
+fn default_end() -> End {
+    End::Back
+}
+
+fn default_pop_end() -> End {
+    End::Front
+}
+
+fn default_stop() -> isize {
+    -1
+}
+
+/// Push a value (the body) to a list. Responds with the length of the list.
+pub async fn push(
+    Path(KeyPath { key }): Path<KeyPath>,
+    Extension(map): Extension<MapState>,
+    Query(EndQuery { end }): Query<EndQuery>,
+    value: Bytes,
+) -> Response {
+    match metrics::write(&map)
+        .await
+        .push(key, vec![value.to_vec()], end)
+    {
+        Some(len) => len.to_string().into_response(),
+        None => wrong_kind(ValueKind::List),
+    }
+}
+
+/// Pop a value from a list, waiting up to `timeout` milliseconds (at most
+/// [`MAX_POP_TIMEOUT`]) for one to be pushed if the list is empty.
+pub async fn pop(
+    Path(KeyPath { key }): Path<KeyPath>,
+    Extension(map): Extension<MapState>,
+    Query(PopQuery { end, timeout }): Query<PopQuery>,
+) -> Response {
+    let now = Instant::now();
+    let deadline = now
+        .checked_add(Duration::from_millis(timeout.min(MAX_POP_TIMEOUT)))
+        .unwrap_or(now);
+
+    // subscribe first, so nothing pushed between popping and waiting is missed
+    let mut events = metrics::read(&map).await.subscribe();
+
+    loop {
+        let mut writer = metrics::write(&map).await;
+        match writer.list_len(&key) {
+            None => return wrong_kind(ValueKind::List),
+            Some(0) => drop(writer),
+            Some(_) => {
+                let value = writer.pop(&key, end).unwrap_or_default();
+                return (
+                    StatusCode::OK,
+                    [(header::CONTENT_TYPE, content_type(&value))],
+                    value,
+                )
+                    .into_response();
+            }
+        }
+
+        // wait for something to be pushed to the list
+        loop {
+            match timeout_at(deadline, events.recv()).await {
+                Ok(Ok(event)) if event.kind == EventKind::Insert && event.key == key => break,
+                Ok(Ok(_)) => continue,
+                // we might've missed a push
+                Ok(Err(RecvError::Lagged(_))) => break,
+                Ok(Err(RecvError::Closed)) | Err(_) => {
+                    return StatusCode::NOT_FOUND.into_response();
+                }
+            }
+        }
+    }
+}
+
+/// Get the values of a list between two positions (every value by default).
+/// Values are sent as strings (invalid UTF-8 is replaced).
+pub async fn range(
+    Path(KeyPath { key }): Path<KeyPath>,
+    Extension(map): Extension<MapState>,
+    Query(SpanQuery { start, stop }): Query<SpanQuery>,
+) -> Response {
+    match metrics::read(&map).await.range(&key, start, stop) {
+        Some(values) => Json(
+            values
+                .iter()
+                .map(|x| String::from_utf8_lossy(x).to_string())
+                .collect::<Vec<_>>(),
+        )
+        .into_response(),
+        None => wrong_kind(ValueKind::List),
+    }
+}
+
+/// Remove every value of a list which isn't between two positions. Responds with
+/// the length of the list.
+pub async fn trim(
+    Path(KeyPath { key }): Path<KeyPath>,
+    Extension(map): Extension<MapState>,
+    Query(SpanQuery { start, stop }): Query<SpanQuery>,
+) -> Response {
+    match metrics::write(&map).await.trim(&key, start, stop) {
+        Some(len) => len.to_string().into_response(),
+        None => wrong_kind(ValueKind::List),
+    }
+}
+
+/// Get the length of a list.
+pub async fn len(
+    Path(KeyPath { key }): Path<KeyPath>,
+    Extension(map): Extension<MapState>,
+) -> Response {
+    match metrics::read(&map).await.list_len(&key) {
+        Some(len) => len.to_string().into_response(),
+        None => wrong_kind(ValueKind::List),
+    }
+}
@@ -1,63 +1,132 @@
-use axum::http::StatusCode;
-use oysters_core::Oyster;
+use axum::body::Bytes;
+use axum::http::{StatusCode, header};
+use oysters_core::{Oyster, OysterOptions, Pearl, ValueKind};
 
 use axum::extract::Path;
-use axum::response::IntoResponse;
-use axum::routing::{delete, get, post};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{any, delete, get, post};
 use axum::{Extension, Json, Router};
 use tower_http::trace::{self, TraceLayer};
-use tracing::{Level, info};
+use tracing::{Level, error, info};
 
+use auth::Auth;
+use axum::middleware;
+use config::{Protocol, RouteGroup};
+use health::HEALTH;
+use namespaces::{Namespaces, NamespacesState};
+use ratelimit::Limiter;
+use serde::Deserialize;
+use std::io::ErrorKind;
+use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::RwLock;
 
+mod auth;
 mod config;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod hash;
+mod health;
+mod info;
+mod json;
+mod list;
+mod memcached;
+mod metrics;
+mod namespaces;
+mod pubsub;
+mod ratelimit;
+mod resp;
+mod set;
+mod sorted_set;
+mod tls;
+mod watch;
 
-type Map = Oyster<String, String>;
+type Map = Oyster<String, Vec<u8>>;
 type MapState = Arc<RwLock<Map>>;
 
+/// The content type of a value. Values which aren't valid UTF-8 are sent as
+/// `application/octet-stream`.
+fn content_type(value: &[u8]) -> &'static str {
+    if std::str::from_utf8(value).is_ok() {
+        "text/plain; charset=utf-8"
+    } else {
+        "application/octet-stream"
+    }
+}
+
+/// The response sent when a key holds a value which isn't of the `expected` kind
+/// (e.g. popping from a key which doesn't hold a list).
+fn wrong_kind(expected: ValueKind) -> Response {
+    (
+        StatusCode::CONFLICT,
+        format!(
+            "key doesn't hold a {}",
+            format!("{expected:?}").to_lowercase()
+        ),
+    )
+        .into_response()
+}
+
+/// The key a route is about (routes can have other parameters too, like the
+/// namespace in `/ns/{name}/{key}`).
+#[derive(Deserialize)]
+pub struct KeyPath {
+    key: String,
+}
+
 /// Get a value given its `key`.
 pub async fn get_value(
-    Path(key): Path<String>,
+    Path(KeyPath { key }): Path<KeyPath>,
     Extension(map): Extension<MapState>,
 ) -> impl IntoResponse {
-    let reader = map.read().await;
+    let reader = metrics::read(&map).await;
     if let Some(v) = reader.get(&key) {
-        let v = v.clone(); // we need to clone v so that it is no longer a reference to a value under reader
         drop(reader);
-        map.write().await.update_resource_descriptor(&key);
-        (StatusCode::OK, v)
+        metrics::write(&map).await.update_resource_descriptor(&key);
+        (
+            StatusCode::OK,
+            [(header::CONTENT_TYPE, content_type(&v))],
+            v,
+        )
     } else {
-        (StatusCode::NOT_FOUND, String::new())
+        (
+            StatusCode::NOT_FOUND,
+            [(header::CONTENT_TYPE, "text/plain; charset=utf-8")],
+            Vec::new(),
+        )
     }
 }
 
 /// Get a full value given its `key`.
 pub async fn get_full_value(
-    Path(key): Path<String>,
+    Path(KeyPath { key }): Path<KeyPath>,
     Extension(map): Extension<MapState>,
 ) -> impl IntoResponse {
-    if let Some(v) = map.read().await.get_full(&key) {
+    if let Some(v) = metrics::read(&map).await.get_full(&key) {
         (
             StatusCode::OK,
-            format!("Value: {}\nLast Used: {}", v.0, v.1.used),
+            format!(
+                "Value: {}\nLast Used: {}",
+                String::from_utf8_lossy(&v.0),
+                v.1.used
+            ),
         )
     } else {
         (StatusCode::NOT_FOUND, String::new())
     }
 }
 
-/// Filter all.
+/// Filter all. Values are sent as strings (invalid UTF-8 is replaced).
 pub async fn filter_all(Extension(map): Extension<MapState>, pattern: String) -> impl IntoResponse {
-    let reader = map.read().await;
-    let values = reader.filter(&pattern);
-    let mut owned_values = Vec::new();
-
-    for value in values {
-        owned_values.push((value.0.to_owned(), value.1.to_owned()));
-    }
+    let values: Vec<(String, Pearl<String>)> = metrics::read(&map)
+        .await
+        .filter(&pattern)
+        .into_iter()
+        .map(|(k, v)| (k, Pearl(String::from_utf8_lossy(&v.0).to_string(), v.1)))
+        .collect();
 
-    Json(owned_values)
+    Json(values)
 }
 
 /// Filter all by keys.
@@ -65,7 +134,7 @@ pub async fn filter_keys(
     Extension(map): Extension<MapState>,
     pattern: String,
 ) -> impl IntoResponse {
-    let reader = map.read().await;
+    let reader = metrics::read(&map).await;
     let values = reader.filter_keys(&pattern);
     let mut owned_values = Vec::new();
 
@@ -76,88 +145,389 @@ pub async fn filter_keys(
     Json(owned_values)
 }
 
-/// Insert a key.
+/// Insert a key. The body is stored as-is, so any content type (including
+/// `application/octet-stream`) can be used.
 pub async fn insert_value(
-    Path(key): Path<String>,
+    Path(KeyPath { key }): Path<KeyPath>,
     Extension(map): Extension<MapState>,
-    value: String,
+    value: Bytes,
 ) -> impl IntoResponse {
-    map.write().await.insert(key, value);
+    metrics::write(&map).await.insert(key, value.to_vec());
+}
+
+/// The response for [`Oyster::incr`] and [`Oyster::decr`]: the new number, or
+/// why it couldn't be changed.
+fn counted(result: std::io::Result<i64>) -> Response {
+    match result {
+        Ok(number) => number.to_string().into_response(),
+        Err(e) => {
+            let status = match e.kind() {
+                ErrorKind::NotFound => StatusCode::NOT_FOUND,
+                ErrorKind::InvalidData => StatusCode::CONFLICT,
+                _ => StatusCode::BAD_REQUEST,
+            };
+
+            (status, e.to_string()).into_response()
+        }
+    }
 }
 
-/// Increment a key.
+/// Increment a key. Responds with the new number.
 pub async fn incr_value(
-    Path(key): Path<String>,
+    Path(KeyPath { key }): Path<KeyPath>,
     Extension(map): Extension<MapState>,
-) -> impl IntoResponse {
-    map.write().await.incr(key);
+) -> Response {
+    counted(metrics::write(&map).await.incr(key))
 }
 
-/// Decrement a key.
+/// Decrement a key. Responds with the new number.
 pub async fn decr_value(
-    Path(key): Path<String>,
+    Path(KeyPath { key }): Path<KeyPath>,
     Extension(map): Extension<MapState>,
-) -> impl IntoResponse {
-    map.write().await.decr(key);
+) -> Response {
+    counted(metrics::write(&map).await.decr(key))
 }
 
-/// Dump the map to a database.
-pub async fn dump(Extension(map): Extension<MapState>) -> impl IntoResponse {
+/// Dump every namespace to a database.
+pub async fn dump(Extension(namespaces): Extension<NamespacesState>) -> impl IntoResponse {
     tokio::task::spawn(async move {
-        map.read().await.dump().unwrap();
+        for map in namespaces.all().await {
+            metrics::read(&map).await.dump().unwrap();
+        }
     });
 
     "Data dump in started"
 }
 
-/// Scan the map for old values and remove them.
-pub async fn scan(Extension(map): Extension<MapState>) -> impl IntoResponse {
-    map.write().await.scan_sync();
+/// Scan every namespace for old values and remove them.
+pub async fn scan(Extension(namespaces): Extension<NamespacesState>) -> impl IntoResponse {
+    for map in namespaces.all().await {
+        metrics::write(&map).await.scan_sync();
+    }
 }
 
 /// Remove a key.
 pub async fn remove_value(
-    Path(key): Path<String>,
+    Path(KeyPath { key }): Path<KeyPath>,
     Extension(map): Extension<MapState>,
 ) -> impl IntoResponse {
-    map.write().await.remove(&key);
+    metrics::write(&map).await.remove(&key);
 }
 
-#[tokio::main]
-async fn main() {
-    tracing_subscriber::fmt()
-        .with_target(false)
-        .compact()
-        .init();
-
-    let mut map: Map = Oyster::new();
-    map.restore().unwrap();
-
-    let config = config::Config::get_config();
-
-    let app = Router::new()
-        .route("/_dump", post(dump))
-        .route("/_scan", post(scan))
+/// The routes for reading and writing keys (in the map given as an extension).
+fn data_routes() -> Router {
+    Router::new()
         .route("/_full/{key}", get(get_full_value))
         .route("/_filter", post(filter_all))
         .route("/_filter/keys", post(filter_keys))
+        .route("/_filter/json", post(json::filter))
         .route("/_incr/{key}", post(incr_value))
         .route("/_decr/{key}", post(decr_value))
+        .route("/_watch", get(watch::watch))
+        .route("/_list/{key}", get(list::range))
+        .route("/_list/{key}/len", get(list::len))
+        .route("/_list/{key}/push", post(list::push))
+        .route("/_list/{key}/pop", post(list::pop))
+        .route("/_list/{key}/trim", post(list::trim))
+        .route("/_hash/{key}", get(hash::get_all))
+        .route("/_hash/{key}/{field}", get(hash::get))
+        .route("/_hash/{key}/{field}", post(hash::set))
+        .route("/_hash/{key}/{field}", delete(hash::remove))
+        .route("/_hash/{key}/{field}/incr", post(hash::incr))
+        .route("/_set/{key}", get(set::members))
+        .route("/_set/{key}/len", get(set::len))
+        .route("/_set/{key}/union", get(set::union))
+        .route("/_set/{key}/intersect", get(set::intersect))
+        .route("/_set/{key}/members/{member}", get(set::is_member))
+        .route("/_set/{key}/members/{member}", post(set::add))
+        .route("/_set/{key}/members/{member}", delete(set::remove))
+        .route("/_sorted/{key}", get(sorted_set::range))
+        .route("/_sorted/{key}/by_score", get(sorted_set::range_by_score))
+        .route("/_sorted/{key}/len", get(sorted_set::len))
+        .route("/_sorted/{key}/members/{member}", get(sorted_set::score))
+        .route("/_sorted/{key}/members/{member}", post(sorted_set::add))
+        .route(
+            "/_sorted/{key}/members/{member}",
+            delete(sorted_set::remove),
+        )
+        .route(
+            "/_sorted/{key}/members/{member}/rank",
+            get(sorted_set::rank),
+        )
+        .route(
+            "/_sorted/{key}/members/{member}/incr",
+            post(sorted_set::incr),
+        )
+        .route("/_json/{key}", get(json::get))
+        .route("/_json/{key}", post(json::set))
+        .route("/_json/{key}", delete(json::remove))
+        .route("/_json/{key}/incr", post(json::incr))
         .route("/{key}", get(get_value))
         .route("/{key}", post(insert_value))
         .route("/{key}", delete(remove_value))
-        .layer(Extension(Arc::new(RwLock::new(map))))
+}
+
+/// Build the HTTP router exposing the given route `groups`. Requests are checked
+/// against `auth` and counted by `limiter` if they are set.
+fn router(
+    groups: &[RouteGroup],
+    namespaces: NamespacesState,
+    auth: Option<Arc<Auth>>,
+    limiter: Option<Arc<Limiter>>,
+) -> Router {
+    let mut app = Router::new();
+
+    if groups.contains(&RouteGroup::Admin) {
+        app = app
+            .route("/_dump", post(dump))
+            .route("/_scan", post(scan))
+            .route("/_ns", get(namespaces::list))
+            .route("/_ns/{name}", post(namespaces::create))
+            .route("/_ns/{name}", delete(namespaces::remove))
+            .route("/_usage", get(ratelimit::usage))
+            .route("/_metrics", get(metrics::metrics))
+            .route("/_info", get(info::info));
+    } else {
+        // don't let `/{key}` pick these up
+        for path in config::ADMIN_ROUTES {
+            app = app.route(&format!("/{path}"), any(StatusCode::NOT_FOUND));
+        }
+    }
+
+    if groups.contains(&RouteGroup::Data) {
+        app = app
+            .merge(data_routes())
+            .nest(
+                "/ns/{name}",
+                data_routes().route_layer(middleware::from_fn(namespaces::select)),
+            )
+            // channels are shared by every namespace
+            .route("/_publish/{channel}", post(pubsub::publish))
+            .route("/_subscribe/{channel}", get(pubsub::subscribe));
+    }
+
+    if let Some(auth) = auth {
+        app = app.route_layer(middleware::from_fn_with_state(auth, auth::authorize));
+    }
+
+    // limits are checked before tokens
+    if let Some(limiter) = limiter {
+        app = app
+            .route_layer(middleware::from_fn_with_state(
+                limiter.clone(),
+                ratelimit::limit,
+            ))
+            .layer(Extension(limiter));
+    }
+
+    app = app.route_layer(middleware::from_fn(metrics::track));
+
+    // health checks skip tokens, limits and metrics
+    app = app
+        .route("/_health", get(health::health))
+        .route("/_ready", get(health::ready));
+
+    app.layer(Extension(namespaces.default.clone()))
+        .layer(Extension(namespaces))
         .layer(
             TraceLayer::new_for_http()
                 .make_span_with(trace::DefaultMakeSpan::new().level(Level::INFO))
                 .on_response(trace::DefaultOnResponse::new().level(Level::INFO)),
-        );
+        )
+}
 
-    let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", config.port))
-        .await
-        .unwrap();
+#[tokio::main]
+async fn main() {
+    tracing_subscriber::fmt()
+        .with_target(false)
+        .compact()
+        .init();
+
+    let config = config::Config::get_config();
+    info::init(&config);
+
+    let compression = config
+        .compression
+        .build()
+        .expect("failed to read compression dictionary");
+
+    // the maps are restored once the listeners are up (see below), requests
+    // wait for them until then
+    let map: MapState = Arc::new(RwLock::new(Oyster::with_options(OysterOptions {
+        compression: compression.clone(),
+        ..Default::default()
+    })));
+    let namespaces: NamespacesState = Arc::new(Namespaces::new(map.clone(), compression));
+    let restoring = namespaces.lock().await;
 
     info!("🦪 OYSTERS");
-    info!("listening on http://localhost:{}", config.port);
-    axum::serve(listener, app).await.unwrap();
+
+    let auth = (!config.tokens.is_empty()).then(|| Arc::new(Auth(config.tokens.clone())));
+    let limiter = Limiter::new(
+        config.rate_limits.clone(),
+        auth.clone(),
+        namespaces.system.clone(),
+    );
+
+    // bind everything up front so a bad listener fails startup
+    let mut listeners = Vec::new();
+    for listener in config.listeners() {
+        // only http checks tokens, so anything else would be an open door
+        if auth.is_some() && listener.protocol != Protocol::Http {
+            panic!(
+                "{} listener on {}:{} can't be used with tokens (only http checks them)",
+                listener.protocol, listener.address, listener.port
+            );
+        }
+
+        // only http is served over tls, so anything else would be plaintext
+        if listener.tls.is_some() && listener.protocol != Protocol::Http {
+            panic!(
+                "{} listener on {}:{} can't be served over tls (only http is)",
+                listener.protocol, listener.address, listener.port
+            );
+        }
+
+        let tcp = listener.bind().await.unwrap_or_else(|e| {
+            panic!("failed to bind {}:{}: {e}", listener.address, listener.port)
+        });
+
+        info!(
+            "{}{} listening on {}:{}",
+            listener.protocol,
+            if listener.tls.is_some() { " (tls)" } else { "" },
+            listener.address,
+            listener.port
+        );
+        listeners.push((listener, tcp));
+    }
+
+    let mut tasks = Vec::new();
+
+    #[cfg(unix)]
+    if let Some(ref unix) = config.unix {
+        let listener = unix.bind().expect("failed to bind unix socket");
+        info!("http listening on unix://{}", unix.path);
+
+        let app = router(
+            &config::default_routes(),
+            namespaces.clone(),
+            auth.clone(),
+            Some(limiter.clone()),
+        );
+        tasks.push(tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap()
+        }));
+    }
+
+    for (listener, tcp) in listeners {
+        let map = map.clone();
+        let namespaces = namespaces.clone();
+        let auth = auth.clone();
+        let limiter = limiter.clone();
+        tasks.push(tokio::spawn(async move {
+            match listener.protocol {
+                Protocol::Http => {
+                    let app = router(&listener.routes, namespaces, auth, Some(limiter));
+                    match listener.tls {
+                        Some(config) => tls::serve(tcp, app, config).await,
+                        None => axum::serve(
+                            tcp,
+                            app.into_make_service_with_connect_info::<SocketAddr>(),
+                        )
+                        .await
+                        .unwrap(),
+                    }
+                }
+                Protocol::Resp => resp::serve(tcp, map).await,
+                Protocol::Memcached => memcached::serve(tcp, map).await,
+                #[cfg(feature = "grpc")]
+                Protocol::Grpc => grpc::serve(tcp, map).await,
+            }
+        }));
+    }
+
+    if tasks.is_empty() {
+        panic!("no listeners configured");
+    }
+
+    // restore while the listeners are up, so the server can say it isn't ready
+    let configured = config.namespaces.clone();
+    tokio::spawn(async move {
+        let restored = namespaces.restore(restoring, &configured).await;
+        match restored {
+            Ok(()) => info!("restored from dump file"),
+            Err(ref e) => error!("{e}"),
+        }
+
+        limiter.restore().await;
+        HEALTH.restored(restored);
+    });
+
+    tokio::select! {
+        _ = async {
+            for task in tasks {
+                task.await.unwrap();
+            }
+        } => {}
+        _ = health::shutdown_signal() => {
+            HEALTH.shutting_down();
+            info!("shutting down in {}ms", config.shutdown_delay);
+            tokio::time::sleep(Duration::from_millis(config.shutdown_delay)).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::config::default_routes;
+    use crate::namespaces::Namespaces;
+    use oysters_client::{Client, Encoding};
+    use oysters_core::Oyster;
+    use serde::{Deserialize, Serialize};
+    use std::io::ErrorKind;
+    use std::sync::Arc;
+    use tokio::net::TcpListener;
+    use tokio::sync::RwLock;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Shell {
+        name: String,
+        pearls: Vec<u8>,
+    }
+
+    #[tokio::test]
+    async fn typed() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let map = Arc::new(RwLock::new(Oyster::new()));
+        let namespaces = Arc::new(Namespaces::new(map, Default::default()));
+        let app = crate::router(&default_routes(), namespaces, None, None);
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let client = Client::new(format!("http://127.0.0.1:{port}"));
+        let shell = Shell {
+            name: "oyster".to_string(),
+            pearls: vec![0, 1, 255],
+        };
+
+        for (key, encoding) in [
+            ("typed:json", Encoding::Json),
+            ("typed:msgpack", Encoding::MessagePack),
+            ("typed:bincode", Encoding::Bincode),
+        ] {
+            let typed = client.typed(encoding);
+            assert_eq!(typed.get::<Shell>(key).await.unwrap(), None);
+
+            typed.insert(key, &shell).await.unwrap();
+            assert_eq!(
+                typed.get::<Shell>(key).await.unwrap().as_ref(),
+                Some(&shell)
+            );
+            assert_eq!(
+                typed.get::<Vec<bool>>(key).await.unwrap_err().kind(),
+                ErrorKind::InvalidData
+            );
+        }
+    }
 }
@@ -0,0 +1,568 @@
+//! Memcached text protocol listener.
+//!
+//! Lets existing memcached clients use the same map as the HTTP server.
+//!
+//! Supported commands: `get`, `gets`, `set`, `add`, `replace`, `cas`, `delete`,
+//! `incr`, `decr`, `touch`, `stats`, `version`, and `quit`.
+//!
+//! Client flags are stored with the value (see [`ResourceDescriptor::flags`]), and
+//! cas values come from [`ResourceDescriptor::cas`], so they work the same way as
+//! they do in memcached.
+//!
+//! [`ResourceDescriptor::flags`]: oysters_core::pearl::ResourceDescriptor::flags
+//! [`ResourceDescriptor::cas`]: oysters_core::pearl::ResourceDescriptor::cas
+use crate::MapState;
+use oysters_core::Pearl;
+use std::io::{Error, ErrorKind, Result};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::warn;
+
+/// The longest command line a client can send.
+const MAX_LINE_LEN: u64 = 8 * 1024;
+/// The longest key a client can use (the same limit as memcached).
+const MAX_KEY_LEN: usize = 250;
+/// The largest value a client can store (the same limit as memcached's default
+/// item size).
+const MAX_VALUE_LEN: usize = 1024 * 1024;
+/// Expiry times larger than this (30 days) are unix timestamps instead of a
+/// number of seconds from now.
+const RELATIVE_EXPIRY_LIMIT: i64 = 60 * 60 * 24 * 30;
+
+/// Counters reported by `stats`.
+#[derive(Debug)]
+pub struct Stats {
+    started: Instant,
+    connections: AtomicU64,
+    cmd_get: AtomicU64,
+    cmd_set: AtomicU64,
+    get_hits: AtomicU64,
+    get_misses: AtomicU64,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            connections: AtomicU64::new(0),
+            cmd_get: AtomicU64::new(0),
+            cmd_set: AtomicU64::new(0),
+            get_hits: AtomicU64::new(0),
+            get_misses: AtomicU64::new(0),
+        }
+    }
+}
+
+/// How a storage command treats existing values.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Store {
+    /// Always store the value.
+    Set,
+    /// Only store the value if the key doesn't exist.
+    Add,
+    /// Only store the value if the key exists.
+    Replace,
+    /// Only store the value if it hasn't changed since it was read.
+    Cas(u64),
+}
+
+/// Create an [`ErrorKind::InvalidData`] error.
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+/// Convert a memcached expiry time into the number of milliseconds until the
+/// value expires.
+///
+/// # Returns
+/// `None` if the value never expires, `Some(0)` if it has already expired.
+fn ttl(exptime: i64) -> Option<usize> {
+    match exptime {
+        0 => None,
+        x if x < 0 => Some(0),
+        x if x <= RELATIVE_EXPIRY_LIMIT => Some(x as usize * 1000),
+        x => {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs() as i64;
+
+            Some(((x - now).max(0) as usize).saturating_mul(1000))
+        }
+    }
+}
+
+/// If `key` can be used as a memcached key.
+fn valid_key(key: &str) -> bool {
+    !key.is_empty() && key.len() <= MAX_KEY_LEN && !key.bytes().any(|x| x.is_ascii_control())
+}
+
+/// Read a single line (without its line ending).
+///
+/// Returns `None` if the connection was closed.
+async fn read_line<R: AsyncBufRead + Unpin>(reader: &mut R) -> Result<Option<String>> {
+    let mut line = Vec::new();
+
+    if (&mut *reader)
+        .take(MAX_LINE_LEN)
+        .read_until(b'\n', &mut line)
+        .await?
+        == 0
+    {
+        return Ok(None);
+    }
+
+    if line.pop() != Some(b'\n') {
+        return Err(invalid("line too long"));
+    }
+
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+
+    Ok(Some(String::from_utf8_lossy(&line).to_string()))
+}
+
+/// Accept memcached connections forever.
+///
+/// # Arguments
+/// * `listener` - the listener to accept connections from
+/// * `map` - the shared map
+pub async fn serve(listener: TcpListener, map: MapState) {
+    let stats = Arc::new(Stats::default());
+
+    loop {
+        let (stream, addr) = match listener.accept().await {
+            Ok(x) => x,
+            Err(e) => {
+                warn!("failed to accept memcached connection: {e}");
+                continue;
+            }
+        };
+
+        let map = map.clone();
+        let stats = stats.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle(stream, map, stats).await {
+                warn!("memcached connection from {addr} failed: {e}");
+            }
+        });
+    }
+}
+
+/// Handle every command sent over a single connection.
+async fn handle(stream: TcpStream, map: MapState, stats: Arc<Stats>) -> Result<()> {
+    stats.connections.fetch_add(1, Ordering::Relaxed);
+
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+    let mut out = Vec::new();
+
+    while let Some(line) = read_line(&mut reader).await? {
+        let args: Vec<&str> = line.split_ascii_whitespace().collect();
+        let Some((name, args)) = args.split_first() else {
+            continue;
+        };
+
+        out.clear();
+
+        match *name {
+            "get" | "gets" => get(args, *name == "gets", &map, &stats, &mut out).await,
+            "set" | "add" | "replace" | "cas" => {
+                let noreply = store(name, args, &mut reader, &map, &stats, &mut out).await?;
+                if noreply {
+                    out.clear();
+                }
+            }
+            "quit" => break,
+            _ => {
+                let noreply = args.last() == Some(&"noreply");
+                let args = if noreply {
+                    &args[..args.len() - 1]
+                } else {
+                    args
+                };
+
+                execute(name, args, &map, &stats, &mut out).await;
+
+                if noreply {
+                    out.clear();
+                }
+            }
+        }
+
+        writer.write_all(&out).await?;
+    }
+
+    Ok(())
+}
+
+/// `get <key>*` and `gets <key>*`
+async fn get(args: &[&str], with_cas: bool, map: &MapState, stats: &Stats, out: &mut Vec<u8>) {
+    if args.is_empty() {
+        out.extend_from_slice(b"ERROR\r\n");
+        return;
+    }
+
+    let mut writer = map.write().await;
+
+    for key in args {
+        let key = key.to_string();
+        stats.cmd_get.fetch_add(1, Ordering::Relaxed);
+
+        let Some(pearl) = writer.get_full(&key) else {
+            stats.get_misses.fetch_add(1, Ordering::Relaxed);
+            continue;
+        };
+
+        stats.get_hits.fetch_add(1, Ordering::Relaxed);
+        writer.update_resource_descriptor(&key);
+
+        let header = if with_cas {
+            format!(
+                "VALUE {key} {} {} {}\r\n",
+                pearl.1.flags,
+                pearl.0.len(),
+                pearl.1.cas
+            )
+        } else {
+            format!("VALUE {key} {} {}\r\n", pearl.1.flags, pearl.0.len())
+        };
+
+        out.extend_from_slice(header.as_bytes());
+        out.extend_from_slice(&pearl.0);
+        out.extend_from_slice(b"\r\n");
+    }
+
+    out.extend_from_slice(b"END\r\n");
+}
+
+/// Parse the arguments of a storage command.
+///
+/// # Returns
+/// The key, flags, expiry time, value length, how existing values are treated,
+/// and if the client asked for no reply.
+fn parse_store(name: &str, args: &[&str]) -> Option<(String, u32, i64, usize, Store, bool)> {
+    let (args, noreply) = match args.split_last() {
+        Some((&"noreply", args)) => (args, true),
+        _ => (args, false),
+    };
+
+    let (key, flags, exptime, bytes, mode) = match (name, args) {
+        ("set", [key, flags, exptime, bytes]) => (key, flags, exptime, bytes, Store::Set),
+        ("add", [key, flags, exptime, bytes]) => (key, flags, exptime, bytes, Store::Add),
+        ("replace", [key, flags, exptime, bytes]) => (key, flags, exptime, bytes, Store::Replace),
+        ("cas", [key, flags, exptime, bytes, cas]) => {
+            (key, flags, exptime, bytes, Store::Cas(cas.parse().ok()?))
+        }
+        _ => return None,
+    };
+
+    Some((
+        key.to_string(),
+        flags.parse().ok()?,
+        exptime.parse().ok()?,
+        bytes.parse().ok()?,
+        mode,
+        noreply,
+    ))
+}
+
+/// `<set|add|replace> <key> <flags> <exptime> <bytes> [noreply]` and
+/// `cas <key> <flags> <exptime> <bytes> <cas unique> [noreply]`
+///
+/// # Returns
+/// If the client asked for no reply.
+async fn store<R: AsyncBufRead + Unpin>(
+    name: &str,
+    args: &[&str],
+    reader: &mut R,
+    map: &MapState,
+    stats: &Stats,
+    out: &mut Vec<u8>,
+) -> Result<bool> {
+    let Some((key, flags, exptime, bytes, mode, noreply)) = parse_store(name, args) else {
+        out.extend_from_slice(b"CLIENT_ERROR bad command line format\r\n");
+        return Ok(false);
+    };
+
+    if bytes > MAX_VALUE_LEN {
+        return Err(invalid("value too large"));
+    }
+
+    // the value is followed by a line ending
+    let mut value = vec![0; bytes + 2];
+    reader.read_exact(&mut value).await?;
+
+    if !value.ends_with(b"\r\n") {
+        out.extend_from_slice(b"CLIENT_ERROR bad data chunk\r\n");
+        return Ok(false);
+    }
+
+    value.truncate(bytes);
+
+    if !valid_key(&key) {
+        out.extend_from_slice(b"CLIENT_ERROR bad command line format\r\n");
+        return Ok(false);
+    }
+
+    stats.cmd_set.fetch_add(1, Ordering::Relaxed);
+
+    let mut pearl = Pearl::new(value);
+    pearl.1.flags = flags;
+
+    let mut writer = map.write().await;
+    let exists = writer.get_full(&key).is_some();
+
+    let reply: &[u8] = match mode {
+        Store::Add if exists => b"NOT_STORED\r\n",
+        Store::Replace if !exists => b"NOT_STORED\r\n",
+        Store::Cas(cas) => match writer.compare_and_swap(key.clone(), cas, pearl) {
+            Some(true) => b"STORED\r\n",
+            Some(false) => b"EXISTS\r\n",
+            None => b"NOT_FOUND\r\n",
+        },
+        _ => {
+            writer.insert_full(key.clone(), pearl);
+            b"STORED\r\n"
+        }
+    };
+
+    // new values never expire, so only values with an expiry time need updating
+    if reply == b"STORED\r\n"
+        && let Some(ttl) = ttl(exptime)
+    {
+        writer.expire(&key, ttl);
+    }
+
+    out.extend_from_slice(reply);
+    Ok(noreply)
+}
+
+/// Run a single command which doesn't send a value (with `noreply` removed from
+/// `args`).
+async fn execute(name: &str, args: &[&str], map: &MapState, stats: &Stats, out: &mut Vec<u8>) {
+    let reply = match (name, args) {
+        ("delete", [key]) => {
+            let key = key.to_string();
+            let writer = &mut *map.write().await;
+
+            if writer.get_full(&key).is_some() {
+                writer.remove(&key);
+                "DELETED\r\n".to_string()
+            } else {
+                "NOT_FOUND\r\n".to_string()
+            }
+        }
+        ("incr" | "decr", [key, delta]) => {
+            let Ok(delta) = delta.parse::<u64>() else {
+                out.extend_from_slice(b"CLIENT_ERROR invalid numeric delta argument\r\n");
+                return;
+            };
+
+            count(key, delta, name == "decr", map).await
+        }
+        ("touch", [key, exptime]) => {
+            let Ok(exptime) = exptime.parse::<i64>() else {
+                out.extend_from_slice(b"CLIENT_ERROR bad command line format\r\n");
+                return;
+            };
+
+            let key = key.to_string();
+            let mut writer = map.write().await;
+
+            let touched = match ttl(exptime) {
+                Some(ttl) => writer.expire(&key, ttl),
+                None => writer.persist(&key),
+            };
+
+            if touched {
+                "TOUCHED\r\n".to_string()
+            } else {
+                "NOT_FOUND\r\n".to_string()
+            }
+        }
+        ("stats", []) => report(map, stats).await,
+        ("version", []) => format!("VERSION {}\r\n", env!("CARGO_PKG_VERSION")),
+        _ => "ERROR\r\n".to_string(),
+    };
+
+    out.extend_from_slice(reply.as_bytes());
+}
+
+/// `incr <key> <delta>` and `decr <key> <delta>`
+///
+/// Like memcached, `incr` wraps around at 64 bits and `decr` stops at `0`.
+async fn count(key: &str, delta: u64, decr: bool, map: &MapState) -> String {
+    let key = key.to_string();
+    let writer = &mut *map.write().await;
+
+    let Some(mut pearl) = writer.get_full(&key) else {
+        return "NOT_FOUND\r\n".to_string();
+    };
+
+    let Some(value) = std::str::from_utf8(&pearl.0)
+        .ok()
+        .and_then(|x| x.trim().parse::<u64>().ok())
+    else {
+        return "CLIENT_ERROR cannot increment or decrement non-numeric value\r\n".to_string();
+    };
+
+    let value = if decr {
+        value.saturating_sub(delta)
+    } else {
+        value.wrapping_add(delta)
+    };
+
+    // keep the flags and expiry time
+    pearl.0 = value.to_string().into_bytes();
+    pearl.1.touch();
+    writer.insert_full(key, pearl);
+
+    format!("{value}\r\n")
+}
+
+/// `stats`
+async fn report(map: &MapState, stats: &Stats) -> String {
+    let items = map.read().await.filter_keys("*").len();
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+
+    let fields: [(&str, String); 10] = [
+        ("pid", std::process::id().to_string()),
+        ("uptime", stats.started.elapsed().as_secs().to_string()),
+        ("time", time.to_string()),
+        ("version", env!("CARGO_PKG_VERSION").to_string()),
+        ("curr_items", items.to_string()),
+        (
+            "total_connections",
+            stats.connections.load(Ordering::Relaxed).to_string(),
+        ),
+        ("cmd_get", stats.cmd_get.load(Ordering::Relaxed).to_string()),
+        ("cmd_set", stats.cmd_set.load(Ordering::Relaxed).to_string()),
+        (
+            "get_hits",
+            stats.get_hits.load(Ordering::Relaxed).to_string(),
+        ),
+        (
+            "get_misses",
+            stats.get_misses.load(Ordering::Relaxed).to_string(),
+        ),
+    ];
+
+    let mut out = String::new();
+    for (k, v) in fields {
+        out.push_str(&format!("STAT {k} {v}\r\n"));
+    }
+
+    out.push_str("END\r\n");
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::serve;
+    use oysters_core::Oyster;
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio::sync::RwLock;
+
+    /// Send raw bytes, and check the raw response.
+    async fn roundtrip(stream: &mut TcpStream, command: &[u8], expected: &[u8]) {
+        stream.write_all(command).await.unwrap();
+
+        let mut response = vec![0; expected.len()];
+        stream.read_exact(&mut response).await.unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&response),
+            String::from_utf8_lossy(expected)
+        );
+    }
+
+    #[tokio::test]
+    async fn memcached() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let map = Arc::new(RwLock::new(Oyster::new()));
+        tokio::spawn(serve(listener, map.clone()));
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+
+        roundtrip(&mut stream, b"set a 5 0 5\r\nhello\r\n", b"STORED\r\n").await;
+        roundtrip(
+            &mut stream,
+            b"get a b\r\n",
+            b"VALUE a 5 5\r\nhello\r\nEND\r\n",
+        )
+        .await;
+        roundtrip(&mut stream, b"add a 0 0 1\r\nx\r\n", b"NOT_STORED\r\n").await;
+        roundtrip(&mut stream, b"replace b 0 0 1\r\nx\r\n", b"NOT_STORED\r\n").await;
+
+        // cas
+        let cas = map.read().await.get_full(&"a".to_string()).unwrap().1.cas;
+        roundtrip(
+            &mut stream,
+            b"gets a\r\n",
+            format!("VALUE a 5 5 {cas}\r\nhello\r\nEND\r\n").as_bytes(),
+        )
+        .await;
+        roundtrip(
+            &mut stream,
+            format!("cas a 0 0 5 {cas}\r\nworld\r\n").as_bytes(),
+            b"STORED\r\n",
+        )
+        .await;
+        roundtrip(
+            &mut stream,
+            format!("cas a 0 0 5 {cas}\r\nagain\r\n").as_bytes(),
+            b"EXISTS\r\n",
+        )
+        .await;
+        roundtrip(&mut stream, b"cas b 0 0 1 1\r\nx\r\n", b"NOT_FOUND\r\n").await;
+
+        // counters
+        roundtrip(&mut stream, b"set n 0 0 2\r\n10\r\n", b"STORED\r\n").await;
+        roundtrip(&mut stream, b"incr n 5\r\n", b"15\r\n").await;
+        roundtrip(&mut stream, b"decr n 20\r\n", b"0\r\n").await;
+        roundtrip(
+            &mut stream,
+            b"incr a 1\r\n",
+            b"CLIENT_ERROR cannot increment or decrement non-numeric value\r\n",
+        )
+        .await;
+
+        // expiry
+        roundtrip(&mut stream, b"touch n 100\r\n", b"TOUCHED\r\n").await;
+        assert!(map.read().await.ttl(&"n".to_string()).unwrap().is_some());
+        roundtrip(&mut stream, b"touch n 0\r\n", b"TOUCHED\r\n").await;
+        assert_eq!(map.read().await.ttl(&"n".to_string()), Some(None));
+        roundtrip(&mut stream, b"set e 0 -1 1\r\nx\r\n", b"STORED\r\n").await;
+        roundtrip(&mut stream, b"get e\r\n", b"END\r\n").await;
+
+        // noreply
+        roundtrip(&mut stream, b"delete n noreply\r\n", b"").await;
+        roundtrip(&mut stream, b"delete n\r\n", b"NOT_FOUND\r\n").await;
+        roundtrip(&mut stream, b"bogus\r\n", b"ERROR\r\n").await;
+
+        stream.write_all(b"stats\r\n").await.unwrap();
+        let mut stats = Vec::new();
+        while !stats.ends_with(b"END\r\n") {
+            let mut buf = [0; 1024];
+            let n = stream.read(&mut buf).await.unwrap();
+            stats.extend_from_slice(&buf[..n]);
+        }
+
+        // other tests share the table with the sqlite backend
+        let items = map.read().await.filter_keys("*").len();
+        let stats = String::from_utf8_lossy(&stats);
+        assert!(stats.contains(&format!("STAT curr_items {items}\r\n")));
+        #[cfg(not(feature = "sqlite_backend"))]
+        assert_eq!(items, 1);
+    }
+}
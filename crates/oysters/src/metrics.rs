@@ -0,0 +1,347 @@
+//! Prometheus metrics for the HTTP API (`GET /_metrics`).
+//!
+//! Request counts and latencies are kept per route (e.g. `/{key}` or
+//! `/ns/{name}/{key}`), as are hits and misses (reads which found a key, or
+//! didn't). Everything about the maps themselves (keys, memory, evictions,
+//! dumps) comes from [`Oyster::metrics`](oysters_core::Oyster::metrics).
+use crate::namespaces::NamespacesState;
+use crate::{Map, MapState};
+use axum::Extension;
+use axum::extract::{MatchedPath, Request};
+use axum::http::{Method, StatusCode, header};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use oysters_core::metrics::MetricsReport;
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{RwLockReadGuard, RwLockWriteGuard};
+
+/// The upper bounds (in seconds) of the histogram buckets.
+const BUCKETS: [f64; 12] = [
+    0.0001, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 1.0, 5.0,
+];
+
+/// The metrics of the server.
+pub static METRICS: LazyLock<ServerMetrics> = LazyLock::new(ServerMetrics::default);
+
+/// A latency histogram.
+#[derive(Clone, Debug, Default)]
+struct Histogram {
+    buckets: [u64; BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, took: Duration) {
+        let secs = took.as_secs_f64();
+        for (i, bound) in BUCKETS.iter().enumerate() {
+            if secs <= *bound {
+                self.buckets[i] += 1;
+            }
+        }
+
+        self.count += 1;
+        self.sum += secs;
+    }
+
+    /// Write the histogram in the Prometheus text format.
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        let sep = if labels.is_empty() { "" } else { "," };
+        for (bound, count) in BUCKETS.iter().zip(self.buckets) {
+            let _ = writeln!(out, "{name}_bucket{{{labels}{sep}le=\"{bound}\"}} {count}");
+        }
+
+        let _ = writeln!(
+            out,
+            "{name}_bucket{{{labels}{sep}le=\"+Inf\"}} {}",
+            self.count
+        );
+        let _ = writeln!(out, "{name}_sum{{{labels}}} {}", self.sum);
+        let _ = writeln!(out, "{name}_count{{{labels}}} {}", self.count);
+    }
+}
+
+/// What happened on a single route.
+#[derive(Clone, Debug, Default)]
+struct RouteMetrics {
+    statuses: BTreeMap<u16, u64>,
+    latency: Histogram,
+    hits: u64,
+    misses: u64,
+}
+
+/// Metrics which aren't about a single map.
+#[derive(Debug, Default)]
+pub struct ServerMetrics {
+    /// Keyed by method and route.
+    routes: Mutex<BTreeMap<(String, String), RouteMetrics>>,
+    read_wait: Mutex<Histogram>,
+    write_wait: Mutex<Histogram>,
+}
+
+/// Lock a map for reading (and record how long that took).
+pub async fn read(map: &MapState) -> RwLockReadGuard<'_, Map> {
+    let started = Instant::now();
+    let guard = map.read().await;
+    METRICS.read_wait.lock().unwrap().observe(started.elapsed());
+    guard
+}
+
+/// Lock a map for writing (and record how long that took).
+pub async fn write(map: &MapState) -> RwLockWriteGuard<'_, Map> {
+    let started = Instant::now();
+    let guard = map.write().await;
+    METRICS
+        .write_wait
+        .lock()
+        .unwrap()
+        .observe(started.elapsed());
+    guard
+}
+
+/// Record the status and latency of a request.
+pub async fn track(request: Request, next: Next) -> Response {
+    let started = Instant::now();
+    let method = request.method().clone();
+    let route = match request.extensions().get::<MatchedPath>() {
+        Some(path) => path.as_str().to_string(),
+        None => "unmatched".to_string(),
+    };
+
+    let response = next.run(request).await;
+    let status = response.status();
+
+    let mut routes = METRICS.routes.lock().unwrap();
+    let metrics = routes
+        .entry((method.to_string(), route.clone()))
+        .or_default();
+
+    *metrics.statuses.entry(status.as_u16()).or_default() += 1;
+    metrics.latency.observe(started.elapsed());
+
+    // reads of a single key either find it or they don't
+    if method == Method::GET && route.ends_with("{key}") {
+        if status.is_success() {
+            metrics.hits += 1;
+        } else if status == StatusCode::NOT_FOUND {
+            metrics.misses += 1;
+        }
+    }
+
+    response
+}
+
+/// The state of a map when the metrics were rendered.
+struct MapSnapshot {
+    name: String,
+    keys: usize,
+    memory: usize,
+    metrics: MetricsReport,
+}
+
+/// A metric read from a `T`: its name, type, help text, and how to read it.
+type Metric<T, V> = (&'static str, &'static str, &'static str, fn(&T) -> V);
+
+/// Write the `# HELP` and `# TYPE` lines of a metric.
+fn describe(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+/// Render every metric in the Prometheus text format.
+pub async fn render(namespaces: &NamespacesState) -> String {
+    let mut out = String::new();
+
+    // maps
+    let mut maps = Vec::new();
+    for (name, map) in namespaces.named().await {
+        let map = map.read().await;
+        maps.push(MapSnapshot {
+            name,
+            keys: map.len(),
+            memory: map.memory_usage(),
+            metrics: map.metrics(),
+        });
+    }
+
+    let gauges: [Metric<MapSnapshot, String>; 12] = [
+        (
+            "oysters_keys",
+            "gauge",
+            "Number of keys (including expired keys not scanned yet).",
+            |x| x.keys.to_string(),
+        ),
+        (
+            "oysters_memory_bytes",
+            "gauge",
+            "Approximate memory used by keys and values.",
+            |x| x.memory.to_string(),
+        ),
+        (
+            "oysters_keyspace_hits_total",
+            "counter",
+            "Reads which found a key.",
+            |x| x.metrics.hits.to_string(),
+        ),
+        (
+            "oysters_keyspace_misses_total",
+            "counter",
+            "Reads which didn't find a key.",
+            |x| x.metrics.misses.to_string(),
+        ),
+        (
+            "oysters_evictions_total",
+            "counter",
+            "Keys evicted to make room for new keys.",
+            |x| x.metrics.evictions.to_string(),
+        ),
+        (
+            "oysters_expirations_total",
+            "counter",
+            "Expired keys removed by scans.",
+            |x| x.metrics.expirations.to_string(),
+        ),
+        (
+            "oysters_scan_evictions_total",
+            "counter",
+            "Keys removed by scans for going unused longer than the maximum age.",
+            |x| x.metrics.scan_evictions.to_string(),
+        ),
+        (
+            "oysters_scan_removed_total",
+            "counter",
+            "Keys removed by scans.",
+            |x| (x.metrics.expirations + x.metrics.scan_evictions).to_string(),
+        ),
+        ("oysters_scans_total", "counter", "Number of scans.", |x| {
+            x.metrics.scans.to_string()
+        }),
+        ("oysters_dumps_total", "counter", "Number of dumps.", |x| {
+            x.metrics.dumps.to_string()
+        }),
+        (
+            "oysters_last_dump_duration_seconds",
+            "gauge",
+            "How long the last dump took.",
+            |x| x.metrics.last_dump_duration().as_secs_f64().to_string(),
+        ),
+        (
+            "oysters_last_dump_size_bytes",
+            "gauge",
+            "Size of the dump file after the last dump.",
+            |x| x.metrics.last_dump_bytes.to_string(),
+        ),
+    ];
+
+    for (name, kind, help, value) in gauges {
+        describe(&mut out, name, kind, help);
+        for map in &maps {
+            let _ = writeln!(out, "{name}{{namespace=\"{}\"}} {}", map.name, value(map));
+        }
+    }
+
+    // requests
+    let routes = METRICS.routes.lock().unwrap().clone();
+
+    describe(
+        &mut out,
+        "oysters_http_requests_total",
+        "counter",
+        "HTTP requests by route and status.",
+    );
+    for ((method, route), metrics) in &routes {
+        for (status, count) in &metrics.statuses {
+            let _ = writeln!(
+                out,
+                "oysters_http_requests_total{{method=\"{method}\",route=\"{route}\",status=\"{status}\"}} {count}"
+            );
+        }
+    }
+
+    let reads: [Metric<RouteMetrics, u64>; 2] = [
+        (
+            "oysters_http_hits_total",
+            "counter",
+            "Reads of a single key which found it, by route.",
+            |x| x.hits,
+        ),
+        (
+            "oysters_http_misses_total",
+            "counter",
+            "Reads of a single key which didn't find it, by route.",
+            |x| x.misses,
+        ),
+    ];
+
+    for (name, kind, help, value) in reads {
+        describe(&mut out, name, kind, help);
+        for ((method, route), metrics) in &routes {
+            if method == "GET" && route.ends_with("{key}") {
+                let _ = writeln!(out, "{name}{{route=\"{route}\"}} {}", value(metrics));
+            }
+        }
+    }
+
+    describe(
+        &mut out,
+        "oysters_http_request_duration_seconds",
+        "histogram",
+        "HTTP request latency by route.",
+    );
+    for ((method, route), metrics) in &routes {
+        metrics.latency.render(
+            &mut out,
+            "oysters_http_request_duration_seconds",
+            &format!("method=\"{method}\",route=\"{route}\""),
+        );
+    }
+
+    // locks
+    describe(
+        &mut out,
+        "oysters_lock_wait_seconds",
+        "histogram",
+        "Time spent waiting for a map lock (HTTP API).",
+    );
+    let read_wait = METRICS.read_wait.lock().unwrap().clone();
+    read_wait.render(&mut out, "oysters_lock_wait_seconds", "kind=\"read\"");
+    let write_wait = METRICS.write_wait.lock().unwrap().clone();
+    write_wait.render(&mut out, "oysters_lock_wait_seconds", "kind=\"write\"");
+
+    out
+}
+
+/// Get every metric in the Prometheus text format.
+pub async fn metrics(Extension(namespaces): Extension<NamespacesState>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        render(&namespaces).await,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::{BUCKETS, Histogram};
+    use std::time::Duration;
+
+    #[test]
+    fn histogram() {
+        let mut histogram = Histogram::default();
+        histogram.observe(Duration::from_millis(2));
+        histogram.observe(Duration::from_secs(10));
+
+        assert_eq!(histogram.count, 2);
+        assert_eq!(histogram.buckets[BUCKETS.len() - 1], 1);
+        assert_eq!(histogram.buckets[0], 0);
+
+        let mut out = String::new();
+        histogram.render(&mut out, "x", "a=\"b\"");
+        assert!(out.contains("x_bucket{a=\"b\",le=\"0.0025\"} 1\n"));
+        assert!(out.contains("x_bucket{a=\"b\",le=\"+Inf\"} 2\n"));
+        assert!(out.contains("x_count{a=\"b\"} 2\n"));
+    }
+}
@@ -0,0 +1,470 @@
+//! Namespaces (isolated maps sharing one server).
+//!
+//! Every namespace is its own [`Oyster`] with its own capacity, scan policy and
+//! dump table. Keys in a namespace are used through `/ns/{name}/{key}`. The
+//! namespaces which were created through the API are recorded in the server's
+//! own namespace, so they're restored when the server starts. The other
+//! protocols (RESP, memcached and gRPC) only use the default map.
+use crate::{Map, MapState};
+use axum::body::Bytes;
+use axum::extract::{Path, Request};
+use axum::http::StatusCode;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use oysters_core::compression::Compression;
+use oysters_core::{Oyster, OysterOptions};
+use serde::{Deserialize, Serialize};
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::{OwnedRwLockWriteGuard, RwLock};
+use tracing::warn;
+
+/// The table of the server's own namespace.
+pub const SYSTEM_TABLE: &str = "_oysters";
+
+/// The name the default map goes by (where a name is needed).
+pub const DEFAULT_NAME: &str = "_default";
+
+/// Options for a namespace.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct NamespaceOptions {
+    /// The maximum number of keys (the least recently used key is evicted to
+    /// make room for new keys).
+    #[serde(default)]
+    pub capacity: Option<usize>,
+    /// How long (in milliseconds) a key can go unused before it's removed by a
+    /// scan. `0` keeps unused keys forever.
+    #[serde(default = "default_max_age")]
+    pub max_age: usize,
+}
+
+fn default_max_age() -> usize {
+    604800000 // 7 days
+}
+
+impl Default for NamespaceOptions {
+    fn default() -> Self {
+        Self {
+            capacity: None,
+            max_age: default_max_age(),
+        }
+    }
+}
+
+/// A namespace created when the server starts
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct NamespaceConfig {
+    /// The name of the namespace.
+    pub name: String,
+    #[serde(flatten)]
+    pub options: NamespaceOptions,
+}
+
+/// If `name` can be used as a namespace name. Names are made of letters, digits,
+/// `-` and `_`, and can't start with `_` (those are kept for the server).
+pub fn valid_name(name: &str) -> bool {
+    (1..=64).contains(&name.len())
+        && !name.starts_with('_')
+        && name
+            .chars()
+            .all(|x| x.is_ascii_alphanumeric() || x == '-' || x == '_')
+}
+
+/// Every namespace the server has.
+pub struct Namespaces {
+    /// The map used when no namespace is given.
+    pub default: MapState,
+    /// The server's own namespace (see [`SYSTEM_TABLE`]).
+    pub system: MapState,
+    maps: Arc<RwLock<HashMap<String, MapState>>>,
+    compression: Compression,
+}
+
+/// Write locks on the default map, the server's own namespace and the list of
+/// namespaces, held until they've been restored (see [`Namespaces::lock`]).
+pub struct Restoring {
+    default: OwnedRwLockWriteGuard<Map>,
+    system: OwnedRwLockWriteGuard<Map>,
+    maps: OwnedRwLockWriteGuard<HashMap<String, MapState>>,
+}
+
+pub type NamespacesState = Arc<Namespaces>;
+
+/// The key a namespace is recorded under in the server's own namespace.
+fn record_key(name: &str) -> String {
+    format!("namespace:{name}")
+}
+
+impl Namespaces {
+    /// Create a new [`Namespaces`] (see [`Namespaces::restore`]). Namespaces use
+    /// the same `compression` as the default map.
+    pub fn new(default: MapState, compression: Compression) -> Self {
+        let system: Map = Oyster::with_options(OysterOptions {
+            compression: compression.clone(),
+            table: SYSTEM_TABLE.to_string(),
+            max_age: None,
+            ..Default::default()
+        });
+
+        Self {
+            default,
+            system: Arc::new(RwLock::new(system)),
+            maps: Arc::new(RwLock::new(HashMap::new())),
+            compression,
+        }
+    }
+
+    /// Create the map for a namespace (and read its items from the dump file).
+    ///
+    /// # Returns
+    /// The map, and why reading its items failed (if it did).
+    fn open(&self, name: &str, options: &NamespaceOptions) -> (MapState, Result<(), String>) {
+        let mut map: Map = Oyster::with_options(OysterOptions {
+            compression: self.compression.clone(),
+            table: format!("ns_{name}"),
+            capacity: options.capacity,
+            max_age: Some(options.max_age).filter(|x| *x > 0),
+        });
+
+        let restored = map
+            .restore()
+            .map_err(|e| format!("failed to restore namespace {name}: {e}"));
+
+        (Arc::new(RwLock::new(map)), restored)
+    }
+
+    /// Lock every map until it's been restored, so requests made before then
+    /// wait for the restore (instead of seeing half of the items, or having
+    /// their writes replaced by the dumped items). This should happen before the
+    /// server starts listening.
+    pub async fn lock(&self) -> Restoring {
+        Restoring {
+            default: self.default.clone().write_owned().await,
+            system: self.system.clone().write_owned().await,
+            maps: self.maps.clone().write_owned().await,
+        }
+    }
+
+    /// Read the default map and the server's own namespace from the dump file,
+    /// then open every namespace which was created through the API, and every
+    /// namespace in `configured`.
+    ///
+    /// # Arguments
+    /// * `locked` - the locks taken by [`Namespaces::lock`] (each is released
+    ///   once its map has been restored)
+    /// * `configured` - the namespaces created when the server starts
+    ///
+    /// # Returns
+    /// Everything which couldn't be restored. Maps which couldn't be restored
+    /// are still used (without the items which couldn't be read).
+    pub async fn restore(
+        &self,
+        locked: Restoring,
+        configured: &[NamespaceConfig],
+    ) -> Result<(), String> {
+        let mut errors = Vec::new();
+        let Restoring {
+            mut default,
+            mut system,
+            mut maps,
+        } = locked;
+
+        if let Err(e) = default.restore() {
+            errors.push(format!("failed to restore the default map: {e}"));
+        }
+
+        if let Err(e) = system.restore() {
+            errors.push(format!("failed to restore the server's namespace: {e}"));
+        }
+
+        drop(default);
+        let recorded = system.filter("namespace:*");
+        drop(system);
+
+        let mut open = |name: &str, options: &NamespaceOptions| {
+            let (map, restored) = self.open(name, options);
+            if let Err(e) = restored {
+                errors.push(e);
+            }
+
+            maps.insert(name.to_string(), map);
+        };
+
+        for (key, value) in recorded {
+            let name = key.trim_start_matches("namespace:");
+            match toml::from_str::<NamespaceOptions>(&String::from_utf8_lossy(&value.0)) {
+                Ok(options) => open(name, &options),
+                Err(e) => warn!("invalid options for namespace {name}: {e}"),
+            }
+        }
+
+        for config in configured {
+            if !valid_name(&config.name) {
+                warn!("invalid namespace name: {}", config.name);
+                continue;
+            }
+
+            // configured options win
+            open(&config.name, &config.options);
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join("; "))
+        }
+    }
+
+    /// Get a namespace given its `name`.
+    pub async fn get(&self, name: &str) -> Option<MapState> {
+        self.maps.read().await.get(name).cloned()
+    }
+
+    /// Get the name of every namespace.
+    pub async fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.maps.read().await.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Get every map (including the default map and the server's own namespace).
+    pub async fn all(&self) -> Vec<MapState> {
+        self.named().await.into_iter().map(|x| x.1).collect()
+    }
+
+    /// Get every map (like [`Namespaces::all`]) with its name. The default map is
+    /// named [`DEFAULT_NAME`], and the server's own namespace [`SYSTEM_TABLE`].
+    pub async fn named(&self) -> Vec<(String, MapState)> {
+        let mut all = vec![
+            (DEFAULT_NAME.to_string(), self.default.clone()),
+            (SYSTEM_TABLE.to_string(), self.system.clone()),
+        ];
+
+        let maps = self.maps.read().await;
+        let mut names: Vec<&String> = maps.keys().collect();
+        names.sort();
+        all.extend(names.into_iter().map(|x| (x.clone(), maps[x].clone())));
+        all
+    }
+
+    /// Create a namespace.
+    ///
+    /// # Returns
+    /// `false` if the namespace already exists.
+    pub async fn create(&self, name: &str, options: NamespaceOptions) -> bool {
+        let mut maps = self.maps.write().await;
+        if maps.contains_key(name) {
+            return false;
+        }
+
+        let (map, restored) = self.open(name, &options);
+        if let Err(e) = restored {
+            warn!("{e}");
+        }
+
+        maps.insert(name.to_string(), map);
+        self.system.write().await.insert(
+            record_key(name),
+            toml::to_string(&options).unwrap().into_bytes(),
+        );
+
+        true
+    }
+
+    /// Drop a namespace (and everything in it).
+    ///
+    /// # Returns
+    /// `false` if the namespace doesn't exist.
+    pub async fn remove(&self, name: &str) -> bool {
+        let Some(map) = self.maps.write().await.remove(name) else {
+            return false;
+        };
+
+        self.system.write().await.remove(&record_key(name));
+        if let Err(e) = map.read().await.drop_tables() {
+            warn!("failed to drop the table of namespace {name}: {e}");
+        }
+
+        true
+    }
+}
+
+/// Use the namespace named in the route (`/ns/{name}/...`) as the map.
+pub async fn select(
+    Extension(namespaces): Extension<NamespacesState>,
+    Path(params): Path<HashMap<String, String>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let map = match params.get("name") {
+        Some(name) => namespaces.get(name).await,
+        None => None,
+    };
+
+    let Some(map) = map else {
+        return (StatusCode::NOT_FOUND, "namespace does not exist").into_response();
+    };
+
+    request.extensions_mut().insert(map);
+    next.run(request).await
+}
+
+/// List every namespace.
+pub async fn list(Extension(namespaces): Extension<NamespacesState>) -> impl IntoResponse {
+    Json(namespaces.names().await)
+}
+
+/// Create a namespace.
+pub async fn create(
+    Path(name): Path<String>,
+    Extension(namespaces): Extension<NamespacesState>,
+    body: Bytes,
+) -> impl IntoResponse {
+    if !valid_name(&name) {
+        return (StatusCode::BAD_REQUEST, "invalid namespace name");
+    }
+
+    // the options are optional
+    let options = if body.is_empty() {
+        NamespaceOptions::default()
+    } else {
+        match Json::<NamespaceOptions>::from_bytes(&body) {
+            Ok(Json(options)) => options,
+            Err(_) => return (StatusCode::BAD_REQUEST, "invalid namespace options"),
+        }
+    };
+
+    if namespaces.create(&name, options).await {
+        (StatusCode::CREATED, "")
+    } else {
+        (StatusCode::CONFLICT, "namespace already exists")
+    }
+}
+
+/// Drop a namespace.
+pub async fn remove(
+    Path(name): Path<String>,
+    Extension(namespaces): Extension<NamespacesState>,
+) -> impl IntoResponse {
+    if namespaces.remove(&name).await {
+        StatusCode::OK
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::default_routes;
+    use oysters_client::Client;
+    use tokio::net::TcpListener;
+
+    fn namespaces() -> NamespacesState {
+        let map = Arc::new(RwLock::new(Oyster::new()));
+        Arc::new(Namespaces::new(map, Default::default()))
+    }
+
+    #[test]
+    fn names() {
+        assert!(valid_name("billing"));
+        assert!(valid_name("team-a_1"));
+        assert!(!valid_name(""));
+        assert!(!valid_name("_oysters"));
+        assert!(!valid_name("a/b"));
+        assert!(!valid_name("a\"b"));
+        assert!(!valid_name(&"a".repeat(65)));
+    }
+
+    #[tokio::test]
+    async fn routes() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let app = crate::router(&default_routes(), namespaces(), None, None);
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let client = Client::new(format!("http://127.0.0.1:{port}"));
+        assert!(client.create_namespace("a", None, None).await);
+        assert!(client.create_namespace("b", None, None).await);
+        assert!(!client.create_namespace("a", None, None).await);
+        assert!(!client.create_namespace("_a", None, None).await);
+        assert_eq!(client.namespaces().await, ["a", "b"]);
+
+        // keys in different namespaces don't see each other
+        let (a, b) = (client.namespace("a"), client.namespace("b"));
+        assert!(a.insert("k", "1").await);
+        assert!(b.insert("k", "2").await);
+        assert_eq!(a.get("k").await, "1");
+        assert_eq!(b.get("k").await, "2");
+        assert_eq!(client.get("k").await, "");
+
+        // capacity
+        assert!(client.create_namespace("small", Some(2), None).await);
+        let small = client.namespace("small");
+        for key in ["x", "y", "z"] {
+            assert!(small.insert(key, key).await);
+            // keys used in the same millisecond are evicted in any order
+            tokio::time::sleep(std::time::Duration::from_millis(2)).await;
+        }
+
+        assert_eq!(small.get("x").await, "");
+        assert_eq!(small.get("z").await, "z");
+
+        // max_age
+        assert!(client.create_namespace("short", None, Some(1)).await);
+        assert!(client.create_namespace("forever", None, Some(0)).await);
+        let (short, forever) = (client.namespace("short"), client.namespace("forever"));
+        assert!(short.insert("k", "v").await);
+        assert!(forever.insert("k", "v").await);
+
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        client.scan().await;
+        assert_eq!(short.get("k").await, "");
+        assert_eq!(forever.get("k").await, "v");
+        assert_eq!(a.get("k").await, "1");
+
+        // drop
+        assert!(client.drop_namespace("a").await);
+        assert!(!client.drop_namespace("a").await);
+        assert!(!a.insert("k", "1").await);
+        assert_eq!(b.get("k").await, "2");
+        assert!(!client.namespaces().await.contains(&"a".to_string()));
+    }
+
+    #[cfg(feature = "persistance")]
+    #[tokio::test]
+    async fn dump_tables() {
+        let existed = std::fs::exists("dump.db").unwrap_or(false);
+        let names = ["test-dump-a", "test-dump-b"];
+
+        let dumped = namespaces();
+        for (i, name) in names.iter().enumerate() {
+            assert!(dumped.create(name, NamespaceOptions::default()).await);
+            let map = dumped.get(name).await.unwrap();
+            let map = &mut *map.write().await;
+            assert_eq!(map.options().table, format!("ns_{name}"));
+            map.insert("k".to_string(), i.to_string().into_bytes());
+            map.dump().unwrap();
+        }
+
+        dumped.system.read().await.dump().unwrap();
+
+        // every recorded namespace comes back, and reads its own table
+        let restored = namespaces();
+        restored.restore(restored.lock().await, &[]).await.unwrap();
+        for (i, name) in names.iter().enumerate() {
+            let map = restored.get(name).await.unwrap();
+            let value = map.read().await.get(&"k".to_string());
+            assert_eq!(value, Some(i.to_string().into_bytes()));
+            assert!(restored.remove(name).await);
+        }
+
+        if !existed {
+            for suffix in ["", "-wal", "-shm"] {
+                let _ = std::fs::remove_file(format!("dump.db{suffix}"));
+            }
+        }
+    }
+}
@@ -0,0 +1,108 @@
+//! Publish/subscribe channels (`POST /_publish/{channel}`, `GET /_subscribe/{channel}`).
+//!
+//! Channels have nothing to do with keys: messages are sent to everyone
+//! subscribed to a channel when they're published, and aren't stored anywhere.
+//! Channels are shared by every namespace (tokens are checked as if the channel
+//! was a key in the default namespace). Subscribers get messages the same way
+//! watchers get events (see [`crate::watch`]).
+use crate::watch::stream;
+use axum::body::Bytes;
+use axum::extract::Path;
+use axum::extract::ws::{WebSocketUpgrade, rejection::WebSocketUpgradeRejection};
+use axum::response::Response;
+use oysters_core::events::CAPACITY;
+use serde::Serialize;
+
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+use tokio::sync::broadcast;
+
+/// Every channel with subscribers.
+pub static CHANNELS: LazyLock<Channels> = LazyLock::new(Channels::default);
+
+/// A message published to a channel.
+#[derive(Clone, Serialize, Debug)]
+pub struct Message {
+    /// The channel the message was published to.
+    pub channel: String,
+    /// The message itself (invalid UTF-8 is replaced).
+    pub message: String,
+}
+
+/// Channels, by name.
+#[derive(Debug, Default)]
+pub struct Channels(Mutex<HashMap<String, broadcast::Sender<Message>>>);
+
+impl Channels {
+    /// Send a `message` to everyone subscribed to a `channel`.
+    ///
+    /// # Returns
+    /// The number of subscribers the message was sent to.
+    pub fn publish(&self, channel: &str, message: String) -> usize {
+        let mut channels = self.0.lock().unwrap();
+        let Some(sender) = channels.get(channel) else {
+            return 0;
+        };
+
+        match sender.send(Message {
+            channel: channel.to_string(),
+            message,
+        }) {
+            Ok(count) => count,
+            Err(_) => {
+                // everyone unsubscribed
+                channels.remove(channel);
+                0
+            }
+        }
+    }
+
+    /// Subscribe to a `channel` (which is created if it doesn't exist yet).
+    pub fn subscribe(&self, channel: &str) -> broadcast::Receiver<Message> {
+        let mut channels = self.0.lock().unwrap();
+        channels.retain(|_, x| x.receiver_count() > 0);
+        channels
+            .entry(channel.to_string())
+            .or_insert_with(|| broadcast::channel(CAPACITY).0)
+            .subscribe()
+    }
+}
+
+/// Publish a message (the body) to a channel. Responds with the number of
+/// subscribers the message was sent to.
+pub async fn publish(Path(channel): Path<String>, message: Bytes) -> String {
+    CHANNELS
+        .publish(&channel, String::from_utf8_lossy(&message).to_string())
+        .to_string()
+}
+
+/// Subscribe to a channel.
+pub async fn subscribe(
+    ws: Result<WebSocketUpgrade, WebSocketUpgradeRejection>,
+    Path(channel): Path<String>,
+) -> Response {
+    stream(ws, CHANNELS.subscribe(&channel), Some)
+}
+
+#[cfg(test)]
+mod test {
+    use super::Channels;
+
+    #[test]
+    fn channels() {
+        let channels = Channels::default();
+        assert_eq!(channels.publish("a", "nobody".to_string()), 0);
+
+        let mut a = channels.subscribe("a");
+        let mut also_a = channels.subscribe("a");
+        let b = channels.subscribe("b");
+        assert_eq!(channels.publish("a", "hi".to_string()), 2);
+        assert_eq!(a.try_recv().unwrap().message, "hi");
+        assert_eq!(also_a.try_recv().unwrap().channel, "a");
+
+        drop((a, also_a));
+        assert_eq!(channels.publish("a", "bye".to_string()), 0);
+        assert_eq!(channels.0.lock().unwrap().len(), 1);
+        drop(b);
+    }
+}
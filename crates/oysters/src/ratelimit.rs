@@ -0,0 +1,454 @@
+//! Rate limiting and usage counters for the HTTP API.
+//!
+//! Clients are identified by their token (if it's one of the configured tokens)
+//! or by their IP address. Every [`RateLimitConfig`] gives each client its own
+//! token bucket; requests which find a bucket empty are turned away with
+//! `429 Too Many Requests` and a `Retry-After` header.
+//!
+//! How many requests each client made (and how many bytes they wrote) is
+//! counted as well. The counters of clients identified by their token are kept
+//! in the server's own namespace as `usage:{client}:ops` and
+//! `usage:{client}:bytes_written`, so they're dumped (and restored) with
+//! everything else. The counters of clients identified by their IP address are
+//! only kept in memory, and are forgotten once the client has been idle for
+//! [`USAGE_IDLE`].
+use crate::MapState;
+use crate::auth::Auth;
+use crate::config::{RateLimitConfig, RouteGroup};
+use axum::extract::{ConnectInfo, MatchedPath, Request, State};
+use axum::http::{Method, StatusCode, header};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use serde::Serialize;
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How often the usage counters are written to the server's namespace.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long a client which isn't identified by its token can go without a
+/// request before its usage counters are forgotten.
+pub const USAGE_IDLE: Duration = Duration::from_secs(3600);
+
+/// A token bucket.
+#[derive(Clone, Copy, Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    /// Create a full bucket.
+    fn new(burst: f64, now: Instant) -> Self {
+        Self {
+            tokens: burst,
+            updated: now,
+        }
+    }
+
+    /// Add the tokens earned since the bucket was last updated.
+    fn refill(&mut self, rate: f64, burst: f64, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(burst);
+        self.updated = now;
+    }
+
+    /// How long until the bucket has a token (after [`Bucket::refill`]).
+    fn wait(&self, rate: f64) -> Option<Duration> {
+        if self.tokens >= 1.0 {
+            None
+        } else {
+            // buckets with no rate never refill
+            Some(Duration::try_from_secs_f64((1.0 - self.tokens) / rate).unwrap_or(Duration::MAX))
+        }
+    }
+}
+
+/// What a client has done.
+#[derive(Clone, Copy, Serialize, Debug, Default, PartialEq, Eq)]
+pub struct Usage {
+    /// The number of requests.
+    pub ops: usize,
+    /// The number of bytes sent in requests which write.
+    pub bytes_written: usize,
+}
+
+/// A client's [`Usage`] (as it's kept by the [`Limiter`]).
+#[derive(Clone, Copy, Debug)]
+struct Counters {
+    usage: Usage,
+    /// If the usage changed since it was last written.
+    changed: bool,
+    /// When the client last made a request.
+    used: Instant,
+}
+
+impl Counters {
+    fn new(now: Instant) -> Self {
+        Self {
+            usage: Usage::default(),
+            changed: false,
+            used: now,
+        }
+    }
+}
+
+/// If the counters of `client` are kept in the server's namespace.
+fn persisted(client: &str) -> bool {
+    client.starts_with("token:")
+}
+
+/// Rate limits and usage counters.
+pub struct Limiter {
+    limits: Vec<RateLimitConfig>,
+    auth: Option<Arc<Auth>>,
+    system: MapState,
+    buckets: Mutex<HashMap<(usize, String), Bucket>>,
+    usage: Mutex<HashMap<String, Counters>>,
+}
+
+impl Limiter {
+    /// Create a new [`Limiter`] (with the usage counters kept in `system`, see
+    /// [`Limiter::restore`]).
+    pub fn new(
+        limits: Vec<RateLimitConfig>,
+        auth: Option<Arc<Auth>>,
+        system: MapState,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            limits,
+            auth,
+            system,
+            buckets: Mutex::new(HashMap::new()),
+            usage: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Add the counters kept in the server's namespace (once it's been restored)
+    /// to the counters of this run, and start writing them back.
+    pub async fn restore(self: &Arc<Self>) {
+        let system = &mut *self.system.write().await;
+        let mut usage = self.usage.lock().unwrap();
+        let now = Instant::now();
+
+        for (key, value) in system.filter("usage:*") {
+            let Some((client, counter)) = key
+                .trim_start_matches("usage:")
+                .rsplit_once(':')
+                .map(|(a, b)| (a.to_string(), b))
+            else {
+                continue;
+            };
+
+            // older versions kept the counters of every client
+            if !persisted(&client) {
+                system.remove(&key);
+                continue;
+            }
+
+            let value: usize = String::from_utf8_lossy(&value.0).parse().unwrap_or(0);
+            let entry = usage.entry(client).or_insert_with(|| Counters::new(now));
+            match counter {
+                "ops" => entry.usage.ops += value,
+                "bytes_written" => entry.usage.bytes_written += value,
+                _ => continue,
+            }
+
+            entry.changed = true;
+        }
+
+        drop(usage);
+        tokio::spawn(self.clone().flush_forever());
+    }
+
+    /// Identify the client which sent a request.
+    fn client(&self, request: &Request) -> String {
+        if let Some(token) = self.auth.as_ref().and_then(|x| x.token(request)) {
+            return format!("token:{}", token.name());
+        }
+
+        match request.extensions().get::<ConnectInfo<SocketAddr>>() {
+            Some(ConnectInfo(addr)) => format!("ip:{}", addr.ip()),
+            None => "local".to_string(),
+        }
+    }
+
+    /// Take a token from every bucket of `client` which applies to `group`.
+    ///
+    /// # Returns
+    /// How long the client has to wait if a bucket is empty (nothing is taken
+    /// from any bucket then).
+    fn take(&self, client: &str, token: Option<&str>, group: RouteGroup) -> Option<Duration> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+
+        let applies = self.limits.iter().enumerate().filter(|(_, limit)| {
+            limit.routes.contains(&group)
+                && (limit.token.is_none() || limit.token.as_deref() == token)
+        });
+
+        let mut taken = Vec::new();
+        for (i, limit) in applies {
+            let burst = limit.burst.unwrap_or(limit.rate).max(1.0);
+            let bucket = buckets
+                .entry((i, client.to_string()))
+                .or_insert_with(|| Bucket::new(burst, now));
+
+            bucket.refill(limit.rate, burst, now);
+            if let Some(wait) = bucket.wait(limit.rate) {
+                return Some(wait);
+            }
+
+            taken.push(i);
+        }
+
+        for i in taken {
+            buckets.get_mut(&(i, client.to_string())).unwrap().tokens -= 1.0;
+        }
+
+        None
+    }
+
+    /// Count a request from `client`.
+    fn count(&self, client: String, bytes_written: usize) {
+        let now = Instant::now();
+        let mut usage = self.usage.lock().unwrap();
+        let counters = usage.entry(client).or_insert_with(|| Counters::new(now));
+        counters.usage.ops += 1;
+        counters.usage.bytes_written += bytes_written;
+        counters.changed = true;
+        counters.used = now;
+    }
+
+    /// Forget the counters of clients which aren't written to the server's
+    /// namespace and have been idle for [`USAGE_IDLE`] (at `now`).
+    fn forget_idle(&self, now: Instant) {
+        self.usage.lock().unwrap().retain(|client, counters| {
+            persisted(client) || now.saturating_duration_since(counters.used) < USAGE_IDLE
+        });
+    }
+
+    /// Write the counters which changed to the server's namespace (and forget
+    /// idle clients, and buckets which have filled up again).
+    async fn flush(&self) {
+        let now = Instant::now();
+        self.forget_idle(now);
+
+        let changed: Vec<(String, Usage)> = self
+            .usage
+            .lock()
+            .unwrap()
+            .iter_mut()
+            .filter(|(client, counters)| counters.changed && persisted(client))
+            .map(|(client, counters)| {
+                counters.changed = false;
+                (client.clone(), counters.usage)
+            })
+            .collect();
+
+        if !changed.is_empty() {
+            let system = &mut *self.system.write().await;
+            for (client, usage) in changed {
+                system.insert(
+                    format!("usage:{client}:ops"),
+                    usage.ops.to_string().into_bytes(),
+                );
+                system.insert(
+                    format!("usage:{client}:bytes_written"),
+                    usage.bytes_written.to_string().into_bytes(),
+                );
+            }
+        }
+
+        self.buckets.lock().unwrap().retain(|(i, _), bucket| {
+            let limit = &self.limits[*i];
+            let burst = limit.burst.unwrap_or(limit.rate).max(1.0);
+            bucket.refill(limit.rate, burst, now);
+            bucket.tokens < burst
+        });
+    }
+
+    async fn flush_forever(self: Arc<Self>) {
+        let mut interval = tokio::time::interval(FLUSH_INTERVAL);
+        loop {
+            interval.tick().await;
+            self.flush().await;
+        }
+    }
+}
+
+/// Count a request, and turn it away if its client is over a rate limit.
+pub async fn limit(State(limiter): State<Arc<Limiter>>, request: Request, next: Next) -> Response {
+    let group = match request.extensions().get::<MatchedPath>() {
+        Some(path) => RouteGroup::of(path.as_str()),
+        None => RouteGroup::Data,
+    };
+
+    let client = limiter.client(&request);
+    let token = client.strip_prefix("token:");
+
+    if let Some(wait) = limiter.take(&client, token, group) {
+        return (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(
+                header::RETRY_AFTER,
+                wait.as_secs_f64().ceil().max(1.0).to_string(),
+            )],
+        )
+            .into_response();
+    }
+
+    let bytes_written = if matches!(*request.method(), Method::GET | Method::HEAD) {
+        0
+    } else {
+        request
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|x| x.to_str().ok())
+            .and_then(|x| x.parse().ok())
+            .unwrap_or(0)
+    };
+
+    limiter.count(client, bytes_written);
+    next.run(request).await
+}
+
+/// Get every client's usage.
+pub async fn usage(Extension(limiter): Extension<Arc<Limiter>>) -> impl IntoResponse {
+    let usage: HashMap<String, Usage> = limiter
+        .usage
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(client, counters)| (client.clone(), counters.usage))
+        .collect();
+
+    Json(usage)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Bucket, Limiter, USAGE_IDLE};
+    use crate::MapState;
+    use crate::config::{RateLimitConfig, RouteGroup, default_routes};
+    use oysters_core::{Oyster, OysterOptions};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+    use tokio::sync::RwLock;
+
+    /// A map of its own (tests share tables with the sqlite backend).
+    fn system(table: &str) -> MapState {
+        Arc::new(RwLock::new(Oyster::with_options(OysterOptions {
+            table: table.to_string(),
+            ..Default::default()
+        })))
+    }
+
+    #[test]
+    fn bucket() {
+        let now = Instant::now();
+        let mut bucket = Bucket::new(2.0, now);
+        bucket.tokens -= 2.0;
+
+        bucket.refill(4.0, 2.0, now);
+        assert_eq!(bucket.wait(4.0), Some(Duration::from_millis(250)));
+
+        bucket.refill(4.0, 2.0, now + Duration::from_secs(10));
+        assert_eq!(bucket.tokens, 2.0);
+        assert_eq!(bucket.wait(4.0), None);
+    }
+
+    #[test]
+    fn limits() {
+        let limiter = Limiter::new(
+            vec![
+                RateLimitConfig {
+                    rate: 1.0,
+                    burst: Some(2.0),
+                    routes: default_routes(),
+                    token: None,
+                },
+                RateLimitConfig {
+                    rate: 0.1,
+                    burst: None,
+                    routes: vec![RouteGroup::Admin],
+                    token: Some("ci".to_string()),
+                },
+            ],
+            None,
+            Arc::new(RwLock::new(Oyster::new())),
+        );
+
+        assert_eq!(limiter.take("ip:a", None, RouteGroup::Data), None);
+        assert_eq!(limiter.take("ip:a", None, RouteGroup::Data), None);
+        assert!(limiter.take("ip:a", None, RouteGroup::Data).is_some());
+        assert_eq!(limiter.take("ip:b", None, RouteGroup::Data), None);
+
+        assert_eq!(
+            limiter.take("token:ci", Some("ci"), RouteGroup::Admin),
+            None
+        );
+        assert!(
+            limiter
+                .take("token:ci", Some("ci"), RouteGroup::Admin)
+                .is_some()
+        );
+        // nothing is taken from the other buckets when one is empty
+        assert_eq!(limiter.take("token:ci", Some("ci"), RouteGroup::Data), None);
+        assert!(
+            limiter
+                .take("token:ci", Some("ci"), RouteGroup::Data)
+                .is_some()
+        );
+    }
+
+    #[tokio::test]
+    async fn usage() {
+        let system = system("test_usage");
+        let limiter = Limiter::new(Vec::new(), None, system.clone());
+        limiter.count("token:ci".to_string(), 2);
+        limiter.count("ip:a".to_string(), 3);
+        limiter.flush().await;
+
+        // only the counters of tokens are written
+        let mut keys: Vec<String> = system
+            .read()
+            .await
+            .filter("usage:*")
+            .into_iter()
+            .map(|x| x.0)
+            .collect();
+        keys.sort();
+        assert_eq!(keys, ["usage:token:ci:bytes_written", "usage:token:ci:ops"]);
+
+        // idle clients are forgotten (unless their counters are written)
+        limiter.forget_idle(Instant::now() + USAGE_IDLE);
+        let usage = limiter.usage.lock().unwrap();
+        assert!(usage.contains_key("token:ci"));
+        assert!(!usage.contains_key("ip:a"));
+    }
+
+    #[tokio::test]
+    async fn restore() {
+        let system = system("test_usage_restore");
+        let written = Limiter::new(Vec::new(), None, system.clone());
+        for client in ["token:restore-a", "token:restore-b"] {
+            written.count(client.to_string(), 1);
+        }
+
+        written.flush().await;
+
+        // every stored counter is read back
+        let restored = Limiter::new(Vec::new(), None, system.clone());
+        restored.restore().await;
+        let usage = restored.usage.lock().unwrap();
+        for client in ["token:restore-a", "token:restore-b"] {
+            assert_eq!(usage[client].usage.ops, 1);
+            assert_eq!(usage[client].usage.bytes_written, 1);
+        }
+    }
+}
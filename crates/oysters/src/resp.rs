@@ -0,0 +1,649 @@
+//! Redis (RESP) protocol listener.
+//!
+//! Lets existing Redis clients (and `redis-cli`) use the same map as the HTTP
+//! server. Connections start out speaking RESP2, and can switch to RESP3 with
+//! `HELLO 3`.
+//!
+//! Supported commands: `PING`, `GET`, `SET` (with `EX`, `PX`, `NX`, and `XX`),
+//! `DEL`, `INCR`, `DECR`, `EXISTS`, `KEYS`, `SCAN`, `EXPIRE`, `TTL`, `MGET`, `MSET`,
+//! `HELLO`, `SELECT`, `CLIENT`, `COMMAND`, and `QUIT`.
+//!
+//! `KEYS` and `SCAN` patterns work like the rest of oysters instead of like
+//! Redis: they only support a single `*` at the start or end of the pattern (see
+//! [`Oyster::filter_keys`](oysters_core::Oyster::filter_keys)).
+use crate::MapState;
+use std::io::{Error, ErrorKind, Result};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::warn;
+
+/// The longest line (inline command or length header) a client can send.
+const MAX_LINE_LEN: u64 = 64 * 1024;
+/// The largest bulk string a client can send (the same limit as Redis).
+const MAX_BULK_LEN: usize = 512 * 1024 * 1024;
+/// The number of keys `SCAN` returns when no `COUNT` is given.
+const DEFAULT_SCAN_COUNT: usize = 10;
+
+/// A RESP value.
+#[derive(Debug, PartialEq)]
+pub enum Frame {
+    Simple(String),
+    Error(String),
+    Integer(i64),
+    Bulk(Vec<u8>),
+    Null,
+    Array(Vec<Frame>),
+    /// A map (sent as a flat array of keys and values to RESP2 clients).
+    Map(Vec<(Frame, Frame)>),
+}
+
+impl Frame {
+    /// `+OK`
+    fn ok() -> Self {
+        Self::Simple("OK".to_string())
+    }
+
+    /// A generic error.
+    fn error(message: &str) -> Self {
+        Self::Error(format!("ERR {message}"))
+    }
+
+    /// The error sent when a command is given the wrong number of arguments.
+    fn arity(command: &str) -> Self {
+        Self::error(&format!(
+            "wrong number of arguments for '{}' command",
+            command.to_lowercase()
+        ))
+    }
+
+    /// The error sent when an argument should've been a number.
+    fn not_integer() -> Self {
+        Self::error("value is not an integer or out of range")
+    }
+
+    /// A bulk string, or null.
+    fn bulk_or_null(value: Option<Vec<u8>>) -> Self {
+        match value {
+            Some(v) => Self::Bulk(v),
+            None => Self::Null,
+        }
+    }
+
+    /// Write the frame into `out`.
+    ///
+    /// # Arguments
+    /// * `version` - the protocol version (`2` or `3`)
+    /// * `out` - the buffer to write into
+    pub fn write(&self, version: u8, out: &mut Vec<u8>) {
+        match self {
+            Self::Simple(s) => out.extend_from_slice(format!("+{s}\r\n").as_bytes()),
+            Self::Error(s) => out.extend_from_slice(format!("-{s}\r\n").as_bytes()),
+            Self::Integer(i) => out.extend_from_slice(format!(":{i}\r\n").as_bytes()),
+            Self::Bulk(b) => {
+                out.extend_from_slice(format!("${}\r\n", b.len()).as_bytes());
+                out.extend_from_slice(b);
+                out.extend_from_slice(b"\r\n");
+            }
+            Self::Null => {
+                if version >= 3 {
+                    out.extend_from_slice(b"_\r\n")
+                } else {
+                    out.extend_from_slice(b"$-1\r\n")
+                }
+            }
+            Self::Array(items) => {
+                out.extend_from_slice(format!("*{}\r\n", items.len()).as_bytes());
+
+                for item in items {
+                    item.write(version, out);
+                }
+            }
+            Self::Map(items) => {
+                if version >= 3 {
+                    out.extend_from_slice(format!("%{}\r\n", items.len()).as_bytes());
+                } else {
+                    out.extend_from_slice(format!("*{}\r\n", items.len() * 2).as_bytes());
+                }
+
+                for (k, v) in items {
+                    k.write(version, out);
+                    v.write(version, out);
+                }
+            }
+        }
+    }
+}
+
+/// Create an [`ErrorKind::InvalidData`] error.
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+/// Parse a number from an argument.
+fn number(bytes: &[u8]) -> Option<usize> {
+    std::str::from_utf8(bytes).ok()?.parse().ok()
+}
+
+/// Turn an expire time sent by a client into milliseconds.
+///
+/// # Arguments
+/// * `time` - the expire time
+/// * `seconds` - if the time is in seconds (rather than milliseconds)
+///
+/// # Returns
+/// `None` if the time is too long to be stored.
+fn expire_time(time: usize, seconds: bool) -> Option<usize> {
+    let ms = if seconds {
+        time.checked_mul(1000)?
+    } else {
+        time
+    };
+    i64::try_from(ms).is_ok().then_some(ms)
+}
+
+/// Read a single line (without its line ending).
+///
+/// Returns `None` if the connection was closed.
+async fn read_line<R: AsyncBufRead + Unpin>(reader: &mut R) -> Result<Option<Vec<u8>>> {
+    let mut line = Vec::new();
+
+    if (&mut *reader)
+        .take(MAX_LINE_LEN)
+        .read_until(b'\n', &mut line)
+        .await?
+        == 0
+    {
+        return Ok(None);
+    }
+
+    if line.pop() != Some(b'\n') {
+        return Err(invalid("line too long"));
+    }
+
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+
+    Ok(Some(line))
+}
+
+/// Read a single command. Commands are either an array of bulk strings, or an
+/// inline command (arguments separated by spaces).
+///
+/// Returns `None` if the connection was closed.
+pub async fn read_command<R: AsyncBufRead + Unpin>(reader: &mut R) -> Result<Option<Vec<Vec<u8>>>> {
+    let line = match read_line(reader).await? {
+        Some(l) => l,
+        None => return Ok(None),
+    };
+
+    let count = match line.strip_prefix(b"*") {
+        Some(count) => number(count).ok_or_else(|| invalid("invalid array length"))?,
+        None => {
+            // inline command
+            return Ok(Some(
+                line.split(|x| x.is_ascii_whitespace())
+                    .filter(|x| !x.is_empty())
+                    .map(|x| x.to_vec())
+                    .collect(),
+            ));
+        }
+    };
+
+    let mut args = Vec::new();
+
+    for _ in 0..count {
+        let header = read_line(reader)
+            .await?
+            .ok_or_else(|| Error::from(ErrorKind::UnexpectedEof))?;
+
+        let len = match header.strip_prefix(b"$").and_then(number) {
+            Some(len) if len <= MAX_BULK_LEN => len,
+            _ => return Err(invalid("expected a bulk string")),
+        };
+
+        // the string is followed by a line ending
+        let mut arg = vec![0; len + 2];
+        reader.read_exact(&mut arg).await?;
+        arg.truncate(len);
+        args.push(arg);
+    }
+
+    Ok(Some(args))
+}
+
+/// Accept RESP connections forever.
+///
+/// # Arguments
+/// * `listener` - the listener to accept connections from
+/// * `map` - the shared map
+pub async fn serve(listener: TcpListener, map: MapState) {
+    loop {
+        let (stream, addr) = match listener.accept().await {
+            Ok(x) => x,
+            Err(e) => {
+                warn!("failed to accept resp connection: {e}");
+                continue;
+            }
+        };
+
+        let map = map.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle(stream, map).await {
+                warn!("resp connection from {addr} failed: {e}");
+            }
+        });
+    }
+}
+
+/// Handle every command sent over a single connection.
+async fn handle(stream: TcpStream, map: MapState) -> Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+    let mut version = 2;
+    let mut out = Vec::new();
+
+    while let Some(args) = read_command(&mut reader).await? {
+        let Some((name, args)) = args.split_first() else {
+            continue;
+        };
+
+        let name = String::from_utf8_lossy(name).to_uppercase();
+        let frame = if name == "QUIT" {
+            Frame::ok()
+        } else {
+            execute(&name, args, &map, &mut version).await
+        };
+
+        out.clear();
+        frame.write(version, &mut out);
+        writer.write_all(&out).await?;
+
+        if name == "QUIT" {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// Run a single command.
+///
+/// # Arguments
+/// * `name` - the (uppercase) name of the command
+/// * `args` - the arguments of the command
+/// * `map` - the shared map
+/// * `version` - the protocol version of the connection (changed by `HELLO`)
+pub async fn execute(name: &str, args: &[Vec<u8>], map: &MapState, version: &mut u8) -> Frame {
+    let key = |x: &Vec<u8>| String::from_utf8_lossy(x).to_string();
+
+    match (name, args) {
+        ("PING", []) => Frame::Simple("PONG".to_string()),
+        ("PING", [message]) => Frame::Bulk(message.clone()),
+        ("GET", [k]) => {
+            let k = key(k);
+            let mut writer = map.write().await;
+            let value = writer.get(&k);
+
+            if value.is_some() {
+                writer.update_resource_descriptor(&k);
+            }
+
+            Frame::bulk_or_null(value)
+        }
+        ("SET", [k, value, options @ ..]) => set(key(k), value, options, map).await,
+        ("DEL", keys) if !keys.is_empty() => {
+            let writer = &mut *map.write().await;
+            let mut removed = 0;
+
+            for k in keys {
+                let k = key(k);
+                if writer.get_full(&k).is_some() {
+                    writer.remove(&k);
+                    removed += 1;
+                }
+            }
+
+            Frame::Integer(removed)
+        }
+        ("INCR", [k]) => count(key(k), false, map).await,
+        ("DECR", [k]) => count(key(k), true, map).await,
+        ("EXISTS", keys) if !keys.is_empty() => {
+            let reader = map.read().await;
+            Frame::Integer(
+                keys.iter()
+                    .filter(|k| reader.get_full(&key(k)).is_some())
+                    .count() as i64,
+            )
+        }
+        ("KEYS", [pattern]) => Frame::Array(
+            matching_keys(&key(pattern), map)
+                .await
+                .into_iter()
+                .map(|x| Frame::Bulk(x.into_bytes()))
+                .collect(),
+        ),
+        ("SCAN", [cursor, options @ ..]) => scan(cursor, options, map).await,
+        ("EXPIRE", [k, seconds]) => match number(seconds) {
+            Some(seconds) => match expire_time(seconds, true) {
+                Some(ttl) => Frame::Integer(map.write().await.expire(&key(k), ttl) as i64),
+                None => Frame::error("invalid expire time in 'expire' command"),
+            },
+            None => Frame::not_integer(),
+        },
+        ("TTL", [k]) => match map.read().await.ttl(&key(k)) {
+            None => Frame::Integer(-2),
+            Some(None) => Frame::Integer(-1),
+            Some(Some(ms)) => Frame::Integer(ms.div_ceil(1000) as i64),
+        },
+        ("MGET", keys) if !keys.is_empty() => {
+            let reader = map.read().await;
+            Frame::Array(
+                keys.iter()
+                    .map(|k| Frame::bulk_or_null(reader.get(&key(k))))
+                    .collect(),
+            )
+        }
+        ("MSET", pairs) if !pairs.is_empty() && pairs.len() % 2 == 0 => {
+            let writer = &mut *map.write().await;
+
+            for pair in pairs.chunks(2) {
+                writer.insert(key(&pair[0]), pair[1].clone());
+            }
+
+            Frame::ok()
+        }
+        ("HELLO", args) => hello(args, version),
+        ("SELECT", [db]) => match number(db) {
+            Some(0) => Frame::ok(),
+            Some(_) => Frame::error("DB index is out of range"),
+            None => Frame::not_integer(),
+        },
+        // clients send these when they connect, none of them change anything
+        ("CLIENT", [_, ..]) => Frame::ok(),
+        ("COMMAND", _) => Frame::Array(Vec::new()),
+        (
+            "PING" | "GET" | "SET" | "DEL" | "INCR" | "DECR" | "EXISTS" | "KEYS" | "SCAN"
+            | "EXPIRE" | "TTL" | "MGET" | "MSET" | "SELECT" | "CLIENT",
+            _,
+        ) => Frame::arity(name),
+        _ => Frame::error(&format!("unknown command '{}'", name.to_lowercase())),
+    }
+}
+
+/// `SET key value [NX | XX] [EX seconds | PX milliseconds]`
+async fn set(key: String, value: &[u8], options: &[Vec<u8>], map: &MapState) -> Frame {
+    let mut ttl = None;
+    let mut nx = false;
+    let mut xx = false;
+
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        match String::from_utf8_lossy(option).to_uppercase().as_str() {
+            "NX" => nx = true,
+            "XX" => xx = true,
+            unit @ ("EX" | "PX") => match options.next().and_then(|x| number(x)) {
+                Some(x) => match expire_time(x, unit == "EX").filter(|x| *x > 0) {
+                    Some(x) => ttl = Some(x),
+                    None => return Frame::error("invalid expire time in 'set' command"),
+                },
+                None => return Frame::not_integer(),
+            },
+            _ => return Frame::error("syntax error"),
+        }
+    }
+
+    if nx && xx {
+        return Frame::error("syntax error");
+    }
+
+    let mut writer = map.write().await;
+    let exists = writer.get_full(&key).is_some();
+
+    if (nx && exists) || (xx && !exists) {
+        return Frame::Null;
+    }
+
+    writer.insert(key.clone(), value.to_vec());
+
+    if let Some(ttl) = ttl {
+        writer.expire(&key, ttl);
+    }
+
+    Frame::ok()
+}
+
+/// `INCR key` and `DECR key`. Keys which don't exist start at `0`.
+async fn count(key: String, decr: bool, map: &MapState) -> Frame {
+    let mut writer = map.write().await;
+    if writer.get_full(&key).is_none() {
+        writer.insert(key.clone(), b"0".to_vec());
+    }
+
+    let counted = if decr {
+        writer.decr(key)
+    } else {
+        writer.incr(key)
+    };
+
+    match counted {
+        Ok(number) => Frame::Integer(number),
+        Err(e) if e.kind() == ErrorKind::InvalidInput => {
+            Frame::error("increment or decrement would overflow")
+        }
+        Err(_) => Frame::not_integer(),
+    }
+}
+
+/// Get every key matching `pattern` (sorted, so `SCAN` cursors stay stable).
+async fn matching_keys(pattern: &str, map: &MapState) -> Vec<String> {
+    let mut keys: Vec<String> = map
+        .read()
+        .await
+        .filter_keys(pattern)
+        .into_iter()
+        .map(|x| x.to_string())
+        .collect();
+
+    keys.sort();
+    keys
+}
+
+/// `SCAN cursor [MATCH pattern] [COUNT count]`
+///
+/// The cursor is the index of the next key to return.
+async fn scan(cursor: &[u8], options: &[Vec<u8>], map: &MapState) -> Frame {
+    let Some(cursor) = number(cursor) else {
+        return Frame::error("invalid cursor");
+    };
+
+    let mut pattern = "*".to_string();
+    let mut count = DEFAULT_SCAN_COUNT;
+
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        match (
+            String::from_utf8_lossy(option).to_uppercase().as_str(),
+            options.next(),
+        ) {
+            ("MATCH", Some(x)) => pattern = String::from_utf8_lossy(x).to_string(),
+            ("COUNT", Some(x)) => match number(x) {
+                Some(x) if x > 0 => count = x,
+                _ => return Frame::not_integer(),
+            },
+            _ => return Frame::error("syntax error"),
+        }
+    }
+
+    let keys = matching_keys(&pattern, map).await;
+    let end = (cursor + count).min(keys.len());
+    let next = if end >= keys.len() { 0 } else { end };
+
+    Frame::Array(vec![
+        Frame::Bulk(next.to_string().into_bytes()),
+        Frame::Array(
+            keys.get(cursor..end)
+                .unwrap_or_default()
+                .iter()
+                .map(|x| Frame::Bulk(x.clone().into_bytes()))
+                .collect(),
+        ),
+    ])
+}
+
+/// `HELLO [protover]`
+fn hello(args: &[Vec<u8>], version: &mut u8) -> Frame {
+    if let Some(protover) = args.first() {
+        match number(protover) {
+            Some(x @ (2 | 3)) => *version = x as u8,
+            _ => return Frame::Error("NOPROTO unsupported protocol version".to_string()),
+        }
+    }
+
+    let field = |k: &str, v: Frame| (Frame::Bulk(k.as_bytes().to_vec()), v);
+
+    Frame::Map(vec![
+        field("server", Frame::Bulk(b"oysters".to_vec())),
+        field(
+            "version",
+            Frame::Bulk(env!("CARGO_PKG_VERSION").as_bytes().to_vec()),
+        ),
+        field("proto", Frame::Integer(*version as i64)),
+        field("mode", Frame::Bulk(b"standalone".to_vec())),
+        field("role", Frame::Bulk(b"master".to_vec())),
+        field("modules", Frame::Array(Vec::new())),
+    ])
+}
+
+#[cfg(test)]
+mod test {
+    use super::serve;
+    use oysters_core::Oyster;
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio::sync::RwLock;
+
+    /// Send a command as an array of bulk strings, and check the raw response.
+    async fn roundtrip(stream: &mut TcpStream, command: &[&[u8]], expected: &[u8]) {
+        let mut out = format!("*{}\r\n", command.len()).into_bytes();
+
+        for arg in command {
+            out.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+            out.extend_from_slice(arg);
+            out.extend_from_slice(b"\r\n");
+        }
+
+        stream.write_all(&out).await.unwrap();
+
+        let mut response = vec![0; expected.len()];
+        stream.read_exact(&mut response).await.unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&response),
+            String::from_utf8_lossy(expected)
+        );
+    }
+
+    #[tokio::test]
+    async fn resp() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener, Arc::new(RwLock::new(Oyster::new()))));
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+
+        // inline commands
+        stream.write_all(b"PING\r\n").await.unwrap();
+        let mut pong = [0; 7];
+        stream.read_exact(&mut pong).await.unwrap();
+        assert_eq!(&pong, b"+PONG\r\n");
+
+        roundtrip(&mut stream, &[b"SET", b"a", b"1"], b"+OK\r\n").await;
+        roundtrip(&mut stream, &[b"GET", b"a"], b"$1\r\n1\r\n").await;
+        roundtrip(&mut stream, &[b"INCR", b"a"], b":2\r\n").await;
+        roundtrip(&mut stream, &[b"DECR", b"counter"], b":-1\r\n").await;
+        roundtrip(&mut stream, &[b"DECR", b"counter"], b":-2\r\n").await;
+        let max = i64::MAX.to_string();
+        roundtrip(&mut stream, &[b"SET", b"max", max.as_bytes()], b"+OK\r\n").await;
+        roundtrip(
+            &mut stream,
+            &[b"INCR", b"max"],
+            b"-ERR increment or decrement would overflow\r\n",
+        )
+        .await;
+        roundtrip(&mut stream, &[b"GET", b"missing"], b"$-1\r\n").await;
+
+        // binary values
+        roundtrip(&mut stream, &[b"SET", b"bin", b"\r\n\0\xFF"], b"+OK\r\n").await;
+        roundtrip(&mut stream, &[b"GET", b"bin"], b"$4\r\n\r\n\0\xFF\r\n").await;
+        roundtrip(
+            &mut stream,
+            &[b"INCR", b"bin"],
+            b"-ERR value is not an integer or out of range\r\n",
+        )
+        .await;
+
+        roundtrip(&mut stream, &[b"MSET", b"b", b"2", b"c", b"3"], b"+OK\r\n").await;
+        roundtrip(
+            &mut stream,
+            &[b"MGET", b"a", b"x", b"c"],
+            b"*3\r\n$1\r\n2\r\n$-1\r\n$1\r\n3\r\n",
+        )
+        .await;
+        roundtrip(&mut stream, &[b"EXISTS", b"a", b"b", b"x"], b":2\r\n").await;
+        roundtrip(
+            &mut stream,
+            &[b"SCAN", b"0", b"COUNT", b"2"],
+            b"*2\r\n$1\r\n2\r\n*2\r\n$1\r\na\r\n$1\r\nb\r\n",
+        )
+        .await;
+        roundtrip(
+            &mut stream,
+            &[b"KEYS", b"c*"],
+            b"*2\r\n$1\r\nc\r\n$7\r\ncounter\r\n",
+        )
+        .await;
+
+        // expiry
+        roundtrip(&mut stream, &[b"TTL", b"a"], b":-1\r\n").await;
+        roundtrip(&mut stream, &[b"EXPIRE", b"a", b"100"], b":1\r\n").await;
+        roundtrip(&mut stream, &[b"TTL", b"a"], b":100\r\n").await;
+        roundtrip(
+            &mut stream,
+            &[b"EXPIRE", b"a", b"18446744073709551615"],
+            b"-ERR invalid expire time in 'expire' command\r\n",
+        )
+        .await;
+        roundtrip(
+            &mut stream,
+            &[b"SET", b"a", b"1", b"EX", b"9223372036854775807"],
+            b"-ERR invalid expire time in 'set' command\r\n",
+        )
+        .await;
+        roundtrip(&mut stream, &[b"TTL", b"a"], b":100\r\n").await;
+        roundtrip(&mut stream, &[b"SET", b"a", b"1", b"PX", b"1"], b"+OK\r\n").await;
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        roundtrip(&mut stream, &[b"GET", b"a"], b"$-1\r\n").await;
+        roundtrip(&mut stream, &[b"TTL", b"a"], b":-2\r\n").await;
+
+        roundtrip(&mut stream, &[b"DEL", b"b", b"c", b"x"], b":2\r\n").await;
+        roundtrip(
+            &mut stream,
+            &[b"GET"],
+            b"-ERR wrong number of arguments for 'get' command\r\n",
+        )
+        .await;
+
+        // resp3
+        let version = env!("CARGO_PKG_VERSION");
+        let hello = format!(
+            "%6\r\n$6\r\nserver\r\n$7\r\noysters\r\n$7\r\nversion\r\n${}\r\n{version}\r\n\
+             $5\r\nproto\r\n:3\r\n$4\r\nmode\r\n$10\r\nstandalone\r\n$4\r\nrole\r\n$6\r\nmaster\r\n\
+             $7\r\nmodules\r\n*0\r\n",
+            version.len()
+        );
+        roundtrip(&mut stream, &[b"HELLO", b"3"], hello.as_bytes()).await;
+        roundtrip(&mut stream, &[b"GET", b"missing"], b"_\r\n").await;
+        roundtrip(&mut stream, &[b"QUIT"], b"+OK\r\n").await;
+    }
+}
@@ -0,0 +1,141 @@
+//! Sets (`/_set/{key}`), see [`oysters_core::set`].
+//!
+//! Unions and intersections read every set they're given
+//! (`?with={key},{key}`), so tokens need read access to all of them.
+use crate::{KeyPath, MapState, metrics, wrong_kind};
+use axum::extract::{Path, Query};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use oysters_core::ValueKind;
+use serde::Deserialize;
+
+/// The key and member a route is about.
+#[derive(Deserialize)]
+pub struct MemberPath {
+    pub key: String,
+    pub member: String,
+}
+
+#[derive(Deserialize)]
+pub struct WithQuery {
+    /// The other sets (a comma-separated list of keys).
+    #[serde(default)]
+    pub with: String,
+}
+
+impl WithQuery {
+    /// The keys of the other sets.
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.with.split(',').filter(|x| !x.is_empty())
+    }
+}
+
+/// Turn members read from a set into a response. Members are sent as strings
+/// (invalid UTF-8 is replaced).
+fn members_response(members: Option<Vec<Vec<u8>>>) -> Response {
+    match members {
+        Some(members) => Json(
+            members
+                .iter()
+                .map(|x| String::from_utf8_lossy(x).to_string())
+                .collect::<Vec<_>>(),
+        )
+        .into_response(),
+        None => wrong_kind(ValueKind::Set),
+    }
+}
+
+/// Get every member of a set.
+pub async fn members(
+    Path(KeyPath { key }): Path<KeyPath>,
+    Extension(map): Extension<MapState>,
+) -> Response {
+    members_response(metrics::read(&map).await.set_members(&key))
+}
+
+/// Check if a value is a member of a set.
+pub async fn is_member(
+    Path(MemberPath { key, member }): Path<MemberPath>,
+    Extension(map): Extension<MapState>,
+) -> Response {
+    match metrics::read(&map)
+        .await
+        .set_is_member(&key, &member.into_bytes())
+    {
+        Some(true) => StatusCode::OK.into_response(),
+        Some(false) => StatusCode::NOT_FOUND.into_response(),
+        None => wrong_kind(ValueKind::Set),
+    }
+}
+
+/// Add a member to a set. Responds with `201 Created` if it wasn't in the set.
+pub async fn add(
+    Path(MemberPath { key, member }): Path<MemberPath>,
+    Extension(map): Extension<MapState>,
+) -> Response {
+    match metrics::write(&map)
+        .await
+        .set_add(key, vec![member.into_bytes()])
+    {
+        Some(1) => StatusCode::CREATED.into_response(),
+        Some(_) => StatusCode::OK.into_response(),
+        None => wrong_kind(ValueKind::Set),
+    }
+}
+
+/// Remove a member from a set.
+pub async fn remove(
+    Path(MemberPath { key, member }): Path<MemberPath>,
+    Extension(map): Extension<MapState>,
+) -> Response {
+    match metrics::write(&map)
+        .await
+        .set_remove(&key, &[member.into_bytes()])
+    {
+        Some(1) => StatusCode::OK.into_response(),
+        Some(_) => StatusCode::NOT_FOUND.into_response(),
+        None => wrong_kind(ValueKind::Set),
+    }
+}
+
+/// Get the number of members in a set.
+pub async fn len(
+    Path(KeyPath { key }): Path<KeyPath>,
+    Extension(map): Extension<MapState>,
+) -> Response {
+    match metrics::read(&map).await.set_len(&key) {
+        Some(len) => len.to_string().into_response(),
+        None => wrong_kind(ValueKind::Set),
+    }
+}
+
+/// Get every value which is a member of the set or any of the other sets.
+pub async fn union(
+    Path(KeyPath { key }): Path<KeyPath>,
+    Extension(map): Extension<MapState>,
+    Query(query): Query<WithQuery>,
+) -> Response {
+    let keys: Vec<String> = [key.as_str()]
+        .into_iter()
+        .chain(query.keys())
+        .map(String::from)
+        .collect();
+
+    members_response(metrics::read(&map).await.set_union(&keys))
+}
+
+/// Get every value which is a member of the set and all of the other sets.
+pub async fn intersect(
+    Path(KeyPath { key }): Path<KeyPath>,
+    Extension(map): Extension<MapState>,
+    Query(query): Query<WithQuery>,
+) -> Response {
+    let keys: Vec<String> = [key.as_str()]
+        .into_iter()
+        .chain(query.keys())
+        .map(String::from)
+        .collect();
+
+    members_response(metrics::read(&map).await.set_intersect(&keys))
+}
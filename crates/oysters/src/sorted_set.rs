@@ -0,0 +1,189 @@
+//! Sorted sets (`/_sorted/{key}`), see [`oysters_core::sorted_set`].
+//!
+//! Ranges are sent as a list of `{"member": ..., "score": ...}` objects, from the
+//! lowest score to the highest.
+use crate::list::SpanQuery;
+use crate::set::MemberPath;
+use crate::{KeyPath, MapState, metrics, wrong_kind};
+use axum::extract::{Path, Query};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use oysters_core::ValueKind;
+use serde::{Deserialize, Serialize};
+
+/// A member of a sorted set (as a string, invalid UTF-8 is replaced) and its
+/// score.
+#[derive(Serialize)]
+pub struct Scored {
+    member: String,
+    score: f64,
+}
+
+#[derive(Deserialize)]
+pub struct ScoreQuery {
+    /// The lowest score (no limit by default).
+    #[serde(default = "default_min")]
+    min: f64,
+    /// The highest score (no limit by default).
+    #[serde(default = "default_max")]
+    max: f64,
+}
+
+#[derive(Deserialize)]
+pub struct IncrQuery {
+    /// What to add to the score (`1` by default).
+    #[serde(default = "default_by")]
+    by: f64,
+}
+
+fn default_min() -> f64 {
+    f64::NEG_INFINITY
+}
+
+fn default_max() -> f64 {
+    f64::INFINITY
+}
+
+fn default_by() -> f64 {
+    1.0
+}
+
+/// Turn a range read from a sorted set into a response.
+fn scored(range: Option<Vec<(Vec<u8>, f64)>>) -> Response {
+    match range {
+        Some(range) => Json(
+            range
+                .into_iter()
+                .map(|(member, score)| Scored {
+                    member: String::from_utf8_lossy(&member).to_string(),
+                    score,
+                })
+                .collect::<Vec<_>>(),
+        )
+        .into_response(),
+        None => wrong_kind(ValueKind::SortedSet),
+    }
+}
+
+/// Get the members between two ranks (every member by default).
+pub async fn range(
+    Path(KeyPath { key }): Path<KeyPath>,
+    Extension(map): Extension<MapState>,
+    Query(SpanQuery { start, stop }): Query<SpanQuery>,
+) -> Response {
+    scored(metrics::read(&map).await.sorted_range(&key, start, stop))
+}
+
+/// Get the members with a score between `min` and `max`.
+pub async fn range_by_score(
+    Path(KeyPath { key }): Path<KeyPath>,
+    Extension(map): Extension<MapState>,
+    Query(ScoreQuery { min, max }): Query<ScoreQuery>,
+) -> Response {
+    scored(
+        metrics::read(&map)
+            .await
+            .sorted_range_by_score(&key, min, max),
+    )
+}
+
+/// Get the number of members in a sorted set.
+pub async fn len(
+    Path(KeyPath { key }): Path<KeyPath>,
+    Extension(map): Extension<MapState>,
+) -> Response {
+    match metrics::read(&map).await.sorted_len(&key) {
+        Some(len) => len.to_string().into_response(),
+        None => wrong_kind(ValueKind::SortedSet),
+    }
+}
+
+/// Get the score of a member.
+pub async fn score(
+    Path(MemberPath { key, member }): Path<MemberPath>,
+    Extension(map): Extension<MapState>,
+) -> Response {
+    let reader = metrics::read(&map).await;
+    if reader.sorted_len(&key).is_none() {
+        return wrong_kind(ValueKind::SortedSet);
+    }
+
+    match reader.sorted_score(&key, &member.into_bytes()) {
+        Some(score) => score.to_string().into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+/// Get the rank of a member (from `0`, the lowest score). This takes longer for
+/// members with higher ranks (see [`oysters_core::sorted_set`]).
+pub async fn rank(
+    Path(MemberPath { key, member }): Path<MemberPath>,
+    Extension(map): Extension<MapState>,
+) -> Response {
+    let reader = metrics::read(&map).await;
+    if reader.sorted_len(&key).is_none() {
+        return wrong_kind(ValueKind::SortedSet);
+    }
+
+    match reader.sorted_rank(&key, &member.into_bytes()) {
+        Some(rank) => rank.to_string().into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+/// Set the score of a member (the body). Responds with `201 Created` if the
+/// member is new.
+pub async fn add(
+    Path(MemberPath { key, member }): Path<MemberPath>,
+    Extension(map): Extension<MapState>,
+    score: String,
+) -> Response {
+    let Some(score) = score.trim().parse::<f64>().ok().filter(|x| !x.is_nan()) else {
+        return (StatusCode::BAD_REQUEST, "score isn't a number").into_response();
+    };
+
+    match metrics::write(&map)
+        .await
+        .sorted_add(key, member.into_bytes(), score)
+    {
+        Some(true) => StatusCode::CREATED.into_response(),
+        Some(false) => StatusCode::OK.into_response(),
+        None => wrong_kind(ValueKind::SortedSet),
+    }
+}
+
+/// Add to the score of a member (`?by=`, `1` by default). Responds with the new
+/// score.
+pub async fn incr(
+    Path(MemberPath { key, member }): Path<MemberPath>,
+    Extension(map): Extension<MapState>,
+    Query(IncrQuery { by }): Query<IncrQuery>,
+) -> Response {
+    if by.is_nan() {
+        return (StatusCode::BAD_REQUEST, "by isn't a number").into_response();
+    }
+
+    match metrics::write(&map)
+        .await
+        .sorted_incr(key, member.into_bytes(), by)
+    {
+        Some(score) => score.to_string().into_response(),
+        None => wrong_kind(ValueKind::SortedSet),
+    }
+}
+
+/// Remove a member.
+pub async fn remove(
+    Path(MemberPath { key, member }): Path<MemberPath>,
+    Extension(map): Extension<MapState>,
+) -> Response {
+    match metrics::write(&map)
+        .await
+        .sorted_remove(&key, &member.into_bytes())
+    {
+        Some(true) => StatusCode::OK.into_response(),
+        Some(false) => StatusCode::NOT_FOUND.into_response(),
+        None => wrong_kind(ValueKind::SortedSet),
+    }
+}
@@ -0,0 +1,184 @@
+//! TLS (and mutual TLS) for the HTTP API.
+//!
+//! Certificates are read again when the server receives `SIGHUP`, so they can be
+//! renewed without restarting the server.
+use crate::config::TlsConfig;
+use axum::Router;
+use axum_server::tls_rustls::RustlsConfig;
+use rustls::ServerConfig;
+use rustls::crypto::ring;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+
+use std::io::{Error, Result};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tracing::{info, warn};
+
+/// Build the rustls config described by `tls`.
+pub fn server_config(tls: &TlsConfig) -> Result<ServerConfig> {
+    let provider = Arc::new(ring::default_provider());
+
+    let certs = CertificateDer::pem_file_iter(&tls.cert)
+        .map_err(Error::other)?
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .map_err(Error::other)?;
+    let key = PrivateKeyDer::from_pem_file(&tls.key).map_err(Error::other)?;
+
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(Error::other)?;
+
+    let builder = match tls.client_ca {
+        Some(ref path) => {
+            let mut roots = rustls::RootCertStore::empty();
+            for cert in CertificateDer::pem_file_iter(path).map_err(Error::other)? {
+                roots
+                    .add(cert.map_err(Error::other)?)
+                    .map_err(Error::other)?;
+            }
+
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                .build()
+                .map_err(Error::other)?;
+
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    let mut config = builder.with_single_cert(certs, key).map_err(Error::other)?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+    Ok(config)
+}
+
+/// Serve the HTTP API (`app`) over TLS on the given `listener`.
+pub async fn serve(listener: TcpListener, app: Router, tls: TlsConfig) {
+    let config = RustlsConfig::from_config(Arc::new(
+        server_config(&tls).expect("failed to read tls certificates"),
+    ));
+
+    #[cfg(unix)]
+    tokio::spawn(reload_on_hangup(tls, config.clone()));
+
+    axum_server::from_tcp_rustls(listener.into_std().unwrap(), config)
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+        .await
+        .unwrap();
+}
+
+/// Read the certificates again every time the server receives `SIGHUP`.
+#[cfg(unix)]
+async fn reload_on_hangup(tls: TlsConfig, config: RustlsConfig) {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let mut hangup = signal(SignalKind::hangup()).expect("failed to listen for SIGHUP");
+    while hangup.recv().await.is_some() {
+        match server_config(&tls) {
+            Ok(c) => {
+                config.reload_from_config(Arc::new(c));
+                info!("reloaded tls certificates from {}", tls.cert);
+            }
+            Err(e) => warn!("failed to reload tls certificates: {e}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::default_routes;
+    use crate::namespaces::Namespaces;
+    use oysters_client::{Client, ClientOptions};
+    use oysters_core::Oyster;
+    use rcgen::{
+        BasicConstraints, Certificate, CertificateParams, ExtendedKeyUsagePurpose, IsCa, KeyPair,
+    };
+    use tokio::sync::RwLock;
+
+    /// Create a certificate (and its key) signed by `ca`.
+    fn issue(
+        name: &str,
+        usage: ExtendedKeyUsagePurpose,
+        ca: &Certificate,
+        ca_key: &KeyPair,
+    ) -> (String, String) {
+        let key = KeyPair::generate().unwrap();
+        let mut params = CertificateParams::new(vec![name.to_string()]).unwrap();
+        params.extended_key_usages = vec![usage];
+        let cert = params.signed_by(&key, ca, ca_key).unwrap();
+        (cert.pem(), key.serialize_pem())
+    }
+
+    #[tokio::test]
+    async fn mutual_tls() {
+        let dir = std::env::temp_dir().join(format!("oysters-tls-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let ca_key = KeyPair::generate().unwrap();
+        let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca = params.self_signed(&ca_key).unwrap();
+
+        let (server_cert, server_key) = issue(
+            "localhost",
+            ExtendedKeyUsagePurpose::ServerAuth,
+            &ca,
+            &ca_key,
+        );
+        let (client_cert, client_key) =
+            issue("client", ExtendedKeyUsagePurpose::ClientAuth, &ca, &ca_key);
+
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+        std::fs::write(path("ca.pem"), ca.pem()).unwrap();
+        std::fs::write(path("cert.pem"), server_cert).unwrap();
+        std::fs::write(path("key.pem"), server_key).unwrap();
+
+        let tls = TlsConfig {
+            cert: path("cert.pem"),
+            key: path("key.pem"),
+            client_ca: Some(path("ca.pem")),
+        };
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let map = Arc::new(RwLock::new(Oyster::new()));
+        let namespaces = Arc::new(Namespaces::new(map, Default::default()));
+        tokio::spawn(serve(
+            listener,
+            crate::router(&default_routes(), namespaces, None, None),
+            tls,
+        ));
+
+        let url = format!("https://localhost:{port}");
+        let client = Client::with_options(
+            url.clone(),
+            ClientOptions {
+                ca_cert: Some(ca.pem().into_bytes()),
+                client_cert: Some(format!("{client_cert}{client_key}").into_bytes()),
+                token: None,
+            },
+        )
+        .unwrap();
+
+        assert!(client.insert("a", "b").await);
+        assert_eq!(client.get("a").await, "b");
+
+        // clients without a certificate are turned away
+        let anonymous = Client::with_options(
+            url,
+            ClientOptions {
+                ca_cert: Some(ca.pem().into_bytes()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let anonymous = tokio::spawn(async move { anonymous.get("a").await });
+        assert!(anonymous.await.is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
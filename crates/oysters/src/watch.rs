@@ -0,0 +1,123 @@
+//! Keyspace notifications (`GET /_watch`).
+//!
+//! Watchers are sent every [`Event`] in a map which matches their pattern (see
+//! [`Event::matches`]), as JSON. Events are sent as Server-Sent Events, unless
+//! the request asks to be upgraded to a WebSocket (then every event is a text
+//! message).
+//!
+//! Watchers which fall too far behind (see [`CAPACITY`](oysters_core::events::CAPACITY))
+//! miss events, and are told how many they missed instead (a `lagged` event over
+//! SSE, or a `{"lagged":n}` message over a WebSocket).
+use crate::MapState;
+use crate::metrics;
+use axum::Extension;
+use axum::extract::Query;
+use axum::extract::ws::{
+    Message, WebSocket, WebSocketUpgrade, rejection::WebSocketUpgradeRejection,
+};
+use axum::response::sse::{self, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use oysters_core::events::Event;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio_stream::StreamExt;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use tracing::warn;
+
+#[derive(Deserialize)]
+pub struct WatchQuery {
+    /// The pattern keys have to match (every key by default).
+    #[serde(default = "default_pattern")]
+    pub pattern: String,
+}
+
+fn default_pattern() -> String {
+    "*".to_string()
+}
+
+/// Send everything `filter` keeps from `receiver` to the client, over a WebSocket
+/// if the request asks for one (or as Server-Sent Events if it doesn't).
+///
+/// # Arguments
+/// * `ws` - the WebSocket upgrade (if the request asked for one)
+/// * `receiver` - where the items come from
+/// * `filter` - which items to send (and what to send for them)
+pub fn stream<T, U, F>(
+    ws: Result<WebSocketUpgrade, WebSocketUpgradeRejection>,
+    receiver: broadcast::Receiver<T>,
+    filter: F,
+) -> Response
+where
+    T: Clone + Send + 'static,
+    U: Serialize + Send + 'static,
+    F: Fn(T) -> Option<U> + Send + Sync + 'static,
+{
+    if let Ok(ws) = ws {
+        return ws
+            .on_upgrade(move |socket| forward(socket, receiver, filter))
+            .into_response();
+    }
+
+    let events = BroadcastStream::new(receiver).filter_map(move |item| match item {
+        Ok(item) => filter(item).map(|x| sse::Event::default().json_data(x)),
+        Err(BroadcastStreamRecvError::Lagged(n)) => {
+            warn!("watcher fell behind and missed {n} events");
+            Some(Ok(sse::Event::default()
+                .event("lagged")
+                .data(n.to_string())))
+        }
+    });
+
+    Sse::new(events)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+/// [`stream`] over a WebSocket. The socket is closed once the client closes it,
+/// or once nothing else can be received.
+async fn forward<T, U, F>(mut socket: WebSocket, mut receiver: broadcast::Receiver<T>, filter: F)
+where
+    T: Clone + Send + 'static,
+    U: Serialize + Send + 'static,
+    F: Fn(T) -> Option<U> + Send + Sync + 'static,
+{
+    loop {
+        tokio::select! {
+            item = receiver.recv() => {
+                let text = match item {
+                    Ok(item) => match filter(item) {
+                        Some(x) => serde_json::to_string(&x).unwrap(),
+                        None => continue,
+                    },
+                    Err(RecvError::Lagged(n)) => {
+                        warn!("watcher fell behind and missed {n} events");
+                        format!("{{\"lagged\":{n}}}")
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+
+                if socket.send(Message::Text(text.into())).await.is_err() {
+                    break;
+                }
+            }
+            // clients aren't expected to send anything, but they can close the socket
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_)) | Err(_)) | None => break,
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Watch the keys matching a pattern for changes.
+pub async fn watch(
+    ws: Result<WebSocketUpgrade, WebSocketUpgradeRejection>,
+    Extension(map): Extension<MapState>,
+    Query(WatchQuery { pattern }): Query<WatchQuery>,
+) -> Response {
+    let receiver = metrics::read(&map).await.subscribe();
+    stream(ws, receiver, move |event: Event| {
+        event.matches(&pattern).then_some(event)
+    })
+}
@@ -1,5 +1,7 @@
 use clap::{Command, arg};
-use oysters_client::Client;
+use futures_util::StreamExt;
+use oysters_client::{Client, ClientOptions, Comparison, End};
+use serde_json::Value;
 
 fn cli() -> Command {
     Command::new("oysters-cli")
@@ -7,6 +9,27 @@ fn cli() -> Command {
         .subcommand_required(true)
         .arg_required_else_help(true)
         .allow_external_subcommands(true)
+        .arg(
+            arg!(-u --url <URL> "The server to connect to (`unix://` URLs connect to a Unix socket)")
+                .global(true)
+                .default_value("http://localhost:5072"),
+        )
+        .arg(
+            arg!(-t --token <TOKEN> "The token to authenticate with")
+                .env("OYSTERS_TOKEN")
+                .hide_env_values(true)
+                .global(true),
+        )
+        .arg(
+            arg!(-n --namespace <NAME> "The namespace to read and write keys in")
+                .env("OYSTERS_NAMESPACE")
+                .global(true),
+        )
+        .arg(arg!(--"ca-cert" <PATH> "A CA certificate (PEM) to trust").global(true))
+        .arg(
+            arg!(--"client-cert" <PATH> "A client certificate and its private key (PEM)")
+                .global(true),
+        )
         .subcommand(Command::new("dump").about("Dump the current map to an SQLite file"))
         .subcommand(Command::new("scan").about("Scan the current map for outdated items"))
         .subcommand(
@@ -45,12 +68,382 @@ fn cli() -> Command {
                 .about("Filter by the given pattern (and return ONLY keys)")
                 .arg(arg!(<PATTERN> "The pattern to filter by")),
         )
+        .subcommand(
+            Command::new("info")
+                .about("Show information about the server")
+                .arg(
+                    arg!(--top <COUNT> "How many key prefixes to show for each namespace")
+                        .value_parser(clap::value_parser!(usize))
+                        .default_value("10"),
+                ),
+        )
+        .subcommand(Command::new("namespaces").about("List every namespace"))
+        .subcommand(
+            Command::new("create_namespace")
+                .about("Create a namespace")
+                .arg(arg!(<NAME> "The name of the namespace"))
+                .arg(
+                    arg!(--capacity <KEYS> "The maximum number of keys")
+                        .value_parser(clap::value_parser!(usize)),
+                )
+                .arg(
+                    arg!(--"max-age" <MS> "How long a key can go unused before a scan removes it (0 keeps keys forever)")
+                        .value_parser(clap::value_parser!(usize)),
+                ),
+        )
+        .subcommand(
+            Command::new("drop_namespace")
+                .about("Drop a namespace (and everything in it)")
+                .arg(arg!(<NAME> "The name of the namespace")),
+        )
+        .subcommand(
+            Command::new("push")
+                .about("Push a value to the back of a list")
+                .arg(arg!(<KEY> "The key of the list"))
+                .arg(arg!(<VALUE> "The value to push"))
+                .arg(arg!(--front "Push to the front of the list instead")),
+        )
+        .subcommand(
+            Command::new("pop")
+                .about("Pop a value from the front of a list")
+                .arg(arg!(<KEY> "The key of the list"))
+                .arg(arg!(--back "Pop from the back of the list instead"))
+                .arg(
+                    arg!(--timeout <MS> "How long to wait for a value if the list is empty (at most 5 minutes)")
+                        .value_parser(clap::value_parser!(u64))
+                        .default_value("0"),
+                ),
+        )
+        .subcommand(
+            Command::new("range")
+                .about("Get the values of a list between two positions")
+                .arg(arg!(<KEY> "The key of the list"))
+                .arg(
+                    arg!([START] "The position of the first value")
+                        .value_parser(clap::value_parser!(isize))
+                        .allow_negative_numbers(true)
+                        .default_value("0"),
+                )
+                .arg(
+                    arg!([STOP] "The position of the last value (negative positions count from the end)")
+                        .value_parser(clap::value_parser!(isize))
+                        .allow_negative_numbers(true)
+                        .default_value("-1"),
+                ),
+        )
+        .subcommand(
+            Command::new("trim")
+                .about("Remove every value of a list which isn't between two positions")
+                .arg(arg!(<KEY> "The key of the list"))
+                .arg(
+                    arg!(<START> "The position of the first value to keep")
+                        .value_parser(clap::value_parser!(isize))
+                        .allow_negative_numbers(true),
+                )
+                .arg(
+                    arg!(<STOP> "The position of the last value to keep")
+                        .value_parser(clap::value_parser!(isize))
+                        .allow_negative_numbers(true),
+                ),
+        )
+        .subcommand(
+            Command::new("list_len")
+                .about("Get the length of a list")
+                .arg(arg!(<KEY> "The key of the list")),
+        )
+        .subcommand(
+            Command::new("hash_get")
+                .about("Get the value of a field of a hash")
+                .arg(arg!(<KEY> "The key of the hash"))
+                .arg(arg!(<FIELD> "The field to get")),
+        )
+        .subcommand(
+            Command::new("hash_get_all")
+                .about("Get every field of a hash")
+                .arg(arg!(<KEY> "The key of the hash")),
+        )
+        .subcommand(
+            Command::new("hash_set")
+                .about("Set the value of a field of a hash")
+                .arg(arg!(<KEY> "The key of the hash"))
+                .arg(arg!(<FIELD> "The field to set"))
+                .arg(arg!(<VALUE> "The value to set")),
+        )
+        .subcommand(
+            Command::new("hash_remove")
+                .about("Remove a field of a hash")
+                .arg(arg!(<KEY> "The key of the hash"))
+                .arg(arg!(<FIELD> "The field to remove")),
+        )
+        .subcommand(
+            Command::new("hash_incr")
+                .about("Add to the value of a field of a hash")
+                .arg(arg!(<KEY> "The key of the hash"))
+                .arg(arg!(<FIELD> "The field to add to"))
+                .arg(
+                    arg!([BY] "What to add")
+                        .value_parser(clap::value_parser!(i64))
+                        .allow_negative_numbers(true)
+                        .default_value("1"),
+                ),
+        )
+        .subcommand(
+            Command::new("hash_exists")
+                .about("Check if a field of a hash exists")
+                .arg(arg!(<KEY> "The key of the hash"))
+                .arg(arg!(<FIELD> "The field to check")),
+        )
+        .subcommand(
+            Command::new("set_add")
+                .about("Add a member to a set")
+                .arg(arg!(<KEY> "The key of the set"))
+                .arg(arg!(<MEMBER> "The member to add")),
+        )
+        .subcommand(
+            Command::new("set_remove")
+                .about("Remove a member from a set")
+                .arg(arg!(<KEY> "The key of the set"))
+                .arg(arg!(<MEMBER> "The member to remove")),
+        )
+        .subcommand(
+            Command::new("set_members")
+                .about("Get every member of a set")
+                .arg(arg!(<KEY> "The key of the set")),
+        )
+        .subcommand(
+            Command::new("set_is_member")
+                .about("Check if a value is a member of a set")
+                .arg(arg!(<KEY> "The key of the set"))
+                .arg(arg!(<MEMBER> "The value to check")),
+        )
+        .subcommand(
+            Command::new("set_len")
+                .about("Get the number of members in a set")
+                .arg(arg!(<KEY> "The key of the set")),
+        )
+        .subcommand(
+            Command::new("set_union")
+                .about("Get every value which is a member of any of the given sets")
+                .arg(arg!(<KEYS> ... "The keys of the sets")),
+        )
+        .subcommand(
+            Command::new("set_intersect")
+                .about("Get every value which is a member of all of the given sets")
+                .arg(arg!(<KEYS> ... "The keys of the sets")),
+        )
+        .subcommand(
+            Command::new("sorted_add")
+                .about("Set the score of a member of a sorted set")
+                .arg(arg!(<KEY> "The key of the sorted set"))
+                .arg(arg!(<MEMBER> "The member to set the score of"))
+                .arg(
+                    arg!(<SCORE> "The score of the member")
+                        .value_parser(clap::value_parser!(f64))
+                        .allow_negative_numbers(true),
+                ),
+        )
+        .subcommand(
+            Command::new("sorted_remove")
+                .about("Remove a member from a sorted set")
+                .arg(arg!(<KEY> "The key of the sorted set"))
+                .arg(arg!(<MEMBER> "The member to remove")),
+        )
+        .subcommand(
+            Command::new("sorted_incr")
+                .about("Add to the score of a member of a sorted set")
+                .arg(arg!(<KEY> "The key of the sorted set"))
+                .arg(arg!(<MEMBER> "The member to add to"))
+                .arg(
+                    arg!([BY] "What to add")
+                        .value_parser(clap::value_parser!(f64))
+                        .allow_negative_numbers(true)
+                        .default_value("1"),
+                ),
+        )
+        .subcommand(
+            Command::new("sorted_score")
+                .about("Get the score of a member of a sorted set")
+                .arg(arg!(<KEY> "The key of the sorted set"))
+                .arg(arg!(<MEMBER> "The member to get the score of")),
+        )
+        .subcommand(
+            Command::new("sorted_rank")
+                .about("Get the rank of a member of a sorted set (from 0, the lowest score)")
+                .arg(arg!(<KEY> "The key of the sorted set"))
+                .arg(arg!(<MEMBER> "The member to get the rank of")),
+        )
+        .subcommand(
+            Command::new("sorted_range")
+                .about("Get the members of a sorted set between two ranks")
+                .arg(arg!(<KEY> "The key of the sorted set"))
+                .arg(
+                    arg!([START] "The rank of the first member")
+                        .value_parser(clap::value_parser!(isize))
+                        .allow_negative_numbers(true)
+                        .default_value("0"),
+                )
+                .arg(
+                    arg!([STOP] "The rank of the last member (negative ranks count from the end)")
+                        .value_parser(clap::value_parser!(isize))
+                        .allow_negative_numbers(true)
+                        .default_value("-1"),
+                ),
+        )
+        .subcommand(
+            Command::new("sorted_range_by_score")
+                .about("Get the members of a sorted set with a score between two scores")
+                .arg(arg!(<KEY> "The key of the sorted set"))
+                .arg(
+                    arg!([MIN] "The lowest score")
+                        .value_parser(clap::value_parser!(f64))
+                        .allow_negative_numbers(true)
+                        .default_value("-inf"),
+                )
+                .arg(
+                    arg!([MAX] "The highest score")
+                        .value_parser(clap::value_parser!(f64))
+                        .allow_negative_numbers(true)
+                        .default_value("inf"),
+                ),
+        )
+        .subcommand(
+            Command::new("sorted_len")
+                .about("Get the number of members in a sorted set")
+                .arg(arg!(<KEY> "The key of the sorted set")),
+        )
+        .subcommand(
+            Command::new("json_get")
+                .about("Get the value at a path of a JSON document")
+                .arg(arg!(<KEY> "The key of the document"))
+                .arg(arg!([PATH] "The path of the value (e.g. $.user.name)").default_value("$")),
+        )
+        .subcommand(
+            Command::new("json_set")
+                .about("Set the value at a path of a JSON document")
+                .arg(arg!(<KEY> "The key of the document"))
+                .arg(arg!(<PATH> "The path of the value (e.g. $.user.name)"))
+                .arg(arg!(<VALUE> "The value (as JSON, or a string if it isn't valid JSON)")),
+        )
+        .subcommand(
+            Command::new("json_remove")
+                .about("Remove the value at a path of a JSON document")
+                .arg(arg!(<KEY> "The key of the document"))
+                .arg(arg!([PATH] "The path of the value ($ removes the whole document)").default_value("$")),
+        )
+        .subcommand(
+            Command::new("json_incr")
+                .about("Add to the number at a path of a JSON document")
+                .arg(arg!(<KEY> "The key of the document"))
+                .arg(arg!(<PATH> "The path of the number"))
+                .arg(
+                    arg!([BY] "What to add")
+                        .value_parser(clap::value_parser!(f64))
+                        .allow_negative_numbers(true)
+                        .default_value("1"),
+                ),
+        )
+        .subcommand(
+            Command::new("json_filter_keys")
+                .about("Get the keys of the JSON documents where the value at a path compares to a value")
+                .arg(arg!(<PATTERN> "The pattern keys have to match"))
+                .arg(arg!(<PATH> "The path of the value to compare"))
+                .arg(
+                    arg!(<OP> "How to compare it")
+                        .value_parser(["eq", "ne", "lt", "le", "gt", "ge"]),
+                )
+                .arg(arg!(<VALUE> "What to compare it to (as JSON, or a string if it isn't valid JSON)")),
+        )
+        .subcommand(
+            Command::new("watch")
+                .about("Print every change to the keys matching the given pattern")
+                .arg(arg!([PATTERN] "The pattern to watch").default_value("*")),
+        )
+        .subcommand(
+            Command::new("publish")
+                .about("Publish a message to a channel")
+                .arg(arg!(<CHANNEL> "The channel to publish to"))
+                .arg(arg!(<MESSAGE> "The message to publish")),
+        )
+        .subcommand(
+            Command::new("subscribe")
+                .about("Print every message published to a channel")
+                .arg(arg!(<CHANNEL> "The channel to subscribe to")),
+        )
+}
+
+/// Parse a JSON value given as an argument (arguments which aren't valid JSON
+/// are strings).
+fn json_arg(arg: &str) -> Value {
+    serde_json::from_str(arg).unwrap_or_else(|_| Value::String(arg.to_string()))
+}
+
+/// Print a JSON value as an indented list.
+fn print_value(value: &Value, indent: usize) {
+    let pad = "  ".repeat(indent);
+    match value {
+        Value::Object(fields) => {
+            for (name, field) in fields {
+                match field {
+                    Value::Object(_) | Value::Array(_) => {
+                        println!("{pad}{name}:");
+                        print_value(field, indent + 1);
+                    }
+                    _ => println!("{pad}{name}: {}", scalar(field)),
+                }
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                match item {
+                    Value::Object(_) => {
+                        println!("{pad}-");
+                        print_value(item, indent + 1);
+                    }
+                    // e.g. prefixes and their counts
+                    Value::Array(parts) => println!(
+                        "{pad}- {}",
+                        parts.iter().map(scalar).collect::<Vec<_>>().join(" ")
+                    ),
+                    _ => println!("{pad}- {}", scalar(item)),
+                }
+            }
+        }
+        _ => println!("{pad}{}", scalar(value)),
+    }
+}
+
+/// Format a JSON value which isn't an object or an array.
+fn scalar(value: &Value) -> String {
+    match value {
+        Value::String(x) => x.clone(),
+        Value::Null => "-".to_string(),
+        x => x.to_string(),
+    }
 }
 
 #[tokio::main]
 async fn main() {
     let matches = cli().get_matches();
-    let client = Client::new("http://localhost:5072".to_string());
+    let read = |name: &str| {
+        matches
+            .get_one::<String>(name)
+            .map(|path| std::fs::read(path).expect("failed to read certificate"))
+    };
+
+    let client = Client::with_options(
+        matches.get_one::<String>("url").unwrap().to_owned(),
+        ClientOptions {
+            ca_cert: read("ca-cert"),
+            client_cert: read("client-cert"),
+            token: matches.get_one::<String>("token").cloned(),
+        },
+    )
+    .expect("invalid certificate or token");
+
+    let client = match matches.get_one::<String>("namespace") {
+        Some(name) => client.namespace(name),
+        None => client,
+    };
 
     match matches.subcommand() {
         Some(("dump", _)) => client.dump().await,
@@ -96,6 +489,371 @@ async fn main() {
                 println!("{k}")
             }
         }
+        Some(("info", sub)) => {
+            print_value(&client.info(*sub.get_one::<usize>("top").unwrap()).await, 0)
+        }
+        Some(("namespaces", _)) => {
+            for name in client.namespaces().await {
+                println!("{name}")
+            }
+        }
+        Some(("create_namespace", sub)) => println!(
+            "{}",
+            client
+                .create_namespace(
+                    sub.get_one::<String>("NAME").unwrap(),
+                    sub.get_one::<usize>("capacity").copied(),
+                    sub.get_one::<usize>("max-age").copied(),
+                )
+                .await
+        ),
+        Some(("drop_namespace", sub)) => println!(
+            "{}",
+            client
+                .drop_namespace(sub.get_one::<String>("NAME").unwrap())
+                .await
+        ),
+        Some(("push", sub)) => println!(
+            "{}",
+            client
+                .push(
+                    sub.get_one::<String>("KEY").unwrap(),
+                    sub.get_one::<String>("VALUE").unwrap(),
+                    if sub.get_flag("front") {
+                        End::Front
+                    } else {
+                        End::Back
+                    }
+                )
+                .await
+                .map_or("-".to_string(), |x| x.to_string())
+        ),
+        Some(("pop", sub)) => println!(
+            "{}",
+            client
+                .pop(
+                    sub.get_one::<String>("KEY").unwrap(),
+                    if sub.get_flag("back") {
+                        End::Back
+                    } else {
+                        End::Front
+                    },
+                    *sub.get_one::<u64>("timeout").unwrap()
+                )
+                .await
+                .unwrap_or_default()
+        ),
+        Some(("range", sub)) => {
+            for value in client
+                .range(
+                    sub.get_one::<String>("KEY").unwrap(),
+                    *sub.get_one::<isize>("START").unwrap(),
+                    *sub.get_one::<isize>("STOP").unwrap(),
+                )
+                .await
+            {
+                println!("{value}")
+            }
+        }
+        Some(("trim", sub)) => println!(
+            "{}",
+            client
+                .trim(
+                    sub.get_one::<String>("KEY").unwrap(),
+                    *sub.get_one::<isize>("START").unwrap(),
+                    *sub.get_one::<isize>("STOP").unwrap(),
+                )
+                .await
+                .map_or("-".to_string(), |x| x.to_string())
+        ),
+        Some(("list_len", sub)) => println!(
+            "{}",
+            client
+                .list_len(sub.get_one::<String>("KEY").unwrap())
+                .await
+                .map_or("-".to_string(), |x| x.to_string())
+        ),
+        Some(("hash_get", sub)) => println!(
+            "{}",
+            client
+                .hash_get(
+                    sub.get_one::<String>("KEY").unwrap(),
+                    sub.get_one::<String>("FIELD").unwrap()
+                )
+                .await
+                .unwrap_or_default()
+        ),
+        Some(("hash_get_all", sub)) => {
+            for (field, value) in client
+                .hash_get_all(sub.get_one::<String>("KEY").unwrap())
+                .await
+            {
+                println!("{field} = {value}")
+            }
+        }
+        Some(("hash_set", sub)) => println!(
+            "{}",
+            client
+                .hash_set(
+                    sub.get_one::<String>("KEY").unwrap(),
+                    sub.get_one::<String>("FIELD").unwrap(),
+                    sub.get_one::<String>("VALUE").unwrap()
+                )
+                .await
+        ),
+        Some(("hash_remove", sub)) => println!(
+            "{}",
+            client
+                .hash_remove(
+                    sub.get_one::<String>("KEY").unwrap(),
+                    sub.get_one::<String>("FIELD").unwrap()
+                )
+                .await
+        ),
+        Some(("hash_incr", sub)) => println!(
+            "{}",
+            client
+                .hash_incr(
+                    sub.get_one::<String>("KEY").unwrap(),
+                    sub.get_one::<String>("FIELD").unwrap(),
+                    *sub.get_one::<i64>("BY").unwrap()
+                )
+                .await
+                .map_or("-".to_string(), |x| x.to_string())
+        ),
+        Some(("hash_exists", sub)) => println!(
+            "{}",
+            client
+                .hash_exists(
+                    sub.get_one::<String>("KEY").unwrap(),
+                    sub.get_one::<String>("FIELD").unwrap()
+                )
+                .await
+        ),
+        Some(("set_add", sub)) => println!(
+            "{}",
+            client
+                .set_add(
+                    sub.get_one::<String>("KEY").unwrap(),
+                    sub.get_one::<String>("MEMBER").unwrap()
+                )
+                .await
+        ),
+        Some(("set_remove", sub)) => println!(
+            "{}",
+            client
+                .set_remove(
+                    sub.get_one::<String>("KEY").unwrap(),
+                    sub.get_one::<String>("MEMBER").unwrap()
+                )
+                .await
+        ),
+        Some(("set_members", sub)) => {
+            for member in client
+                .set_members(sub.get_one::<String>("KEY").unwrap())
+                .await
+            {
+                println!("{member}")
+            }
+        }
+        Some(("set_is_member", sub)) => println!(
+            "{}",
+            client
+                .set_is_member(
+                    sub.get_one::<String>("KEY").unwrap(),
+                    sub.get_one::<String>("MEMBER").unwrap()
+                )
+                .await
+        ),
+        Some(("set_len", sub)) => println!(
+            "{}",
+            client
+                .set_len(sub.get_one::<String>("KEY").unwrap())
+                .await
+                .map_or("-".to_string(), |x| x.to_string())
+        ),
+        Some((operation @ ("set_union" | "set_intersect"), sub)) => {
+            let keys: Vec<&str> = sub
+                .get_many::<String>("KEYS")
+                .unwrap()
+                .map(String::as_str)
+                .collect();
+            let members = if operation == "set_union" {
+                client.set_union(&keys).await
+            } else {
+                client.set_intersect(&keys).await
+            };
+
+            for member in members {
+                println!("{member}")
+            }
+        }
+        Some(("sorted_add", sub)) => println!(
+            "{}",
+            client
+                .sorted_add(
+                    sub.get_one::<String>("KEY").unwrap(),
+                    sub.get_one::<String>("MEMBER").unwrap(),
+                    *sub.get_one::<f64>("SCORE").unwrap()
+                )
+                .await
+        ),
+        Some(("sorted_remove", sub)) => println!(
+            "{}",
+            client
+                .sorted_remove(
+                    sub.get_one::<String>("KEY").unwrap(),
+                    sub.get_one::<String>("MEMBER").unwrap()
+                )
+                .await
+        ),
+        Some(("sorted_incr", sub)) => println!(
+            "{}",
+            client
+                .sorted_incr(
+                    sub.get_one::<String>("KEY").unwrap(),
+                    sub.get_one::<String>("MEMBER").unwrap(),
+                    *sub.get_one::<f64>("BY").unwrap()
+                )
+                .await
+                .map_or("-".to_string(), |x| x.to_string())
+        ),
+        Some(("sorted_score", sub)) => println!(
+            "{}",
+            client
+                .sorted_score(
+                    sub.get_one::<String>("KEY").unwrap(),
+                    sub.get_one::<String>("MEMBER").unwrap()
+                )
+                .await
+                .map_or("-".to_string(), |x| x.to_string())
+        ),
+        Some(("sorted_rank", sub)) => println!(
+            "{}",
+            client
+                .sorted_rank(
+                    sub.get_one::<String>("KEY").unwrap(),
+                    sub.get_one::<String>("MEMBER").unwrap()
+                )
+                .await
+                .map_or("-".to_string(), |x| x.to_string())
+        ),
+        Some(("sorted_range", sub)) => {
+            for (member, score) in client
+                .sorted_range(
+                    sub.get_one::<String>("KEY").unwrap(),
+                    *sub.get_one::<isize>("START").unwrap(),
+                    *sub.get_one::<isize>("STOP").unwrap(),
+                )
+                .await
+            {
+                println!("{member} = {score}")
+            }
+        }
+        Some(("sorted_range_by_score", sub)) => {
+            for (member, score) in client
+                .sorted_range_by_score(
+                    sub.get_one::<String>("KEY").unwrap(),
+                    *sub.get_one::<f64>("MIN").unwrap(),
+                    *sub.get_one::<f64>("MAX").unwrap(),
+                )
+                .await
+            {
+                println!("{member} = {score}")
+            }
+        }
+        Some(("sorted_len", sub)) => println!(
+            "{}",
+            client
+                .sorted_len(sub.get_one::<String>("KEY").unwrap())
+                .await
+                .map_or("-".to_string(), |x| x.to_string())
+        ),
+        Some(("json_get", sub)) => println!(
+            "{}",
+            client
+                .json_get::<Value>(
+                    sub.get_one::<String>("KEY").unwrap(),
+                    sub.get_one::<String>("PATH").unwrap()
+                )
+                .await
+                .map_or("-".to_string(), |x| serde_json::to_string_pretty(&x)
+                    .unwrap())
+        ),
+        Some(("json_set", sub)) => println!(
+            "{}",
+            client
+                .json_set(
+                    sub.get_one::<String>("KEY").unwrap(),
+                    sub.get_one::<String>("PATH").unwrap(),
+                    &json_arg(sub.get_one::<String>("VALUE").unwrap())
+                )
+                .await
+        ),
+        Some(("json_remove", sub)) => println!(
+            "{}",
+            client
+                .json_remove(
+                    sub.get_one::<String>("KEY").unwrap(),
+                    sub.get_one::<String>("PATH").unwrap()
+                )
+                .await
+        ),
+        Some(("json_incr", sub)) => println!(
+            "{}",
+            client
+                .json_incr(
+                    sub.get_one::<String>("KEY").unwrap(),
+                    sub.get_one::<String>("PATH").unwrap(),
+                    *sub.get_one::<f64>("BY").unwrap()
+                )
+                .await
+                .map_or("-".to_string(), |x| x.to_string())
+        ),
+        Some(("json_filter_keys", sub)) => {
+            let op: Comparison =
+                serde_json::from_value(json_arg(sub.get_one::<String>("OP").unwrap())).unwrap();
+            for key in client
+                .json_filter_keys(
+                    sub.get_one::<String>("PATTERN").unwrap(),
+                    sub.get_one::<String>("PATH").unwrap(),
+                    op,
+                    &json_arg(sub.get_one::<String>("VALUE").unwrap()),
+                )
+                .await
+            {
+                println!("{key}")
+            }
+        }
+        Some(("watch", sub)) => {
+            let mut events = client
+                .watch(sub.get_one::<String>("PATTERN").unwrap())
+                .await;
+            while let Some(event) = events.next().await {
+                println!(
+                    "{} {}",
+                    format!("{:?}", event.kind).to_lowercase(),
+                    event.key
+                )
+            }
+        }
+        Some(("publish", sub)) => println!(
+            "{}",
+            client
+                .publish(
+                    sub.get_one::<String>("CHANNEL").unwrap(),
+                    sub.get_one::<String>("MESSAGE").unwrap()
+                )
+                .await
+        ),
+        Some(("subscribe", sub)) => {
+            let mut messages = client
+                .subscribe(sub.get_one::<String>("CHANNEL").unwrap())
+                .await;
+            while let Some(message) = messages.next().await {
+                println!("{message}")
+            }
+        }
         _ => unreachable!(),
     }
 }
@@ -1,17 +1,159 @@
+use futures_util::stream::{self, BoxStream, StreamExt};
+use oysters_core::events::Event;
+pub use oysters_core::json::Comparison;
+pub use oysters_core::list::End;
 use oysters_core::pearl::ResourceDescriptor;
-use reqwest::{self, StatusCode};
+use reqwest::{self, StatusCode, header};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::collections::BTreeMap;
+use std::io::{Error, ErrorKind};
+
+mod typed;
+pub use typed::{Encoding, Typed};
+
+/// The generated gRPC client and messages (see `oysters_core/proto/oysters.proto`).
+#[cfg(feature = "grpc")]
+pub mod grpc {
+    pub use oysters_core::grpc::oysters_client::OystersClient;
+    pub use oysters_core::grpc::*;
+
+    /// Connect to the gRPC API at the given `url` (e.g. `http://localhost:50051`).
+    pub async fn connect(
+        url: String,
+    ) -> Result<OystersClient<tonic::transport::Channel>, tonic::transport::Error> {
+        OystersClient::connect(url).await
+    }
+}
+
+/// Options for a [`Client`].
+#[derive(Clone, Debug, Default)]
+pub struct ClientOptions {
+    /// A CA certificate (PEM) to trust in addition to the usual roots (e.g. for
+    /// servers using a self-signed certificate).
+    pub ca_cert: Option<Vec<u8>>,
+    /// A client certificate followed by its private key (PEM), for servers which
+    /// require mutual TLS.
+    pub client_cert: Option<Vec<u8>>,
+    /// The token sent with every request (`Authorization: Bearer {token}`).
+    pub token: Option<String>,
+}
+
+/// Read the data of every Server-Sent Event in a `response`. The stream ends when
+/// the response does, or when the server says events were skipped (a `lagged`
+/// event), since whatever is reading them can't be up to date anymore.
+fn events(response: reqwest::Response) -> BoxStream<'static, String> {
+    stream::unfold(
+        (response.bytes_stream(), Vec::new()),
+        |(mut body, mut buffer)| async move {
+            loop {
+                // events end with an empty line
+                if let Some(end) = buffer.windows(2).position(|x| x == b"\n\n") {
+                    let block: Vec<u8> = buffer.drain(..end + 2).collect();
+                    let mut data = Vec::new();
+
+                    for line in String::from_utf8_lossy(&block).lines() {
+                        if line.strip_prefix("event:").map(str::trim) == Some("lagged") {
+                            return None;
+                        } else if let Some(x) = line.strip_prefix("data:") {
+                            data.push(x.strip_prefix(' ').unwrap_or(x).to_string());
+                        }
+                    }
+
+                    // events without data are just keeping the connection alive
+                    if !data.is_empty() {
+                        return Some((data.join("\n"), (body, buffer)));
+                    }
+
+                    continue;
+                }
+
+                match body.next().await {
+                    Some(Ok(chunk)) => buffer.extend_from_slice(&chunk),
+                    _ => return None,
+                }
+            }
+        },
+    )
+    .boxed()
+}
+
+/// Read the members of a sorted set (and their scores) from a `response`.
+async fn scored(response: reqwest::Response) -> Vec<(String, f64)> {
+    let members: Vec<serde_json::Value> = response.json().await.unwrap_or_default();
+    members
+        .into_iter()
+        .filter_map(|x| Some((x["member"].as_str()?.to_string(), x["score"].as_f64()?)))
+        .collect()
+}
 
 #[derive(Clone)]
 pub struct Client {
     pub url: String,
     client: reqwest::Client,
+    namespace: Option<String>,
 }
 
 impl Client {
+    /// Create a new [`Client`] for the server at the given `url`.
+    ///
+    /// `unix://` URLs (e.g. `unix:///run/oysters.sock`) connect to the server's
+    /// Unix socket.
     pub fn new(url: String) -> Self {
-        Self {
+        Self::with_options(url, ClientOptions::default()).unwrap()
+    }
+
+    /// Create a new [`Client`] with the given `options` (see [`Client::new`]).
+    ///
+    /// # Returns
+    /// An error if a certificate in `options` is invalid, or an
+    /// [`ErrorKind::InvalidInput`] error if the token can't be sent as a header.
+    pub fn with_options(mut url: String, options: ClientOptions) -> std::io::Result<Self> {
+        let mut builder = reqwest::Client::builder();
+
+        #[cfg(unix)]
+        if let Some(path) = url.strip_prefix("unix://") {
+            builder = builder.unix_socket(path);
+            url = "http://localhost".to_string();
+        }
+
+        if options.ca_cert.is_some() || options.client_cert.is_some() {
+            builder = builder.use_rustls_tls();
+        }
+
+        if let Some(ref pem) = options.ca_cert {
+            builder = builder
+                .add_root_certificate(reqwest::Certificate::from_pem(pem).map_err(Error::other)?);
+        }
+
+        if let Some(ref pem) = options.client_cert {
+            builder = builder.identity(reqwest::Identity::from_pem(pem).map_err(Error::other)?);
+        }
+
+        if let Some(ref token) = options.token {
+            let mut value = header::HeaderValue::from_str(&format!("Bearer {token}"))
+                .map_err(|_| Error::new(ErrorKind::InvalidInput, "invalid token"))?;
+            value.set_sensitive(true);
+
+            builder = builder.default_headers(header::HeaderMap::from_iter([(
+                header::AUTHORIZATION,
+                value,
+            )]));
+        }
+
+        Ok(Self {
             url,
-            client: reqwest::Client::new(),
+            client: builder.build().map_err(Error::other)?,
+            namespace: None,
+        })
+    }
+
+    /// Get a [`Client`] which reads and writes keys in the namespace with the
+    /// given `name` (see [`Client::create_namespace`]).
+    pub fn namespace(&self, name: &str) -> Self {
+        Self {
+            namespace: Some(name.to_string()),
+            ..self.clone()
         }
     }
 
@@ -19,6 +161,15 @@ impl Client {
         format!("{}/{path}", self.url)
     }
 
+    /// Build the URL of a route which reads or writes keys (in the client's
+    /// namespace, if it has one).
+    fn build_data_url(&self, path: &str) -> String {
+        match self.namespace {
+            Some(ref name) => format!("{}/ns/{name}/{path}", self.url),
+            None => self.build_url(path),
+        }
+    }
+
     pub async fn dump(&self) {
         let req = self.client.post(self.build_url("_dump"));
         req.send().await.unwrap();
@@ -29,38 +180,58 @@ impl Client {
         req.send().await.unwrap();
     }
 
+    /// Get the value of a key (an empty string if it doesn't exist). See
+    /// [`Client::typed`] to read values as Rust types instead.
     pub async fn get(&self, key: &str) -> String {
-        let req = self.client.get(self.build_url(key));
+        let req = self.client.get(self.build_data_url(key));
         req.send().await.unwrap().text().await.unwrap()
     }
 
     pub async fn insert(&self, key: &str, value: &str) -> bool {
         let req = self
             .client
-            .post(self.build_url(key))
+            .post(self.build_data_url(key))
             .body(value.to_string());
         req.send().await.unwrap().status() == StatusCode::OK
     }
 
+    pub async fn get_bytes(&self, key: &str) -> Vec<u8> {
+        let req = self.client.get(self.build_data_url(key));
+        req.send().await.unwrap().bytes().await.unwrap().to_vec()
+    }
+
+    pub async fn insert_bytes(&self, key: &str, value: &[u8]) -> bool {
+        let req = self
+            .client
+            .post(self.build_data_url(key))
+            .header(header::CONTENT_TYPE, "application/octet-stream")
+            .body(value.to_vec());
+        req.send().await.unwrap().status() == StatusCode::OK
+    }
+
     pub async fn incr(&self, key: &str) -> bool {
-        let req = self.client.post(self.build_url(&format!("_incr/{key}")));
+        let req = self
+            .client
+            .post(self.build_data_url(&format!("_incr/{key}")));
         req.send().await.unwrap().status() == StatusCode::OK
     }
 
     pub async fn decr(&self, key: &str) -> bool {
-        let req = self.client.post(self.build_url(&format!("_decr/{key}")));
+        let req = self
+            .client
+            .post(self.build_data_url(&format!("_decr/{key}")));
         req.send().await.unwrap().status() == StatusCode::OK
     }
 
     pub async fn remove(&self, key: &str) -> String {
-        let req = self.client.delete(self.build_url(key));
+        let req = self.client.delete(self.build_data_url(key));
         req.send().await.unwrap().text().await.unwrap()
     }
 
     pub async fn filter(&self, pattern: &str) -> Vec<(String, (String, ResourceDescriptor))> {
         let req = self
             .client
-            .post(self.build_url("_filter"))
+            .post(self.build_data_url("_filter"))
             .body(pattern.to_string());
         req.send().await.unwrap().json().await.unwrap()
     }
@@ -68,8 +239,539 @@ impl Client {
     pub async fn filter_keys(&self, pattern: &str) -> Vec<String> {
         let req = self
             .client
-            .post(self.build_url("_filter/keys"))
+            .post(self.build_data_url("_filter/keys"))
             .body(pattern.to_string());
         req.send().await.unwrap().json().await.unwrap()
     }
+
+    /// Get information about the server (see `GET /_info`), listing the `top`
+    /// most common key prefixes of every namespace.
+    pub async fn info(&self, top: usize) -> serde_json::Value {
+        let req = self
+            .client
+            .get(self.build_url("_info"))
+            .query(&[("top", top)]);
+        req.send().await.unwrap().json().await.unwrap()
+    }
+
+    /// Get the name of every namespace.
+    pub async fn namespaces(&self) -> Vec<String> {
+        let req = self.client.get(self.build_url("_ns"));
+        req.send().await.unwrap().json().await.unwrap()
+    }
+
+    /// Create a namespace.
+    ///
+    /// # Arguments
+    /// * `name` - the name of the namespace
+    /// * `capacity` - the maximum number of keys (unlimited if `None`)
+    /// * `max_age` - how long (in milliseconds) a key can go unused before it's
+    ///   removed by a scan (`0` keeps keys forever, `None` uses the server's default)
+    ///
+    /// # Returns
+    /// `false` if the namespace couldn't be created (e.g. it already exists).
+    pub async fn create_namespace(
+        &self,
+        name: &str,
+        capacity: Option<usize>,
+        max_age: Option<usize>,
+    ) -> bool {
+        let mut options = Vec::new();
+        if let Some(capacity) = capacity {
+            options.push(format!("\"capacity\":{capacity}"));
+        }
+
+        if let Some(max_age) = max_age {
+            options.push(format!("\"max_age\":{max_age}"));
+        }
+
+        let req = self
+            .client
+            .post(self.build_url(&format!("_ns/{name}")))
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(format!("{{{}}}", options.join(",")));
+        req.send().await.unwrap().status() == StatusCode::CREATED
+    }
+
+    /// Drop a namespace (and everything in it).
+    ///
+    /// # Returns
+    /// `false` if the namespace doesn't exist.
+    pub async fn drop_namespace(&self, name: &str) -> bool {
+        let req = self.client.delete(self.build_url(&format!("_ns/{name}")));
+        req.send().await.unwrap().status() == StatusCode::OK
+    }
+
+    /// Push a `value` to an `end` of a list.
+    ///
+    /// # Returns
+    /// The length of the list, or `None` if the key doesn't hold a list.
+    pub async fn push(&self, key: &str, value: &str, end: End) -> Option<usize> {
+        let req = self
+            .client
+            .post(self.build_data_url(&format!("_list/{key}/push")))
+            .query(&[("end", end)])
+            .body(value.to_string());
+        req.send().await.unwrap().text().await.unwrap().parse().ok()
+    }
+
+    /// Pop a value from an `end` of a list, waiting up to `timeout` milliseconds
+    /// for a value to be pushed if the list is empty (`0` doesn't wait, and the
+    /// server waits 5 minutes at most).
+    ///
+    /// # Returns
+    /// `None` if the list is still empty, or the key doesn't hold a list.
+    pub async fn pop(&self, key: &str, end: End, timeout: u64) -> Option<String> {
+        let req = self
+            .client
+            .post(self.build_data_url(&format!("_list/{key}/pop")))
+            .query(&[("end", end)])
+            .query(&[("timeout", timeout)]);
+        let res = req.send().await.unwrap();
+        if res.status() != StatusCode::OK {
+            return None;
+        }
+
+        Some(res.text().await.unwrap())
+    }
+
+    /// Get the values of a list between two positions (both included, negative
+    /// positions count from the end of the list).
+    pub async fn range(&self, key: &str, start: isize, stop: isize) -> Vec<String> {
+        let req = self
+            .client
+            .get(self.build_data_url(&format!("_list/{key}")))
+            .query(&[("start", start), ("stop", stop)]);
+        req.send().await.unwrap().json().await.unwrap_or_default()
+    }
+
+    /// Remove every value of a list which isn't between two positions (see
+    /// [`Client::range`]).
+    ///
+    /// # Returns
+    /// The length of the list, or `None` if the key doesn't hold a list.
+    pub async fn trim(&self, key: &str, start: isize, stop: isize) -> Option<usize> {
+        let req = self
+            .client
+            .post(self.build_data_url(&format!("_list/{key}/trim")))
+            .query(&[("start", start), ("stop", stop)]);
+        req.send().await.unwrap().text().await.unwrap().parse().ok()
+    }
+
+    /// Get the length of a list.
+    ///
+    /// # Returns
+    /// `None` if the key doesn't hold a list.
+    pub async fn list_len(&self, key: &str) -> Option<usize> {
+        let req = self
+            .client
+            .get(self.build_data_url(&format!("_list/{key}/len")));
+        req.send().await.unwrap().text().await.unwrap().parse().ok()
+    }
+
+    /// Get the value of a `field` of a hash.
+    ///
+    /// # Returns
+    /// `None` if the field doesn't exist, or the key doesn't hold a hash.
+    pub async fn hash_get(&self, key: &str, field: &str) -> Option<String> {
+        let req = self
+            .client
+            .get(self.build_data_url(&format!("_hash/{key}/{field}")));
+        let res = req.send().await.unwrap();
+        if res.status() != StatusCode::OK {
+            return None;
+        }
+
+        Some(res.text().await.unwrap())
+    }
+
+    /// Get every field of a hash.
+    pub async fn hash_get_all(&self, key: &str) -> BTreeMap<String, String> {
+        let req = self
+            .client
+            .get(self.build_data_url(&format!("_hash/{key}")));
+        req.send().await.unwrap().json().await.unwrap_or_default()
+    }
+
+    /// Set the `value` of a `field` of a hash.
+    ///
+    /// # Returns
+    /// If the field is new.
+    pub async fn hash_set(&self, key: &str, field: &str, value: &str) -> bool {
+        let req = self
+            .client
+            .post(self.build_data_url(&format!("_hash/{key}/{field}")))
+            .body(value.to_string());
+        req.send().await.unwrap().status() == StatusCode::CREATED
+    }
+
+    /// Remove a `field` of a hash.
+    ///
+    /// # Returns
+    /// If the field existed.
+    pub async fn hash_remove(&self, key: &str, field: &str) -> bool {
+        let req = self
+            .client
+            .delete(self.build_data_url(&format!("_hash/{key}/{field}")));
+        req.send().await.unwrap().status() == StatusCode::OK
+    }
+
+    /// Add `by` to the value of a `field` of a hash.
+    ///
+    /// # Returns
+    /// The new value, or `None` if the field isn't a number.
+    pub async fn hash_incr(&self, key: &str, field: &str, by: i64) -> Option<i64> {
+        let req = self
+            .client
+            .post(self.build_data_url(&format!("_hash/{key}/{field}/incr")))
+            .query(&[("by", by)]);
+        req.send().await.unwrap().text().await.unwrap().parse().ok()
+    }
+
+    /// Check if a `field` of a hash exists.
+    pub async fn hash_exists(&self, key: &str, field: &str) -> bool {
+        let req = self
+            .client
+            .head(self.build_data_url(&format!("_hash/{key}/{field}")));
+        req.send().await.unwrap().status() == StatusCode::OK
+    }
+
+    /// Add a `member` to a set.
+    ///
+    /// # Returns
+    /// If the member wasn't in the set.
+    pub async fn set_add(&self, key: &str, member: &str) -> bool {
+        let req = self
+            .client
+            .post(self.build_data_url(&format!("_set/{key}/members/{member}")));
+        req.send().await.unwrap().status() == StatusCode::CREATED
+    }
+
+    /// Remove a `member` from a set.
+    ///
+    /// # Returns
+    /// If the member was in the set.
+    pub async fn set_remove(&self, key: &str, member: &str) -> bool {
+        let req = self
+            .client
+            .delete(self.build_data_url(&format!("_set/{key}/members/{member}")));
+        req.send().await.unwrap().status() == StatusCode::OK
+    }
+
+    /// Get every member of a set.
+    pub async fn set_members(&self, key: &str) -> Vec<String> {
+        let req = self.client.get(self.build_data_url(&format!("_set/{key}")));
+        req.send().await.unwrap().json().await.unwrap_or_default()
+    }
+
+    /// Check if a `member` is in a set.
+    pub async fn set_is_member(&self, key: &str, member: &str) -> bool {
+        let req = self
+            .client
+            .get(self.build_data_url(&format!("_set/{key}/members/{member}")));
+        req.send().await.unwrap().status() == StatusCode::OK
+    }
+
+    /// Get the number of members in a set.
+    ///
+    /// # Returns
+    /// `None` if the key doesn't hold a set.
+    pub async fn set_len(&self, key: &str) -> Option<usize> {
+        let req = self
+            .client
+            .get(self.build_data_url(&format!("_set/{key}/len")));
+        req.send().await.unwrap().text().await.unwrap().parse().ok()
+    }
+
+    /// Get every value which is a member of any of the sets stored in `keys`.
+    pub async fn set_union(&self, keys: &[&str]) -> Vec<String> {
+        self.combine_sets("union", keys).await
+    }
+
+    /// Get every value which is a member of all of the sets stored in `keys`.
+    pub async fn set_intersect(&self, keys: &[&str]) -> Vec<String> {
+        self.combine_sets("intersect", keys).await
+    }
+
+    /// Combine the sets stored in `keys` (see [`Client::set_union`] and
+    /// [`Client::set_intersect`]).
+    async fn combine_sets(&self, operation: &str, keys: &[&str]) -> Vec<String> {
+        let Some((first, others)) = keys.split_first() else {
+            return Vec::new();
+        };
+
+        let req = self
+            .client
+            .get(self.build_data_url(&format!("_set/{first}/{operation}")))
+            .query(&[("with", others.join(","))]);
+        req.send().await.unwrap().json().await.unwrap_or_default()
+    }
+
+    /// Set the `score` of a `member` of a sorted set.
+    ///
+    /// # Returns
+    /// If the member wasn't in the set.
+    pub async fn sorted_add(&self, key: &str, member: &str, score: f64) -> bool {
+        let req = self
+            .client
+            .post(self.build_data_url(&format!("_sorted/{key}/members/{member}")))
+            .body(score.to_string());
+        req.send().await.unwrap().status() == StatusCode::CREATED
+    }
+
+    /// Remove a `member` from a sorted set.
+    ///
+    /// # Returns
+    /// If the member was in the set.
+    pub async fn sorted_remove(&self, key: &str, member: &str) -> bool {
+        let req = self
+            .client
+            .delete(self.build_data_url(&format!("_sorted/{key}/members/{member}")));
+        req.send().await.unwrap().status() == StatusCode::OK
+    }
+
+    /// Add `by` to the score of a `member` of a sorted set.
+    ///
+    /// # Returns
+    /// The new score, or `None` if the key doesn't hold a sorted set.
+    pub async fn sorted_incr(&self, key: &str, member: &str, by: f64) -> Option<f64> {
+        let req = self
+            .client
+            .post(self.build_data_url(&format!("_sorted/{key}/members/{member}/incr")))
+            .query(&[("by", by)]);
+        req.send().await.unwrap().text().await.unwrap().parse().ok()
+    }
+
+    /// Get the score of a `member` of a sorted set.
+    ///
+    /// # Returns
+    /// `None` if the member isn't in the set.
+    pub async fn sorted_score(&self, key: &str, member: &str) -> Option<f64> {
+        let req = self
+            .client
+            .get(self.build_data_url(&format!("_sorted/{key}/members/{member}")));
+        let res = req.send().await.unwrap();
+        if res.status() != StatusCode::OK {
+            return None;
+        }
+
+        res.text().await.unwrap().parse().ok()
+    }
+
+    /// Get the rank of a `member` of a sorted set (from `0`, the lowest score).
+    ///
+    /// # Returns
+    /// `None` if the member isn't in the set.
+    pub async fn sorted_rank(&self, key: &str, member: &str) -> Option<usize> {
+        let req = self
+            .client
+            .get(self.build_data_url(&format!("_sorted/{key}/members/{member}/rank")));
+        let res = req.send().await.unwrap();
+        if res.status() != StatusCode::OK {
+            return None;
+        }
+
+        res.text().await.unwrap().parse().ok()
+    }
+
+    /// Get the members of a sorted set between two ranks (both included), with
+    /// their scores. Negative ranks count from the end of the set (`-1` is the
+    /// member with the highest score).
+    pub async fn sorted_range(&self, key: &str, start: isize, stop: isize) -> Vec<(String, f64)> {
+        let req = self
+            .client
+            .get(self.build_data_url(&format!("_sorted/{key}")))
+            .query(&[("start", start), ("stop", stop)]);
+        scored(req.send().await.unwrap()).await
+    }
+
+    /// Get the members of a sorted set with a score between `min` and `max`
+    /// (both included), with their scores.
+    pub async fn sorted_range_by_score(&self, key: &str, min: f64, max: f64) -> Vec<(String, f64)> {
+        let req = self
+            .client
+            .get(self.build_data_url(&format!("_sorted/{key}/by_score")))
+            .query(&[("min", min), ("max", max)]);
+        scored(req.send().await.unwrap()).await
+    }
+
+    /// Get the number of members in a sorted set.
+    ///
+    /// # Returns
+    /// `None` if the key doesn't hold a sorted set.
+    pub async fn sorted_len(&self, key: &str) -> Option<usize> {
+        let req = self
+            .client
+            .get(self.build_data_url(&format!("_sorted/{key}/len")));
+        req.send().await.unwrap().text().await.unwrap().parse().ok()
+    }
+
+    /// Get a JSON document (see [`Client::json_get`]).
+    pub async fn get_json<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        self.json_get(key, "$").await
+    }
+
+    /// Store `value` as a JSON document (replacing whatever `key` holds).
+    pub async fn insert_json<T: Serialize>(&self, key: &str, value: &T) -> bool {
+        let req = self
+            .client
+            .post(self.build_data_url(&format!("_json/{key}")))
+            .json(value);
+        req.send().await.unwrap().status().is_success()
+    }
+
+    /// Get the value at a `path` of a JSON document (e.g. `$.user.name`).
+    ///
+    /// # Returns
+    /// `None` if the path doesn't exist, the key doesn't hold a document, or the
+    /// value can't be deserialized as `T`.
+    pub async fn json_get<T: DeserializeOwned>(&self, key: &str, path: &str) -> Option<T> {
+        let req = self
+            .client
+            .get(self.build_data_url(&format!("_json/{key}")))
+            .query(&[("path", path)]);
+        let res = req.send().await.unwrap();
+        if res.status() != StatusCode::OK {
+            return None;
+        }
+
+        res.json().await.ok()
+    }
+
+    /// Set the value at a `path` of a JSON document (which is created if it
+    /// doesn't exist).
+    ///
+    /// # Returns
+    /// If the value is new.
+    pub async fn json_set<T: Serialize>(&self, key: &str, path: &str, value: &T) -> bool {
+        let req = self
+            .client
+            .post(self.build_data_url(&format!("_json/{key}")))
+            .query(&[("path", path)])
+            .json(value);
+        req.send().await.unwrap().status() == StatusCode::CREATED
+    }
+
+    /// Remove the value at a `path` of a JSON document (`$` removes the whole
+    /// document).
+    ///
+    /// # Returns
+    /// If the value existed.
+    pub async fn json_remove(&self, key: &str, path: &str) -> bool {
+        let req = self
+            .client
+            .delete(self.build_data_url(&format!("_json/{key}")))
+            .query(&[("path", path)]);
+        req.send().await.unwrap().status() == StatusCode::OK
+    }
+
+    /// Add `by` to the number at a `path` of a JSON document.
+    ///
+    /// # Returns
+    /// The new number, or `None` if the value isn't a number.
+    pub async fn json_incr(&self, key: &str, path: &str, by: f64) -> Option<f64> {
+        let req = self
+            .client
+            .post(self.build_data_url(&format!("_json/{key}/incr")))
+            .query(&[("path", path), ("by", &by.to_string())]);
+        let res = req.send().await.unwrap();
+        if res.status() != StatusCode::OK {
+            return None;
+        }
+
+        res.json().await.ok()
+    }
+
+    /// Get the keys of the JSON documents matching a `pattern` where the value at
+    /// `path` compares to `value` (e.g. `$.age` is [`Comparison::Gt`] `30`).
+    pub async fn json_filter_keys<T: Serialize>(
+        &self,
+        pattern: &str,
+        path: &str,
+        op: Comparison,
+        value: &T,
+    ) -> Vec<String> {
+        let op = serde_json::to_value(op).unwrap();
+        let req = self
+            .client
+            .post(self.build_data_url("_filter/json"))
+            .query(&[
+                ("path", path),
+                ("op", op.as_str().unwrap()),
+                ("value", &serde_json::to_string(value).unwrap()),
+            ])
+            .body(pattern.to_string());
+        req.send().await.unwrap().json().await.unwrap_or_default()
+    }
+
+    /// Watch the keys matching a `pattern` (see [`Event::matches`]) for changes.
+    ///
+    /// # Returns
+    /// Every change, as it happens. The stream ends if the connection is lost, or
+    /// if the client fell too far behind and missed changes.
+    pub async fn watch(&self, pattern: &str) -> BoxStream<'static, Event> {
+        let req = self
+            .client
+            .get(self.build_data_url("_watch"))
+            .query(&[("pattern", pattern)]);
+        events(req.send().await.unwrap())
+            .filter_map(|x| async move { serde_json::from_str(&x).ok() })
+            .boxed()
+    }
+
+    /// Publish a `message` to a `channel`.
+    ///
+    /// # Returns
+    /// The number of subscribers the message was sent to.
+    pub async fn publish(&self, channel: &str, message: &str) -> usize {
+        let req = self
+            .client
+            .post(self.build_url(&format!("_publish/{channel}")))
+            .body(message.to_string());
+        req.send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap()
+            .parse()
+            .unwrap_or(0)
+    }
+
+    /// Subscribe to a `channel`.
+    ///
+    /// # Returns
+    /// Every message published to the channel (see [`Client::watch`] for when the
+    /// stream ends).
+    pub async fn subscribe(&self, channel: &str) -> BoxStream<'static, String> {
+        let req = self
+            .client
+            .get(self.build_url(&format!("_subscribe/{channel}")));
+        events(req.send().await.unwrap())
+            .filter_map(|x| async move {
+                serde_json::from_str::<serde_json::Value>(&x)
+                    .ok()?
+                    .get("message")?
+                    .as_str()
+                    .map(|x| x.to_string())
+            })
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Client, ClientOptions};
+    use std::io::ErrorKind;
+
+    #[test]
+    fn bad_token() {
+        let options = ClientOptions {
+            token: Some("a\nb".to_string()),
+            ..Default::default()
+        };
+
+        let client = Client::with_options("http://localhost".to_string(), options);
+        assert_eq!(client.err().unwrap().kind(), ErrorKind::InvalidInput);
+    }
 }
@@ -0,0 +1,156 @@
+//! Reading and writing values as Rust types (see [`Client::typed`]).
+//!
+//! Values are serialized with an [`Encoding`] before they're stored, and
+//! deserialized when they're read, so anything implementing `Serialize` and
+//! `Deserialize` can be stored without converting it by hand. Values have to be
+//! read with the encoding they were written with.
+use crate::Client;
+use reqwest::{StatusCode, header};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::io::{Error, ErrorKind, Result};
+
+/// How values are serialized.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Encoding {
+    /// JSON (readable by anything, and usable with the `/_json` routes).
+    #[default]
+    Json,
+    /// MessagePack (needs the `msgpack` feature).
+    #[cfg(feature = "msgpack")]
+    MessagePack,
+    /// bincode (needs the `bincode` feature).
+    #[cfg(feature = "bincode")]
+    Bincode,
+}
+
+impl Encoding {
+    /// Serialize a `value`.
+    pub fn encode<T: Serialize>(self, value: &T) -> Result<Vec<u8>> {
+        match self {
+            Self::Json => serde_json::to_vec(value).map_err(Error::other),
+            #[cfg(feature = "msgpack")]
+            Self::MessagePack => rmp_serde::to_vec_named(value).map_err(Error::other),
+            #[cfg(feature = "bincode")]
+            Self::Bincode => bincode::serialize(value).map_err(Error::other),
+        }
+    }
+
+    /// Deserialize a value created by [`Self::encode`].
+    ///
+    /// # Returns
+    /// An [`ErrorKind::InvalidData`] error if the bytes aren't a valid `T`.
+    pub fn decode<T: DeserializeOwned>(self, bytes: &[u8]) -> Result<T> {
+        let invalid = |e: String| Error::new(ErrorKind::InvalidData, e);
+        match self {
+            Self::Json => serde_json::from_slice(bytes).map_err(|e| invalid(e.to_string())),
+            #[cfg(feature = "msgpack")]
+            Self::MessagePack => rmp_serde::from_slice(bytes).map_err(|e| invalid(e.to_string())),
+            #[cfg(feature = "bincode")]
+            Self::Bincode => bincode::deserialize(bytes).map_err(|e| invalid(e.to_string())),
+        }
+    }
+
+    /// The content type values are sent with.
+    fn content_type(self) -> &'static str {
+        match self {
+            Self::Json => "application/json",
+            #[cfg(feature = "msgpack")]
+            Self::MessagePack => "application/msgpack",
+            #[cfg(feature = "bincode")]
+            Self::Bincode => "application/octet-stream",
+        }
+    }
+}
+
+/// A [`Client`] which reads and writes values as Rust types (see
+/// [`Client::typed`]).
+#[derive(Clone)]
+pub struct Typed {
+    client: Client,
+    encoding: Encoding,
+}
+
+impl Client {
+    /// Get a client which reads and writes values as Rust types, serialized
+    /// with the given `encoding` (in the client's namespace, if it has one).
+    pub fn typed(&self, encoding: Encoding) -> Typed {
+        Typed {
+            client: self.clone(),
+            encoding,
+        }
+    }
+}
+
+impl Typed {
+    /// Get the value of a key.
+    ///
+    /// # Returns
+    /// `None` if the key doesn't exist, or an error if the request fails or the
+    /// value can't be deserialized as `T`.
+    pub async fn get<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
+        let req = self.client.client.get(self.client.build_data_url(key));
+        let res = req.send().await.map_err(Error::other)?;
+        if res.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        let res = res.error_for_status().map_err(Error::other)?;
+        let bytes = res.bytes().await.map_err(Error::other)?;
+        self.encoding.decode(&bytes).map(Some)
+    }
+
+    /// Store a `value` in a key.
+    ///
+    /// # Returns
+    /// An error if the value can't be serialized, or the request fails.
+    pub async fn insert<T: Serialize>(&self, key: &str, value: &T) -> Result<()> {
+        let req = self
+            .client
+            .client
+            .post(self.client.build_data_url(key))
+            .header(header::CONTENT_TYPE, self.encoding.content_type())
+            .body(self.encoding.encode(value)?);
+        let res = req.send().await.map_err(Error::other)?;
+        res.error_for_status().map_err(Error::other)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Encoding;
+    use std::collections::BTreeMap;
+    use std::io::ErrorKind;
+
+    #[test]
+    fn encodings() {
+        let value = (
+            "oyster".to_string(),
+            42_u32,
+            BTreeMap::from([("a".to_string(), vec![true, false])]),
+        );
+
+        let encodings = [
+            Encoding::Json,
+            #[cfg(feature = "msgpack")]
+            Encoding::MessagePack,
+            #[cfg(feature = "bincode")]
+            Encoding::Bincode,
+        ];
+
+        for encoding in encodings {
+            let bytes = encoding.encode(&value).unwrap();
+            assert_eq!(
+                encoding
+                    .decode::<(String, u32, BTreeMap<String, Vec<bool>>)>(&bytes)
+                    .unwrap(),
+                value
+            );
+            assert_eq!(
+                encoding.decode::<Vec<String>>(&[0xc1]).unwrap_err().kind(),
+                ErrorKind::InvalidData
+            );
+        }
+    }
+}
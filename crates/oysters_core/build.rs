@@ -0,0 +1,16 @@
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+
+    // generate the gRPC code from the protobuf definitions (without needing protoc)
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/oysters.proto");
+
+        let descriptors = protox::compile(["oysters.proto"], ["proto"])
+            .expect("failed to compile protobuf definitions");
+
+        tonic_build::configure()
+            .compile_fds(descriptors)
+            .expect("failed to generate gRPC code");
+    }
+}
@@ -6,13 +6,15 @@ fn main() {
         pagemap::PageMap::new(pagemap::PageMapOptions {
             pages: 1,
             page_size: 64,
+            compact_threshold: None,
+            compression: compression::Compression::new(compression::Codec::Lz4, 64),
         });
 
-    book.pagebook.insert(b"test", b"Hello, world!");
+    book.pagebook.insert(b"test", b"Hello, world!").unwrap();
     let v = book.pagebook.get(b"test");
     dbg!(&String::from_utf8(v.unwrap()));
 
-    book.pagebook.insert(b"test1", b"Hello, world! 1");
+    book.pagebook.insert(b"test1", b"Hello, world! 1").unwrap();
     let v = book.pagebook.get(b"test1");
     dbg!(&String::from_utf8(v.unwrap()));
 
@@ -20,9 +22,18 @@ fn main() {
     let v = book.pagebook.get(b"test");
     dbg!(v.is_some());
 
-    book.pagebook.insert(b"test2", b"Hello, world! 2");
+    book.pagebook.insert(b"test2", b"Hello, world! 2").unwrap();
     let v = book.pagebook.get_full(b"test2").unwrap();
     dbg!(v.0, &String::from_utf8(v.1));
 
-    book.dump(pathbufd::PathBufD::current().extend(&["page_dump"]));
+    book.dump(pathbufd::PathBufD::current().extend(&["page_dump"]))
+        .unwrap();
+
+    let book: pagemap::PageMap<String, String> = pagemap::PageMap::load(
+        pathbufd::PathBufD::current().extend(&["page_dump"]),
+        compression::Compression::default(),
+    )
+    .unwrap();
+    let v = book.pagebook.get(b"test2");
+    dbg!(&String::from_utf8(v.unwrap()));
 }
@@ -6,13 +6,16 @@ fn main() {
         pagemap::PageMap::new(pagemap::PageMapOptions {
             pages: 1,
             page_size: 16_000,
+            compact_threshold: None,
+            compression: compression::Compression::new(compression::Codec::Lz4, 64),
         });
 
     for i in 0..1 {
         let string = i.to_string();
-        book.insert(string.to_string(), string);
+        book.insert(string.to_string(), string).unwrap();
     }
-    book.insert("2".to_string(), "Hello, world!".to_string());
+    book.insert("2".to_string(), "Hello, world!".to_string())
+        .unwrap();
 
     // verify that we actually inserted everything by checking a random number
     // let v = book.get(&"514".to_string());
@@ -23,5 +26,6 @@ fn main() {
     let v = book.get(&"514".to_string());
     dbg!(&v);
 
-    book.dump(pathbufd::PathBufD::current().extend(&["page_dump"]));
+    book.dump(pathbufd::PathBufD::current().extend(&["page_dump"]))
+        .unwrap();
 }
@@ -6,11 +6,13 @@ fn main() {
         pagemap::PageMap::new(pagemap::PageMapOptions {
             pages: 1,
             page_size: 16_000,
+            compact_threshold: None,
+            compression: compression::Compression::new(compression::Codec::Lz4, 64),
         });
 
     for i in 0..1_000_000_i32 {
         let bytes = i.to_be_bytes();
-        book.pagebook.insert(&bytes, &bytes);
+        book.pagebook.insert(&bytes, &bytes).unwrap();
     }
 
     // verify that we actually inserted everything by checking a random number
@@ -0,0 +1,146 @@
+//! Collections which are kept as real data structures while they're in memory.
+//!
+//! Most collections (like [hashes](crate::hash)) are stored as their items
+//! [packed](crate::value) together, and are unpacked whenever they're used.
+//! Lists, sets and sorted sets are changed one item at a time, so unpacking (and
+//! packing again) on every change would make every change as slow as the whole
+//! collection. Instead, they're kept as a [`Collection`] in memory, and are only
+//! packed when they're read whole (or written to the dump file).
+//!
+//! The `sqlite_backend` feature keeps every value in the staging database, so
+//! collections are still packed (and unpacked) on every change there.
+use crate::pearl::ValueKind;
+
+#[cfg(not(feature = "sqlite_backend"))]
+use crate::compression::Compression;
+#[cfg(not(feature = "sqlite_backend"))]
+use crate::list::Items as ListItems;
+#[cfg(not(feature = "sqlite_backend"))]
+use crate::set::Members as SetMembers;
+#[cfg(not(feature = "sqlite_backend"))]
+use crate::sorted_set::Members as SortedMembers;
+#[cfg(not(feature = "sqlite_backend"))]
+use crate::value::{pack, unpack};
+#[cfg(all(feature = "persistance", not(feature = "sqlite_backend")))]
+use std::borrow::Cow;
+
+/// A collection which is kept as a data structure in memory.
+pub(crate) trait Collection: Default + Sized {
+    /// The kind of value the collection is stored as.
+    const KIND: ValueKind;
+
+    /// Create the collection from its packed items.
+    ///
+    /// # Returns
+    /// `None` if the items aren't a valid collection.
+    fn from_items(items: Vec<Vec<u8>>) -> Option<Self>;
+
+    /// Get the items of the collection, so it can be packed.
+    fn to_items(&self) -> Vec<Vec<u8>>;
+
+    /// If the collection has no members.
+    #[cfg(not(feature = "sqlite_backend"))]
+    fn is_empty(&self) -> bool;
+
+    /// Estimate how much memory (in bytes) the collection uses.
+    #[cfg(not(feature = "sqlite_backend"))]
+    fn memory_usage(&self) -> usize;
+
+    /// Get the collection out of a stored value.
+    #[cfg(not(feature = "sqlite_backend"))]
+    fn stored(stored: &Stored) -> Option<&Self>;
+
+    /// Get the collection out of a stored value (as mutable).
+    #[cfg(not(feature = "sqlite_backend"))]
+    fn stored_mut(stored: &mut Stored) -> Option<&mut Self>;
+
+    /// Turn the collection into a stored value.
+    #[cfg(not(feature = "sqlite_backend"))]
+    fn into_stored(self) -> Stored;
+}
+
+/// How a value is stored in memory.
+#[cfg(not(feature = "sqlite_backend"))]
+#[derive(Clone, Debug)]
+pub(crate) enum Stored {
+    /// Encoded bytes (see [`crate::compression`]).
+    Bytes(Vec<u8>),
+    /// The items of a list (see [`crate::list`]).
+    List(ListItems),
+    /// The members of a set (see [`crate::set`]).
+    Set(SetMembers),
+    /// The members of a sorted set (see [`crate::sorted_set`]).
+    SortedSet(SortedMembers),
+}
+
+#[cfg(not(feature = "sqlite_backend"))]
+impl Stored {
+    /// Create the stored value for the (not yet encoded) `bytes` of a value of
+    /// the given `kind`. Collections which can't be unpacked are kept as bytes.
+    pub(crate) fn new(kind: ValueKind, bytes: &[u8], compression: &Compression) -> Self {
+        let items = || unpack(bytes);
+        let collection = match kind {
+            ValueKind::List => items().and_then(ListItems::from_items).map(Self::List),
+            ValueKind::Set => items().and_then(SetMembers::from_items).map(Self::Set),
+            ValueKind::SortedSet => items()
+                .and_then(SortedMembers::from_items)
+                .map(Self::SortedSet),
+            _ => None,
+        };
+
+        collection.unwrap_or_else(|| Self::Bytes(compression.compress(bytes)))
+    }
+
+    /// Create the stored value for the `encoded` bytes of a value of the given
+    /// `kind` (e.g. a value read from the dump file).
+    #[cfg(feature = "persistance")]
+    pub(crate) fn from_encoded(
+        kind: ValueKind,
+        encoded: Vec<u8>,
+        compression: &Compression,
+    ) -> Self {
+        if !matches!(
+            kind,
+            ValueKind::List | ValueKind::Set | ValueKind::SortedSet
+        ) {
+            return Self::Bytes(encoded);
+        }
+
+        match compression.decompress(&encoded) {
+            Ok(bytes) => Self::new(kind, &bytes, compression),
+            Err(_) => Self::Bytes(encoded),
+        }
+    }
+
+    /// Get the bytes of the value (decoded). Collections are packed.
+    pub(crate) fn bytes(&self, compression: &Compression) -> Option<Vec<u8>> {
+        match self {
+            Self::Bytes(x) => compression.decompress(x).ok(),
+            Self::List(x) => Some(pack(&x.to_items())),
+            Self::Set(x) => Some(pack(&x.to_items())),
+            Self::SortedSet(x) => Some(pack(&x.to_items())),
+        }
+    }
+
+    /// Get the encoded bytes of the value (what's written to the dump file).
+    /// Collections are packed.
+    #[cfg(feature = "persistance")]
+    pub(crate) fn encoded(&self, compression: &Compression) -> Cow<'_, [u8]> {
+        match self {
+            Self::Bytes(x) => Cow::Borrowed(x),
+            Self::List(x) => Cow::Owned(compression.compress(&pack(&x.to_items()))),
+            Self::Set(x) => Cow::Owned(compression.compress(&pack(&x.to_items()))),
+            Self::SortedSet(x) => Cow::Owned(compression.compress(&pack(&x.to_items()))),
+        }
+    }
+
+    /// Estimate how much memory (in bytes) the value uses.
+    pub(crate) fn memory_usage(&self) -> usize {
+        match self {
+            Self::Bytes(x) => x.capacity(),
+            Self::List(x) => x.memory_usage(),
+            Self::Set(x) => x.memory_usage(),
+            Self::SortedSet(x) => x.memory_usage(),
+        }
+    }
+}
@@ -0,0 +1,241 @@
+//! Value compression.
+//!
+//! Every encoded value starts with a single tag byte which says how the rest of
+//! the bytes were compressed, so values written with one codec can still be read
+//! after the codec is changed (with the exception of dictionary compressed values,
+//! which need the same dictionary).
+use std::io::{Error, ErrorKind, Result};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// The value is stored as-is.
+const TAG_NONE: u8 = 0;
+/// The value is LZ4 compressed (with its size prepended).
+const TAG_LZ4: u8 = 1;
+/// The value is zstd compressed.
+#[cfg(feature = "zstd")]
+const TAG_ZSTD: u8 = 2;
+/// The value is zstd compressed with a dictionary.
+#[cfg(feature = "zstd")]
+const TAG_ZSTD_DICT: u8 = 3;
+
+/// A compression algorithm.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum Codec {
+    /// Don't compress values.
+    #[default]
+    None,
+    /// LZ4 compression.
+    Lz4,
+    /// zstd compression at the given level.
+    #[cfg(feature = "zstd")]
+    Zstd { level: i32 },
+    /// zstd compression at the given level, using a trained dictionary (see
+    /// [`Codec::train`]). Dictionaries make compressing lots of small, similar
+    /// values much more effective.
+    #[cfg(feature = "zstd")]
+    ZstdDict {
+        level: i32,
+        dictionary: Arc<Vec<u8>>,
+    },
+}
+
+impl Codec {
+    /// Train a zstd dictionary from the given `samples`.
+    ///
+    /// # Arguments
+    /// * `samples` - example values
+    /// * `max_size` - the maximum size of the dictionary
+    /// * `level` - the zstd compression level
+    #[cfg(feature = "zstd")]
+    pub fn train(samples: &[Vec<u8>], max_size: usize, level: i32) -> Result<Self> {
+        Ok(Self::ZstdDict {
+            level,
+            dictionary: Arc::new(zstd::dict::from_samples(samples, max_size)?),
+        })
+    }
+}
+
+/// Compression statistics (shared between every clone of a [`Compression`]).
+#[derive(Debug, Default)]
+pub struct CompressionStats {
+    /// The total size of every value before it was compressed.
+    raw_bytes: AtomicU64,
+    /// The total size of every value after it was compressed.
+    stored_bytes: AtomicU64,
+}
+
+/// A snapshot of [`CompressionStats`].
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CompressionReport {
+    /// The total size of every value before it was compressed.
+    pub raw_bytes: u64,
+    /// The total size of every value after it was compressed.
+    pub stored_bytes: u64,
+    /// `stored_bytes / raw_bytes`. Lower is better, values above `1.0` mean that
+    /// compression is making values bigger.
+    pub ratio: f64,
+}
+
+impl CompressionStats {
+    /// Take a snapshot of the stats.
+    pub fn report(&self) -> CompressionReport {
+        let raw_bytes = self.raw_bytes.load(Ordering::Relaxed);
+        let stored_bytes = self.stored_bytes.load(Ordering::Relaxed);
+
+        CompressionReport {
+            raw_bytes,
+            stored_bytes,
+            ratio: if raw_bytes == 0 {
+                1.0
+            } else {
+                stored_bytes as f64 / raw_bytes as f64
+            },
+        }
+    }
+}
+
+/// Compression options.
+#[derive(Clone, Debug, Default)]
+pub struct Compression {
+    /// The codec used to compress new values.
+    pub codec: Codec,
+    /// Values smaller than this (in bytes) are never compressed.
+    pub threshold: usize,
+    /// Compression statistics.
+    pub stats: Arc<CompressionStats>,
+}
+
+impl Compression {
+    /// Create a new [`Compression`].
+    pub fn new(codec: Codec, threshold: usize) -> Self {
+        Self {
+            codec,
+            threshold,
+            stats: Arc::new(CompressionStats::default()),
+        }
+    }
+
+    /// Wrap `bytes` as an uncompressed value (without counting it in the stats).
+    pub fn raw(bytes: &[u8]) -> Vec<u8> {
+        [&[TAG_NONE], bytes].concat()
+    }
+
+    /// Compress `bytes` (see [`Self::codec`]).
+    ///
+    /// Values which are smaller than [`Self::threshold`], or which don't get any
+    /// smaller when compressed, are stored as-is.
+    pub fn compress(&self, bytes: &[u8]) -> Vec<u8> {
+        let compressed = if bytes.len() < self.threshold {
+            None
+        } else {
+            match self.codec {
+                Codec::None => None,
+                Codec::Lz4 => Some((TAG_LZ4, lz4_flex::compress_prepend_size(bytes))),
+                #[cfg(feature = "zstd")]
+                Codec::Zstd { level } => zstd::bulk::compress(bytes, level)
+                    .ok()
+                    .map(|x| (TAG_ZSTD, x)),
+                #[cfg(feature = "zstd")]
+                Codec::ZstdDict {
+                    level,
+                    ref dictionary,
+                } => zstd::bulk::Compressor::with_dictionary(level, dictionary)
+                    .and_then(|mut x| x.compress(bytes))
+                    .ok()
+                    .map(|x| (TAG_ZSTD_DICT, x)),
+            }
+        };
+
+        let out: Vec<u8> = match compressed {
+            Some((tag, x)) if x.len() < bytes.len() => [&[tag], x.as_slice()].concat(),
+            _ => Self::raw(bytes),
+        };
+
+        self.stats
+            .raw_bytes
+            .fetch_add(bytes.len() as u64, Ordering::Relaxed);
+        self.stats
+            .stored_bytes
+            .fetch_add(out.len() as u64, Ordering::Relaxed);
+
+        out
+    }
+
+    /// Decompress bytes created by [`Self::compress`].
+    pub fn decompress(&self, bytes: &[u8]) -> Result<Vec<u8>> {
+        let (tag, data) = match bytes.split_first() {
+            Some(x) => x,
+            None => return Err(Error::new(ErrorKind::InvalidData, "missing codec tag")),
+        };
+
+        match *tag {
+            TAG_NONE => Ok(data.to_vec()),
+            TAG_LZ4 => lz4_flex::decompress_size_prepended(data)
+                .map_err(|e| Error::new(ErrorKind::InvalidData, e)),
+            #[cfg(feature = "zstd")]
+            TAG_ZSTD => zstd::stream::decode_all(data),
+            #[cfg(feature = "zstd")]
+            TAG_ZSTD_DICT => match self.codec {
+                Codec::ZstdDict { ref dictionary, .. } => {
+                    let mut out = Vec::new();
+                    let mut decoder =
+                        zstd::stream::read::Decoder::with_dictionary(data, dictionary)?;
+                    std::io::copy(&mut decoder, &mut out)?;
+                    Ok(out)
+                }
+                _ => Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "value needs a zstd dictionary",
+                )),
+            },
+            _ => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("unknown codec tag {tag}"),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Codec, Compression};
+
+    #[test]
+    fn codecs() {
+        let value = "Hello, world! ".repeat(32).into_bytes();
+
+        let mut codecs = vec![Codec::None, Codec::Lz4];
+
+        #[cfg(feature = "zstd")]
+        {
+            codecs.push(Codec::Zstd { level: 3 });
+
+            let samples: Vec<Vec<u8>> = (0..128)
+                .map(|i| format!("{{\"id\":{i},\"name\":\"user {i}\"}}").into_bytes())
+                .collect();
+
+            codecs.push(Codec::train(&samples, 1024, 3).unwrap());
+        }
+
+        for codec in codecs {
+            let compression = Compression::new(codec.clone(), 16);
+
+            let stored = compression.compress(&value);
+            assert_eq!(compression.decompress(&stored).unwrap(), value);
+
+            if codec != Codec::None {
+                assert!(stored.len() < value.len());
+                assert!(compression.stats.report().ratio < 1.0);
+            }
+
+            // below the threshold
+            let stored = compression.compress(b"a");
+            assert_eq!(stored, [0, b'a']);
+        }
+    }
+}
@@ -0,0 +1,157 @@
+//! Helpers shared by everything which stores the map in SQLite.
+use crate::compression::Compression;
+use rusqlite::types::ValueRef;
+use rusqlite::{Connection, Result};
+
+/// Quote a table name so it can be used in a statement.
+pub(crate) fn quote(table: &str) -> String {
+    format!("\"{}\"", table.replace('"', "\"\""))
+}
+
+/// The statement used to create a map table.
+fn create_statement(table: &str) -> String {
+    format!(
+        "CREATE TABLE IF NOT EXISTS {} (
+    key     TEXT NOT NULL,
+    value   BLOB NOT NULL,
+    used    INT,
+    expires INT,
+    flags   INT,
+    cas     INT,
+    kind    INT
+)",
+        quote(table)
+    )
+}
+
+/// Columns which didn't exist in the first version of the map table.
+const ADDED_COLUMNS: [&str; 4] = ["expires", "flags", "cas", "kind"];
+
+/// Create a map table (if it doesn't exist yet).
+///
+/// Tables created by older versions stored values in a TEXT column, and were
+/// missing some columns (see [`ADDED_COLUMNS`]). These tables are migrated so
+/// values are stored in a BLOB column (and existing values are wrapped as
+/// uncompressed values, see [`column_bytes`]).
+///
+/// # Arguments
+/// * `conn` - the database connection
+/// * `table` - the name of the table (see [`OysterOptions::table`](crate::OysterOptions::table))
+pub(crate) fn create_table(conn: &Connection, table: &str) -> Result<()> {
+    conn.execute(&create_statement(table), ())?;
+    let quoted = quote(table);
+
+    for column in ADDED_COLUMNS {
+        let exists: bool = conn.query_row(
+            "SELECT COUNT(*) > 0 FROM pragma_table_info(?) WHERE \"name\" = ?",
+            [table, column],
+            |row| row.get(0),
+        )?;
+
+        if !exists {
+            conn.execute(
+                &format!("ALTER TABLE {quoted} ADD COLUMN \"{column}\" INT"),
+                (),
+            )?;
+        }
+    }
+
+    let column: String = conn.query_row(
+        "SELECT \"type\" FROM pragma_table_info(?) WHERE \"name\" = 'value'",
+        [table],
+        |row| row.get(0),
+    )?;
+
+    if column.eq_ignore_ascii_case("BLOB") {
+        return Ok(());
+    }
+
+    // migrate
+    let old = quote(&format!("{table}_text"));
+    let tx = conn.unchecked_transaction()?;
+    tx.execute(&format!("ALTER TABLE {quoted} RENAME TO {old}"), ())?;
+    tx.execute(&create_statement(table), ())?;
+
+    {
+        let mut query = tx.prepare(&format!(
+            "SELECT \"key\", \"value\", \"used\", \"expires\", \"flags\", \"cas\", \"kind\" FROM {old}"
+        ))?;
+        let mut insert = tx.prepare(&format!(
+            "INSERT INTO {quoted} VALUES (?, ?, ?, ?, ?, ?, ?)"
+        ))?;
+
+        let rows = query.query_map([], |row| {
+            Ok((
+                row.get::<usize, String>(0)?,
+                column_bytes(row.get_ref(1)?),
+                row.get::<usize, Option<usize>>(2)?,
+                row.get::<usize, Option<usize>>(3)?,
+                row.get::<usize, Option<u32>>(4)?,
+                row.get::<usize, Option<u64>>(5)?,
+                row.get::<usize, Option<u8>>(6)?,
+            ))
+        })?;
+
+        for row in rows {
+            insert.execute(row?)?;
+        }
+    }
+
+    tx.execute(&format!("DROP TABLE {old}"), ())?;
+    tx.commit()
+}
+
+/// Drop a map table (if it exists).
+///
+/// # Arguments
+/// * `conn` - the database connection
+/// * `table` - the name of the table
+#[cfg(feature = "persistance")]
+pub(crate) fn drop_table(conn: &Connection, table: &str) -> Result<()> {
+    conn.execute(&format!("DROP TABLE IF EXISTS {}", quote(table)), ())?;
+    Ok(())
+}
+
+/// Read a stored value from an SQLite column.
+///
+/// Values are stored as encoded BLOBs, but values which were written before
+/// compression existed are plain TEXT (so they're wrapped as uncompressed values).
+pub(crate) fn column_bytes(value: ValueRef) -> Vec<u8> {
+    match value {
+        ValueRef::Blob(b) => b.to_vec(),
+        ValueRef::Text(t) => Compression::raw(t),
+        ValueRef::Integer(i) => Compression::raw(i.to_string().as_bytes()),
+        ValueRef::Real(r) => Compression::raw(r.to_string().as_bytes()),
+        ValueRef::Null => Compression::raw(&[]),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::create_table;
+    use rusqlite::Connection;
+
+    #[test]
+    fn migrate() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute(
+            "CREATE TABLE map (key TEXT NOT NULL, value TEXT NOT NULL, used INT)",
+            (),
+        )
+        .unwrap();
+        conn.execute("INSERT INTO map VALUES ('a', 'hello', 1)", ())
+            .unwrap();
+
+        create_table(&conn, "map").unwrap();
+        create_table(&conn, "map").unwrap(); // already migrated
+
+        let (value, used): (Vec<u8>, usize) = conn
+            .query_row("SELECT value, used FROM map WHERE key = 'a'", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+
+        assert_eq!(value, b"\0hello");
+        assert_eq!(used, 1);
+    }
+}
@@ -0,0 +1,59 @@
+//! Keyspace change notifications.
+//!
+//! Every [`Oyster`](crate::Oyster) sends an [`Event`] whenever a key changes
+//! (including when it's removed by a scan, or to make room for another key).
+//! Anything interested in changes can [`subscribe`](crate::Oyster::subscribe).
+use tokio::sync::broadcast;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// The number of events a slow subscriber can fall behind by before it starts
+/// missing events.
+pub const CAPACITY: usize = 1024;
+
+/// What happened to a key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum EventKind {
+    /// The key was inserted (or changed).
+    Insert,
+    /// The key was removed.
+    Remove,
+    /// The key expired (see [`Oyster::expire`](crate::Oyster::expire)), and was
+    /// removed by a scan.
+    Expire,
+    /// The key was removed by a scan because it went unused for too long (see
+    /// [`OysterOptions::max_age`](crate::OysterOptions::max_age)), or to make room
+    /// for another key (see [`OysterOptions::capacity`](crate::OysterOptions::capacity)).
+    Evict,
+}
+
+/// A change to a key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Event {
+    /// What happened to the key.
+    pub kind: EventKind,
+    /// The key which changed.
+    pub key: String,
+}
+
+impl Event {
+    /// If the event's key matches the given `pattern` (see
+    /// [`Oyster::filter`](crate::Oyster::filter)).
+    pub fn matches(&self, pattern: &str) -> bool {
+        let pat = &pattern.replace("*", "");
+        if pattern.starts_with("*") {
+            self.key.ends_with(pat)
+        } else {
+            self.key.starts_with(pat)
+        }
+    }
+}
+
+/// Create the sender events are sent through.
+pub(crate) fn channel() -> broadcast::Sender<Event> {
+    broadcast::channel(CAPACITY).0
+}
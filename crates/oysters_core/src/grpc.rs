@@ -0,0 +1,59 @@
+//! Generated gRPC service and messages (from `proto/oysters.proto`).
+use crate::events;
+
+tonic::include_proto!("oysters");
+
+impl From<crate::pearl::ResourceDescriptor> for ResourceDescriptor {
+    fn from(value: crate::pearl::ResourceDescriptor) -> Self {
+        Self {
+            #[cfg(feature = "lru")]
+            used: value.used as u64,
+            #[cfg(not(feature = "lru"))]
+            used: 0,
+            #[cfg(feature = "lru")]
+            expires: value.expires.map(|x| x as u64),
+            #[cfg(not(feature = "lru"))]
+            expires: None,
+            flags: value.flags,
+            cas: value.cas,
+            kind: resource_descriptor::Kind::from(value.kind).into(),
+        }
+    }
+}
+
+impl From<crate::pearl::ValueKind> for resource_descriptor::Kind {
+    fn from(value: crate::pearl::ValueKind) -> Self {
+        match value {
+            crate::pearl::ValueKind::String => Self::String,
+            crate::pearl::ValueKind::List => Self::List,
+            crate::pearl::ValueKind::Hash => Self::Hash,
+            crate::pearl::ValueKind::Set => Self::Set,
+            crate::pearl::ValueKind::SortedSet => Self::SortedSet,
+            crate::pearl::ValueKind::Json => Self::Json,
+        }
+    }
+}
+
+impl<V: Clone + crate::Value> From<crate::Pearl<V>> for Pearl {
+    fn from(value: crate::Pearl<V>) -> Self {
+        Self {
+            value: value.0.to_bytes(),
+            descriptor: Some(value.1.into()),
+        }
+    }
+}
+
+impl From<events::Event> for Event {
+    fn from(value: events::Event) -> Self {
+        Self {
+            kind: match value.kind {
+                events::EventKind::Insert => event::Kind::Insert,
+                events::EventKind::Remove => event::Kind::Remove,
+                events::EventKind::Expire => event::Kind::Expire,
+                events::EventKind::Evict => event::Kind::Evict,
+            }
+            .into(),
+            key: value.key,
+        }
+    }
+}
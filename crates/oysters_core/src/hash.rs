@@ -0,0 +1,193 @@
+//! Hashes (values stored by field).
+//!
+//! A hash is stored like any other value, but its
+//! [`ResourceDescriptor::kind`](crate::pearl::ResourceDescriptor::kind) is
+//! [`ValueKind::Hash`], and its value is every field followed by its value,
+//! packed together. This means a single field can be changed without the client
+//! having to read and re-write the whole value. Hashes are created by setting a
+//! field, and removed once they have no fields.
+use crate::oyster::Oyster;
+use crate::pearl::{ResourceDescriptor, ValueKind};
+use crate::value::Value;
+use std::collections::BTreeMap;
+use std::hash::Hash;
+
+impl<K, V> Oyster<K, V>
+where
+    K: Hash + Ord + Clone + Send + ToString + From<String>,
+    V: Clone + Send + Value,
+{
+    /// Read the hash stored in `key` (an empty hash if the key doesn't exist).
+    ///
+    /// # Returns
+    /// `None` if the key holds something which isn't a hash.
+    fn read_hash(&self, key: &K) -> Option<(BTreeMap<String, Vec<u8>>, ResourceDescriptor)> {
+        let (items, descriptor) = self.read_packed(ValueKind::Hash, key)?;
+        let mut fields = BTreeMap::new();
+        for pair in items.chunks_exact(2) {
+            fields.insert(
+                String::from_utf8_lossy(&pair[0]).to_string(),
+                pair[1].clone(),
+            );
+        }
+
+        Some((fields, descriptor))
+    }
+
+    /// Store a hash read by [`Self::read_hash`] in `key`.
+    fn write_hash(
+        &mut self,
+        key: K,
+        fields: BTreeMap<String, Vec<u8>>,
+        descriptor: ResourceDescriptor,
+    ) {
+        let items: Vec<Vec<u8>> = fields
+            .into_iter()
+            .flat_map(|(field, value)| [field.into_bytes(), value])
+            .collect();
+        self.write_packed(key, &items, descriptor);
+    }
+
+    /// Get the value of a field.
+    ///
+    /// # Arguments
+    /// * `key` - the key the hash is stored in
+    /// * `field` - the field
+    ///
+    /// # Returns
+    /// `None` if the field doesn't exist, or the key holds something which isn't a hash.
+    pub fn hash_get(&self, key: &K, field: &str) -> Option<V> {
+        let (mut fields, _) = self.read_hash(key)?;
+        fields.remove(field).map(V::from_bytes)
+    }
+
+    /// Get every field of a hash (none if it doesn't exist).
+    ///
+    /// # Returns
+    /// `None` if the key holds something which isn't a hash.
+    pub fn hash_get_all(&self, key: &K) -> Option<BTreeMap<String, V>> {
+        let (fields, _) = self.read_hash(key)?;
+        Some(
+            fields
+                .into_iter()
+                .map(|(field, value)| (field, V::from_bytes(value)))
+                .collect(),
+        )
+    }
+
+    /// Set the value of a field (the hash is created if it doesn't exist). The
+    /// hash keeps its expiry time.
+    ///
+    /// # Arguments
+    /// * `key` - the key the hash is stored in
+    /// * `field` - the field
+    /// * `value` - the value of the field
+    ///
+    /// # Returns
+    /// If the field is new, or `None` if the key holds something which isn't a hash.
+    pub fn hash_set(&mut self, key: K, field: String, value: V) -> Option<bool> {
+        let (mut fields, descriptor) = self.read_hash(&key)?;
+        let created = fields.insert(field, value.to_bytes()).is_none();
+        self.write_hash(key, fields, descriptor);
+        Some(created)
+    }
+
+    /// Remove a field (the hash is removed once it has no fields).
+    ///
+    /// # Returns
+    /// If the field existed, or `None` if the key holds something which isn't a hash.
+    pub fn hash_remove(&mut self, key: &K, field: &str) -> Option<bool> {
+        let (mut fields, descriptor) = self.read_hash(key)?;
+        if fields.remove(field).is_none() {
+            return Some(false);
+        }
+
+        self.write_hash(key.clone(), fields, descriptor);
+        Some(true)
+    }
+
+    /// Add `by` to the value of a field (which is `0` if it doesn't exist). Unlike
+    /// [`Self::incr`], fields can go below `0`.
+    ///
+    /// # Returns
+    /// The new value, or `None` if the field isn't a number (or the key holds
+    /// something which isn't a hash).
+    pub fn hash_incr(&mut self, key: K, field: String, by: i64) -> Option<i64> {
+        let (mut fields, descriptor) = self.read_hash(&key)?;
+        let value = match fields.get(&field) {
+            Some(value) => String::from_utf8_lossy(value)
+                .parse::<i64>()
+                .ok()?
+                .checked_add(by)?,
+            None => by,
+        };
+
+        fields.insert(field, value.to_string().into_bytes());
+        self.write_hash(key, fields, descriptor);
+        Some(value)
+    }
+
+    /// If a field exists.
+    ///
+    /// # Returns
+    /// `None` if the key holds something which isn't a hash.
+    pub fn hash_exists(&self, key: &K, field: &str) -> Option<bool> {
+        Some(self.read_hash(key)?.0.contains_key(field))
+    }
+
+    /// Get the number of fields in a hash (`0` if it doesn't exist).
+    ///
+    /// # Returns
+    /// `None` if the key holds something which isn't a hash.
+    pub fn hash_len(&self, key: &K) -> Option<usize> {
+        Some(self.read_hash(key)?.0.len())
+    }
+}
+
+#[cfg(all(test, not(feature = "sqlite_backend")))]
+mod test {
+    use crate::Oyster;
+    use crate::list::End;
+
+    #[test]
+    fn hash() {
+        let mut map: Oyster<String, String> = Oyster::new();
+        let key = "user:1".to_string();
+
+        assert_eq!(
+            map.hash_set(key.clone(), "name".to_string(), "a".to_string()),
+            Some(true)
+        );
+        assert_eq!(
+            map.hash_set(key.clone(), "name".to_string(), "b".to_string()),
+            Some(false)
+        );
+        assert_eq!(map.hash_get(&key, "name"), Some("b".to_string()));
+        assert_eq!(map.hash_get(&key, "missing"), None);
+
+        assert_eq!(map.hash_incr(key.clone(), "visits".to_string(), 2), Some(2));
+        assert_eq!(
+            map.hash_incr(key.clone(), "visits".to_string(), -5),
+            Some(-3)
+        );
+        assert_eq!(map.hash_incr(key.clone(), "name".to_string(), 1), None);
+        assert_eq!(map.hash_exists(&key, "visits"), Some(true));
+
+        let all = map.hash_get_all(&key).unwrap();
+        assert_eq!(all.keys().collect::<Vec<_>>(), ["name", "visits"]);
+        assert_eq!(all["visits"], "-3");
+
+        // empty hashes are removed
+        assert_eq!(map.hash_remove(&key, "name"), Some(true));
+        assert_eq!(map.hash_remove(&key, "name"), Some(false));
+        assert_eq!(map.hash_remove(&key, "visits"), Some(true));
+        assert_eq!(map.get(&key), None);
+        assert_eq!(map.hash_len(&key), Some(0));
+
+        // lists aren't hashes (and hashes aren't lists)
+        map.push("list".to_string(), vec!["a".to_string()], End::Back);
+        assert_eq!(map.hash_get_all(&"list".to_string()), None);
+        map.hash_set(key.clone(), "a".to_string(), "1".to_string());
+        assert_eq!(map.list_len(&key), None);
+    }
+}
@@ -0,0 +1,482 @@
+//! JSON documents, which can be read and changed one path at a time.
+//!
+//! A document is stored as its JSON text, with
+//! [`ResourceDescriptor::kind`](crate::pearl::ResourceDescriptor::kind) set to
+//! [`ValueKind::Json`]. Paths start at the root of the document (`$`), followed
+//! by fields (`.name`, or `["name"]` for fields with dots in them) and array
+//! indexes (`[0]`), e.g. `$.users[0].name`.
+//!
+//! Setting a path creates the objects it goes through if they don't exist, so
+//! `$.user.name` can be set in an empty document. Array indexes have to exist,
+//! except for the index right after the last item, which appends to the array.
+use crate::oyster::Oyster;
+use crate::pearl::{Pearl, ResourceDescriptor, ValueKind};
+use crate::value::Value;
+use serde_json::Value as Json;
+use std::cmp::Ordering;
+use std::hash::Hash;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A step of a [`JsonPath`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Step {
+    /// A field of an object.
+    Field(String),
+    /// An item of an array.
+    Index(usize),
+}
+
+/// A path to a value in a JSON document (e.g. `$.user.name`).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JsonPath(Vec<Step>);
+
+impl JsonPath {
+    /// Parse a path.
+    ///
+    /// # Returns
+    /// `None` if the path isn't valid.
+    pub fn parse(path: &str) -> Option<Self> {
+        let mut rest = path.strip_prefix('$')?;
+        let mut steps = Vec::new();
+
+        while !rest.is_empty() {
+            if let Some(x) = rest.strip_prefix('.') {
+                let end = x.find(['.', '[']).unwrap_or(x.len());
+                if end == 0 {
+                    return None;
+                }
+
+                steps.push(Step::Field(x[..end].to_string()));
+                rest = &x[end..];
+            } else if let Some(x) = rest.strip_prefix('[') {
+                let end = x.find(']')?;
+                let inner = &x[..end];
+                steps.push(
+                    match inner.strip_prefix('"').and_then(|x| x.strip_suffix('"')) {
+                        Some(field) => Step::Field(field.to_string()),
+                        None => Step::Index(inner.parse().ok()?),
+                    },
+                );
+                rest = &x[end + 1..];
+            } else {
+                return None;
+            }
+        }
+
+        Some(Self(steps))
+    }
+
+    /// If the path is the root of the document (`$`).
+    pub fn is_root(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Find the value at this path in `document`.
+    pub fn find<'a>(&self, document: &'a Json) -> Option<&'a Json> {
+        self.0.iter().try_fold(document, |x, step| match step {
+            Step::Field(field) => x.get(field.as_str()),
+            Step::Index(index) => x.get(*index),
+        })
+    }
+
+    /// Set the value at this path in `document`, creating the objects it goes
+    /// through if they don't exist.
+    ///
+    /// # Returns
+    /// If the value is new, or `None` if it can't be set (e.g. a step goes
+    /// through something which isn't an object or an array).
+    fn set(&self, document: &mut Json, value: Json) -> Option<bool> {
+        let Some((last, parents)) = self.0.split_last() else {
+            *document = value;
+            return Some(false);
+        };
+
+        let mut parent = document;
+        for step in parents {
+            parent = match step {
+                Step::Field(field) => {
+                    if parent.is_null() {
+                        *parent = Json::Object(Default::default());
+                    }
+
+                    parent
+                        .as_object_mut()?
+                        .entry(field.clone())
+                        .or_insert(Json::Null)
+                }
+                Step::Index(index) => parent.as_array_mut()?.get_mut(*index)?,
+            };
+        }
+
+        match last {
+            Step::Field(field) => {
+                if parent.is_null() {
+                    *parent = Json::Object(Default::default());
+                }
+
+                Some(
+                    parent
+                        .as_object_mut()?
+                        .insert(field.clone(), value)
+                        .is_none(),
+                )
+            }
+            Step::Index(index) => {
+                let array = parent.as_array_mut()?;
+                if *index == array.len() {
+                    array.push(value);
+                    return Some(true);
+                }
+
+                *array.get_mut(*index)? = value;
+                Some(false)
+            }
+        }
+    }
+
+    /// Remove the value at this path from `document` (the root can't be removed).
+    ///
+    /// # Returns
+    /// The removed value, or `None` if it doesn't exist.
+    fn remove(&self, document: &mut Json) -> Option<Json> {
+        let (last, parents) = self.0.split_last()?;
+        let mut parent = document;
+        for step in parents {
+            parent = match step {
+                Step::Field(field) => parent.get_mut(field.as_str())?,
+                Step::Index(index) => parent.get_mut(*index)?,
+            };
+        }
+
+        match last {
+            Step::Field(field) => parent.as_object_mut()?.shift_remove(field),
+            Step::Index(index) => {
+                let array = parent.as_array_mut()?;
+                (*index < array.len()).then(|| array.remove(*index))
+            }
+        }
+    }
+}
+
+/// How a value in a document is compared to another value (see
+/// [`Oyster::json_filter_keys`]).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum Comparison {
+    /// Equal to.
+    Eq,
+    /// Not equal to.
+    Ne,
+    /// Less than.
+    Lt,
+    /// Less than or equal to.
+    Le,
+    /// Greater than.
+    Gt,
+    /// Greater than or equal to.
+    Ge,
+}
+
+impl Comparison {
+    /// Compare `a` to `b`. Numbers are compared by value, and strings
+    /// alphabetically. Other values (or values of different types) are only
+    /// equal if they're the same.
+    pub fn matches(self, a: &Json, b: &Json) -> bool {
+        let ordering = match (a, b) {
+            (Json::Number(a), Json::Number(b)) => a.as_f64().partial_cmp(&b.as_f64()),
+            (Json::String(a), Json::String(b)) => Some(a.cmp(b)),
+            _ => None,
+        };
+
+        let equal = ordering == Some(Ordering::Equal) || a == b;
+        match self {
+            Self::Eq => equal,
+            Self::Ne => !equal,
+            Self::Lt => ordering == Some(Ordering::Less),
+            Self::Le => equal || ordering == Some(Ordering::Less),
+            Self::Gt => ordering == Some(Ordering::Greater),
+            Self::Ge => equal || ordering == Some(Ordering::Greater),
+        }
+    }
+}
+
+/// Add `by` to a number, keeping it an integer if both are integers.
+fn add(number: &Json, by: f64) -> Option<Json> {
+    if let Some(x) = number.as_i64()
+        && by.fract() == 0.0
+        && by.abs() < i64::MAX as f64
+    {
+        return Some(x.checked_add(by as i64)?.into());
+    }
+
+    serde_json::Number::from_f64(number.as_f64()? + by).map(Json::Number)
+}
+
+impl<K, V> Oyster<K, V>
+where
+    K: Hash + Ord + Clone + Send + ToString + From<String>,
+    V: Clone + Send + Value,
+{
+    /// Read the document stored in `key`.
+    ///
+    /// # Returns
+    /// The document (`None` if the key doesn't exist) and its descriptor, or
+    /// `None` if the key holds something which isn't a document.
+    fn read_json(&self, key: &K) -> Option<(Option<Json>, ResourceDescriptor)> {
+        match self.get_full(key) {
+            Some(pearl) if pearl.1.kind == ValueKind::Json => Some((
+                Some(serde_json::from_slice(&pearl.0.to_bytes()).ok()?),
+                pearl.1,
+            )),
+            Some(_) => None,
+            None => Some((
+                None,
+                ResourceDescriptor {
+                    kind: ValueKind::Json,
+                    ..Default::default()
+                },
+            )),
+        }
+    }
+
+    /// Store a document read by [`Self::read_json`] in `key` (keeping its expiry
+    /// time).
+    fn write_json(&mut self, key: K, document: &Json, mut descriptor: ResourceDescriptor) {
+        descriptor.kind = ValueKind::Json;
+        descriptor.touch();
+        let bytes = serde_json::to_vec(document).unwrap();
+        self.insert_full(key, Pearl(V::from_bytes(bytes), descriptor));
+    }
+
+    /// Get the value at a path of a document.
+    ///
+    /// # Arguments
+    /// * `key` - the key the document is stored in
+    /// * `path` - the path of the value
+    ///
+    /// # Returns
+    /// `None` if the path doesn't exist, or the key holds something which isn't a
+    /// document.
+    pub fn json_get(&self, key: &K, path: &JsonPath) -> Option<Json> {
+        let document = self.read_json(key)?.0?;
+        path.find(&document).cloned()
+    }
+
+    /// Set the value at a path of a document (which is created if it doesn't
+    /// exist). Setting the root (`$`) replaces whatever the key holds, like
+    /// [`Self::insert`].
+    ///
+    /// # Arguments
+    /// * `key` - the key the document is stored in
+    /// * `path` - the path of the value
+    /// * `value` - the value
+    ///
+    /// # Returns
+    /// If the value is new, or `None` if it can't be set (or the key holds
+    /// something which isn't a document).
+    pub fn json_set(&mut self, key: K, path: &JsonPath, value: Json) -> Option<bool> {
+        if path.is_root() {
+            let (existed, descriptor) = match self.read_json(&key) {
+                Some((document, descriptor)) => (document.is_some(), descriptor),
+                None => (true, ResourceDescriptor::default()),
+            };
+
+            self.write_json(key, &value, descriptor);
+            return Some(!existed);
+        }
+
+        let (document, descriptor) = self.read_json(&key)?;
+        let mut document = document.unwrap_or_default();
+        let created = path.set(&mut document, value)?;
+        self.write_json(key, &document, descriptor);
+        Some(created)
+    }
+
+    /// Remove the value at a path of a document. Removing the root (`$`) removes
+    /// the whole document.
+    ///
+    /// # Returns
+    /// If the value existed, or `None` if the key holds something which isn't a
+    /// document.
+    pub fn json_remove(&mut self, key: &K, path: &JsonPath) -> Option<bool> {
+        let (document, descriptor) = self.read_json(key)?;
+        let Some(mut document) = document else {
+            return Some(false);
+        };
+
+        if path.is_root() {
+            self.remove(key);
+            return Some(true);
+        }
+
+        if path.remove(&mut document).is_none() {
+            return Some(false);
+        }
+
+        self.write_json(key.clone(), &document, descriptor);
+        Some(true)
+    }
+
+    /// Add `by` to the number at a path of a document (which is `0` if it doesn't
+    /// exist). Integers stay integers if `by` is an integer.
+    ///
+    /// # Returns
+    /// The new number, or `None` if the value isn't a number (or the key holds
+    /// something which isn't a document).
+    pub fn json_incr(&mut self, key: K, path: &JsonPath, by: f64) -> Option<Json> {
+        let (document, _) = self.read_json(&key)?;
+        let current = document
+            .as_ref()
+            .and_then(|x| path.find(x))
+            .cloned()
+            .unwrap_or(Json::from(0));
+
+        let value = add(&current, by)?;
+        self.json_set(key, path, value.clone())?;
+        Some(value)
+    }
+
+    /// Get the keys of every document matching `pattern` (see [`Self::filter`])
+    /// where the value at `path` compares to `value` (e.g. `$.age` is greater than
+    /// `30`). Documents without a value at `path` never match.
+    ///
+    /// # Arguments
+    /// * `pattern` - the pattern to match keys against
+    /// * `path` - the path of the value to compare
+    /// * `comparison` - how to compare it
+    /// * `value` - what to compare it to
+    pub fn json_filter_keys(
+        &self,
+        pattern: &str,
+        path: &JsonPath,
+        comparison: Comparison,
+        value: &Json,
+    ) -> Vec<K> {
+        self.filter(pattern)
+            .into_iter()
+            .filter(|(_, pearl)| pearl.1.kind == ValueKind::Json)
+            .filter_map(|(key, pearl)| {
+                let document: Json = serde_json::from_slice(&pearl.0.to_bytes()).ok()?;
+                comparison
+                    .matches(path.find(&document)?, value)
+                    .then_some(key)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Comparison, JsonPath, Step};
+    use serde_json::json;
+
+    #[test]
+    fn paths() {
+        let field = |x: &str| Step::Field(x.to_string());
+        assert_eq!(JsonPath::parse("$"), Some(JsonPath(vec![])));
+        assert_eq!(
+            JsonPath::parse("$.users[1].name"),
+            Some(JsonPath(vec![
+                field("users"),
+                Step::Index(1),
+                field("name")
+            ]))
+        );
+        assert_eq!(
+            JsonPath::parse("$[\"a.b\"].c"),
+            Some(JsonPath(vec![field("a.b"), field("c")]))
+        );
+        assert_eq!(JsonPath::parse("users.name"), None);
+        assert_eq!(JsonPath::parse("$..name"), None);
+        assert_eq!(JsonPath::parse("$.a[x]"), None);
+        assert_eq!(JsonPath::parse("$.a[0"), None);
+    }
+
+    #[test]
+    fn comparisons() {
+        assert!(Comparison::Eq.matches(&json!(1), &json!(1.0)));
+        assert!(Comparison::Gt.matches(&json!(31), &json!(30)));
+        assert!(Comparison::Le.matches(&json!("a"), &json!("b")));
+        assert!(Comparison::Ne.matches(&json!("1"), &json!(1)));
+        assert!(!Comparison::Lt.matches(&json!("1"), &json!(2)));
+        assert!(Comparison::Eq.matches(&json!({"a": [1]}), &json!({"a": [1]})));
+    }
+
+    #[cfg(not(feature = "sqlite_backend"))]
+    #[test]
+    fn documents() {
+        use crate::Oyster;
+
+        let mut map: Oyster<String, String> = Oyster::new();
+        let path = |x: &str| JsonPath::parse(x).unwrap();
+        let key = "user:1".to_string();
+
+        // objects are created on the way
+        assert_eq!(
+            map.json_set(key.clone(), &path("$.name"), json!("a")),
+            Some(true)
+        );
+        assert_eq!(
+            map.json_set(key.clone(), &path("$.address.city"), json!("b")),
+            Some(true)
+        );
+        assert_eq!(
+            map.json_get(&key, &path("$")),
+            Some(json!({"name": "a", "address": {"city": "b"}}))
+        );
+
+        // arrays can be appended to, but not past the end
+        map.json_set(key.clone(), &path("$.tags"), json!(["x"]));
+        assert_eq!(
+            map.json_set(key.clone(), &path("$.tags[1]"), json!("y")),
+            Some(true)
+        );
+        assert_eq!(
+            map.json_set(key.clone(), &path("$.tags[5]"), json!("z")),
+            None
+        );
+        assert_eq!(map.json_get(&key, &path("$.tags[1]")), Some(json!("y")));
+        assert_eq!(
+            map.json_set(key.clone(), &path("$.name.first"), json!(1)),
+            None
+        );
+
+        assert_eq!(
+            map.json_incr(key.clone(), &path("$.visits"), 2.0),
+            Some(json!(2))
+        );
+        assert_eq!(
+            map.json_incr(key.clone(), &path("$.visits"), 0.5),
+            Some(json!(2.5))
+        );
+        assert_eq!(map.json_incr(key.clone(), &path("$.name"), 1.0), None);
+
+        assert_eq!(map.json_remove(&key, &path("$.tags[0]")), Some(true));
+        assert_eq!(map.json_get(&key, &path("$.tags")), Some(json!(["y"])));
+        assert_eq!(map.json_remove(&key, &path("$.missing")), Some(false));
+
+        map.json_set("user:2".to_string(), &path("$"), json!({"visits": 1}));
+        map.json_set("user:3".to_string(), &path("$"), json!({"name": "c"}));
+        map.insert("user:4".to_string(), "{\"visits\": 10}".to_string());
+        assert_eq!(
+            map.json_filter_keys("user:", &path("$.visits"), Comparison::Ge, &json!(2)),
+            vec![key.clone()]
+        );
+
+        // setting the root replaces any value, but other paths don't
+        assert_eq!(
+            map.json_set("user:4".to_string(), &path("$.a"), json!(1)),
+            None
+        );
+        assert_eq!(
+            map.json_set("user:4".to_string(), &path("$"), json!(1)),
+            Some(false)
+        );
+
+        assert_eq!(map.json_remove(&key, &path("$")), Some(true));
+        assert_eq!(map.get(&key), None);
+    }
+}
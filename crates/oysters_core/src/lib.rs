@@ -1,6 +1,23 @@
+pub mod compression;
+pub mod events;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod hash;
+#[cfg(feature = "json")]
+pub mod json;
+pub mod list;
+pub mod metrics;
 pub mod oyster;
 pub mod pagemap;
 pub mod pearl;
+pub mod set;
+pub mod sorted_set;
+pub mod value;
+
+mod collection;
+
+#[cfg(any(feature = "persistance", feature = "sqlite_backend"))]
+mod db;
 
 #[cfg(feature = "sqlite_backend")]
 pub mod sqlite_backend;
@@ -12,7 +29,8 @@ mod time;
 mod lru;
 
 #[cfg(feature = "persistance")]
-mod persistance;
+pub mod persistance;
 
-pub use oyster::Oyster;
-pub use pearl::Pearl;
+pub use oyster::{Oyster, OysterOptions};
+pub use pearl::{Pearl, ValueKind};
+pub use value::Value;
@@ -0,0 +1,265 @@
+//! Lists (which can be used as queues).
+//!
+//! A list is stored like any other value, so it can expire, is removed by scans,
+//! and is dumped and restored with everything else. Its
+//! [`ResourceDescriptor::kind`](crate::pearl::ResourceDescriptor::kind) is
+//! [`ValueKind::List`]. Lists are kept as a [`VecDeque`] in memory (see
+//! [`crate::collection`]), so both ends can be pushed to and popped from without
+//! moving the other items, and their value is every item
+//! [packed](crate::value) together when they're read whole. Lists are created by
+//! pushing to them, and removed once they're empty.
+use crate::collection::Collection;
+use crate::oyster::Oyster;
+use crate::pearl::ValueKind;
+use crate::value::Value;
+use std::collections::VecDeque;
+use std::hash::Hash;
+use std::ops::Range;
+
+#[cfg(not(feature = "sqlite_backend"))]
+use crate::collection::Stored;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// An end of a list.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum End {
+    /// The first item.
+    Front,
+    /// The last item.
+    Back,
+}
+
+/// The items of a list.
+pub(crate) type Items = VecDeque<Vec<u8>>;
+
+impl Collection for Items {
+    const KIND: ValueKind = ValueKind::List;
+
+    fn from_items(items: Vec<Vec<u8>>) -> Option<Self> {
+        Some(items.into())
+    }
+
+    fn to_items(&self) -> Vec<Vec<u8>> {
+        self.iter().cloned().collect()
+    }
+
+    #[cfg(not(feature = "sqlite_backend"))]
+    fn is_empty(&self) -> bool {
+        VecDeque::is_empty(self)
+    }
+
+    #[cfg(not(feature = "sqlite_backend"))]
+    fn memory_usage(&self) -> usize {
+        self.iter()
+            .map(|x| x.capacity() + std::mem::size_of::<Vec<u8>>())
+            .sum()
+    }
+
+    #[cfg(not(feature = "sqlite_backend"))]
+    fn stored(stored: &Stored) -> Option<&Self> {
+        match stored {
+            Stored::List(x) => Some(x),
+            _ => None,
+        }
+    }
+
+    #[cfg(not(feature = "sqlite_backend"))]
+    fn stored_mut(stored: &mut Stored) -> Option<&mut Self> {
+        match stored {
+            Stored::List(x) => Some(x),
+            _ => None,
+        }
+    }
+
+    #[cfg(not(feature = "sqlite_backend"))]
+    fn into_stored(self) -> Stored {
+        Stored::List(self)
+    }
+}
+
+/// Get the items between `start` and `stop` (both included) of a list with `len`
+/// items. Negative positions count from the end of the list (`-1` is the last
+/// item).
+pub(crate) fn span(len: usize, start: isize, stop: isize) -> Range<usize> {
+    let resolve = |x: isize| {
+        if x < 0 {
+            len.saturating_sub(x.unsigned_abs())
+        } else {
+            (x as usize).min(len)
+        }
+    };
+
+    let start = resolve(start);
+    let stop = if stop < 0 && stop.unsigned_abs() > len {
+        return 0..0;
+    } else {
+        resolve(stop).saturating_add(1).min(len)
+    };
+
+    start..stop.max(start)
+}
+
+impl<K, V> Oyster<K, V>
+where
+    K: Hash + Ord + Clone + Send + ToString + From<String>,
+    V: Clone + Send + Value,
+{
+    /// Push values to an end of a list (which is created if it doesn't exist).
+    /// Values pushed to the front end up in reverse order.
+    ///
+    /// # Arguments
+    /// * `key` - the key the list is stored in
+    /// * `values` - the values to push
+    /// * `end` - the end to push them to
+    ///
+    /// # Returns
+    /// The length of the list, or `None` if the key holds something which isn't a list.
+    pub fn push(&mut self, key: K, values: Vec<V>, end: End) -> Option<usize> {
+        self.update_collection(key, |items: &mut Items| {
+            let changed = !values.is_empty();
+            for value in values {
+                match end {
+                    End::Front => items.push_front(value.to_bytes()),
+                    End::Back => items.push_back(value.to_bytes()),
+                }
+            }
+
+            (items.len(), changed)
+        })
+    }
+
+    /// Pop a value from an end of a list.
+    ///
+    /// # Arguments
+    /// * `key` - the key the list is stored in
+    /// * `end` - the end to pop from
+    ///
+    /// # Returns
+    /// `None` if the list is empty, or the key holds something which isn't a list.
+    pub fn pop(&mut self, key: &K, end: End) -> Option<V> {
+        let item = self.update_collection(key.clone(), |items: &mut Items| {
+            let item = match end {
+                End::Front => items.pop_front(),
+                End::Back => items.pop_back(),
+            };
+
+            let changed = item.is_some();
+            (item, changed)
+        })?;
+
+        Some(V::from_bytes(item?))
+    }
+
+    /// Get the values between two positions of a list (see [`Self::trim`] for how
+    /// positions work).
+    ///
+    /// # Arguments
+    /// * `key` - the key the list is stored in
+    /// * `start` - the position of the first value
+    /// * `stop` - the position of the last value
+    ///
+    /// # Returns
+    /// `None` if the key holds something which isn't a list.
+    pub fn range(&self, key: &K, start: isize, stop: isize) -> Option<Vec<V>> {
+        self.read_collection(key, |items: &Items| {
+            let span = span(items.len(), start, stop);
+            items.range(span).cloned().map(V::from_bytes).collect()
+        })
+    }
+
+    /// Remove every value of a list which isn't between two positions. Both
+    /// positions are included, and negative positions count from the end of the
+    /// list (`-1` is the last value).
+    ///
+    /// # Arguments
+    /// * `key` - the key the list is stored in
+    /// * `start` - the position of the first value to keep
+    /// * `stop` - the position of the last value to keep
+    ///
+    /// # Returns
+    /// The length of the list, or `None` if the key holds something which isn't a list.
+    pub fn trim(&mut self, key: &K, start: isize, stop: isize) -> Option<usize> {
+        self.update_collection(key.clone(), |items: &mut Items| {
+            let span = span(items.len(), start, stop);
+            let changed = span.len() < items.len();
+
+            items.truncate(span.end);
+            items.drain(..span.start);
+            (items.len(), changed)
+        })
+    }
+
+    /// Get the length of a list (`0` if it doesn't exist).
+    ///
+    /// # Returns
+    /// `None` if the key holds something which isn't a list.
+    pub fn list_len(&self, key: &K) -> Option<usize> {
+        self.read_collection(key, |items: &Items| items.len())
+    }
+}
+
+#[cfg(all(test, not(feature = "sqlite_backend")))]
+mod test {
+    use super::{End, span};
+    use crate::Oyster;
+
+    #[test]
+    fn spans() {
+        assert_eq!(span(5, 0, -1), 0..5);
+        assert_eq!(span(5, 1, 2), 1..3);
+        assert_eq!(span(5, -2, -1), 3..5);
+        assert_eq!(span(5, 3, 1), 3..3);
+        assert_eq!(span(5, 0, 100), 0..5);
+        assert_eq!(span(5, 0, -6), 0..0);
+        assert_eq!(span(0, 0, -1), 0..0);
+    }
+
+    #[test]
+    fn list() {
+        let mut map: Oyster<String, String> = Oyster::new();
+        let key = "queue".to_string();
+        let strings = |x: &[&str]| x.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(
+            map.push(key.clone(), strings(&["b", "c"]), End::Back),
+            Some(2)
+        );
+        assert_eq!(
+            map.push(key.clone(), strings(&["a", "z"]), End::Front),
+            Some(4)
+        );
+        assert_eq!(map.range(&key, 0, -1), Some(strings(&["z", "a", "b", "c"])));
+        // read whole, the list is packed
+        assert_eq!(map.get(&key), Some("1:z1:a1:b1:c".to_string()));
+
+        assert_eq!(map.pop(&key, End::Front), Some("z".to_string()));
+        assert_eq!(map.pop(&key, End::Back), Some("c".to_string()));
+        assert_eq!(map.trim(&key, 1, -1), Some(1));
+        assert_eq!(map.range(&key, 0, -1), Some(strings(&["b"])));
+
+        // empty lists are removed
+        map.expire(&key, 60_000);
+        assert_eq!(map.pop(&key, End::Back), Some("b".to_string()));
+        assert_eq!(map.get(&key), None);
+        assert_eq!(map.pop(&key, End::Back), None);
+        assert_eq!(map.list_len(&key), Some(0));
+
+        // pushing keeps the expiry time
+        map.push(key.clone(), strings(&["a"]), End::Back);
+        map.expire(&key, 60_000);
+        map.push(key.clone(), strings(&["b"]), End::Back);
+        assert!(map.ttl(&key).unwrap().is_some());
+
+        // other values aren't lists
+        map.insert("plain".to_string(), "1".to_string());
+        assert_eq!(
+            map.push("plain".to_string(), strings(&["a"]), End::Back),
+            None
+        );
+        assert_eq!(map.list_len(&"plain".to_string()), None);
+    }
+}
@@ -1,17 +1,34 @@
+use crate::events::EventKind;
 use crate::oyster::Oyster;
-use crate::pearl::{EPOCH_YEAR, ResourceDescriptor};
+use crate::pearl::EPOCH_YEAR;
 use crate::time::epoch_timestamp;
+use crate::value::Value;
 use std::hash::Hash;
 
+#[cfg(not(feature = "sqlite_backend"))]
+use crate::collection::Stored;
+#[cfg(feature = "sqlite_backend")]
+use crate::db::quote;
 #[cfg(not(feature = "sqlite_backend"))]
 use crate::pearl::Pearl;
+#[cfg(feature = "sqlite_backend")]
+use crate::sqlite_backend::delete_returning_keys;
 #[cfg(not(feature = "sqlite_backend"))]
 use std::collections::HashMap;
 
+/// Get the time an item expires at if it's given `ttl` milliseconds from `now`.
+///
+/// # Returns
+/// `None` if the time doesn't fit in an SQLite integer (which is how it's stored
+/// in the dump file).
+fn expires_at(now: usize, ttl: usize) -> Option<usize> {
+    now.checked_add(ttl).filter(|x| i64::try_from(*x).is_ok())
+}
+
 impl<K, V> Oyster<K, V>
 where
     K: Hash + Ord + Clone + Send + ToString + From<String>,
-    V: Clone + Send + ToString + From<String>,
+    V: Clone + Send + Value,
 {
     /// Update the resource descriptor of an item. This method assumes that the item
     /// exists already, and thus will panic if it doesn't.
@@ -20,31 +37,113 @@ where
     /// * `key` - the key the item is stored in
     #[cfg(not(feature = "sqlite_backend"))]
     pub fn update_resource_descriptor(&mut self, key: &K) {
-        let item = self.0.get_mut(key).unwrap();
-        item.1 = ResourceDescriptor::default();
+        self.0.get_mut(key).unwrap().1.touch();
+    }
+
+    /// Set the time (in milliseconds from now) after which an item expires.
+    ///
+    /// # Arguments
+    /// * `key` - the key the item is stored in
+    /// * `ttl` - the number of milliseconds the item should live for
+    ///
+    /// # Returns
+    /// `false` if the item doesn't exist, or `ttl` is too long (see [`expires_at`]).
+    #[cfg(not(feature = "sqlite_backend"))]
+    pub fn expire(&mut self, key: &K, ttl: usize) -> bool {
+        let Some(expires) = expires_at(epoch_timestamp(EPOCH_YEAR), ttl) else {
+            return false;
+        };
+
+        match self.0.get_mut(key) {
+            Some(item) if !item.1.is_expired() => {
+                item.1.expires = Some(expires);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Make an item never expire.
+    ///
+    /// # Arguments
+    /// * `key` - the key the item is stored in
+    ///
+    /// # Returns
+    /// `false` if the item doesn't exist.
+    #[cfg(not(feature = "sqlite_backend"))]
+    pub fn persist(&mut self, key: &K) -> bool {
+        match self.0.get_mut(key) {
+            Some(item) if !item.1.is_expired() => {
+                item.1.expires = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Get the time (in milliseconds) until an item expires.
+    ///
+    /// # Arguments
+    /// * `key` - the key the item is stored in
+    ///
+    /// # Returns
+    /// `None` if the item doesn't exist, `Some(None)` if it never expires.
+    #[cfg(not(feature = "sqlite_backend"))]
+    pub fn ttl(&self, key: &K) -> Option<Option<usize>> {
+        let item = self.0.get(key).filter(|x| !x.1.is_expired())?;
+        Some(
+            item.1
+                .expires
+                .map(|x| x.saturating_sub(epoch_timestamp(EPOCH_YEAR))),
+        )
     }
 
     /// [`Self::scan_sync`] backend.
+    ///
+    /// # Arguments
+    /// * `map` - the items to scan
+    /// * `max_age` - see [`OysterOptions::max_age`](crate::OysterOptions::max_age)
+    ///
+    /// # Returns
+    /// The keys of the items removed, and why they were removed
+    /// ([`EventKind::Expire`] or [`EventKind::Evict`]).
     #[cfg(not(feature = "sqlite_backend"))]
-    pub fn scan_with(map: &mut HashMap<K, Pearl<V>>) {
+    pub(crate) fn scan_with(
+        map: &mut HashMap<K, Pearl<Stored>>,
+        max_age: Option<usize>,
+    ) -> Vec<(K, EventKind)> {
         let now = epoch_timestamp(EPOCH_YEAR);
-        const MAXIMUM_AGE: usize = 604800000; // 7 days
+        let mut removed = Vec::new();
 
         let clone = map.clone();
         for (k, item) in &clone {
-            if (now - item.1.used) > MAXIMUM_AGE {
-                map.remove(k);
-            }
+            let kind = if item.1.is_expired() {
+                EventKind::Expire
+            } else if max_age.is_some_and(|x| now.saturating_sub(item.1.used) > x) {
+                EventKind::Evict
+            } else {
+                continue;
+            };
+
+            map.remove(k);
+            removed.push((k.clone(), kind));
         }
         drop(clone);
+
+        removed
     }
 
     /// Scan the entire map for outdated items (and remove them).
     #[cfg(not(feature = "sqlite_backend"))]
     pub fn scan_sync(&mut self) {
         let mut clone = self.0.clone();
-        Oyster::scan_with(&mut clone);
+        let removed = Self::scan_with(&mut clone, self.2.max_age);
         self.0 = clone;
+
+        self.count_scan(&removed);
+        for (key, kind) in removed {
+            self.notify(kind, &key);
+        }
     }
 
     /// Update the resource descriptor of an item. This method assumes that the item
@@ -55,34 +154,134 @@ where
     #[cfg(feature = "sqlite_backend")]
     pub fn update_resource_descriptor(&mut self, key: &K) {
         let conn = crate::sqlite_backend::connect().unwrap();
-        let rd = ResourceDescriptor::default();
 
         conn.execute(
-            "UPDATE \"map\" SET \"used\" = ? WHERE \"key\" = ?",
-            (rd.used, key.to_string()),
+            &format!(
+                "UPDATE {} SET \"used\" = ? WHERE \"key\" = ?",
+                quote(&self.2.table)
+            ),
+            (epoch_timestamp(EPOCH_YEAR), key.to_string()),
         )
         .expect("failed to update");
     }
 
+    /// Set the time (in milliseconds from now) after which an item expires.
+    ///
+    /// # Arguments
+    /// * `key` - the key the item is stored in
+    /// * `ttl` - the number of milliseconds the item should live for
+    ///
+    /// # Returns
+    /// `false` if the item doesn't exist, or `ttl` is too long (see [`expires_at`]).
+    #[cfg(feature = "sqlite_backend")]
+    pub fn expire(&mut self, key: &K, ttl: usize) -> bool {
+        let now = epoch_timestamp(EPOCH_YEAR);
+        let Some(expires) = expires_at(now, ttl) else {
+            return false;
+        };
+
+        let conn = crate::sqlite_backend::connect().unwrap();
+        conn.execute(
+            &format!("UPDATE {} SET \"expires\" = ? WHERE \"key\" = ? AND (\"expires\" IS NULL OR \"expires\" > ?)", quote(&self.2.table)),
+            (expires, key.to_string(), now),
+        )
+        .expect("failed to update")
+            > 0
+    }
+
+    /// Make an item never expire.
+    ///
+    /// # Arguments
+    /// * `key` - the key the item is stored in
+    ///
+    /// # Returns
+    /// `false` if the item doesn't exist.
+    #[cfg(feature = "sqlite_backend")]
+    pub fn persist(&mut self, key: &K) -> bool {
+        let conn = crate::sqlite_backend::connect().unwrap();
+
+        conn.execute(
+            &format!("UPDATE {} SET \"expires\" = NULL WHERE \"key\" = ? AND (\"expires\" IS NULL OR \"expires\" > ?)", quote(&self.2.table)),
+            (key.to_string(), epoch_timestamp(EPOCH_YEAR)),
+        )
+        .expect("failed to update")
+            > 0
+    }
+
+    /// Get the time (in milliseconds) until an item expires.
+    ///
+    /// # Arguments
+    /// * `key` - the key the item is stored in
+    ///
+    /// # Returns
+    /// `None` if the item doesn't exist, `Some(None)` if it never expires.
+    #[cfg(feature = "sqlite_backend")]
+    pub fn ttl(&self, key: &K) -> Option<Option<usize>> {
+        let item = self.get_full(key)?;
+        Some(
+            item.1
+                .expires
+                .map(|x| x.saturating_sub(epoch_timestamp(EPOCH_YEAR))),
+        )
+    }
+
     /// [`Self::scan_sync`] backend.
+    ///
+    /// # Returns
+    /// The keys of the items removed, and why they were removed
+    /// ([`EventKind::Expire`] or [`EventKind::Evict`]).
     #[cfg(feature = "sqlite_backend")]
-    pub fn scan_with(&self) {
+    pub fn scan_with(&self) -> Vec<(K, EventKind)> {
         let conn = crate::sqlite_backend::connect().unwrap();
 
         let now = epoch_timestamp(EPOCH_YEAR);
-        const MAXIMUM_AGE: usize = 604800000; // 7 days
+        let table = quote(&self.2.table);
 
-        conn.execute(
-            &format!("DELETE FROM \"map\" WHERE (used - {now}) > {MAXIMUM_AGE}"),
+        let mut removed: Vec<(K, EventKind)> = delete_returning_keys(
+            &conn,
+            &format!("DELETE FROM {table} WHERE \"expires\" <= {now}"),
             (),
         )
-        .expect("failed to update");
+        .expect("failed to update")
+        .into_iter()
+        .map(|x| (x.into(), EventKind::Expire))
+        .collect();
+
+        if let Some(max_age) = self.2.max_age {
+            removed.extend(
+                delete_returning_keys(
+                    &conn,
+                    &format!("DELETE FROM {table} WHERE ({now} - \"used\") > {max_age}"),
+                    (),
+                )
+                .expect("failed to update")
+                .into_iter()
+                .map(|x| (x.into(), EventKind::Evict)),
+            );
+        }
+
+        removed
     }
 
     /// Scan the entire map for outdated items (and remove them).
     #[cfg(feature = "sqlite_backend")]
     pub fn scan_sync(&mut self) {
-        self.scan_with();
+        let removed = self.scan_with();
+
+        self.count_scan(&removed);
+        for (key, kind) in removed {
+            self.notify(kind, &key);
+        }
+    }
+
+    /// Count a scan which removed the given items in the map's metrics.
+    fn count_scan(&self, removed: &[(K, EventKind)]) {
+        let expired = removed
+            .iter()
+            .filter(|(_, kind)| *kind == EventKind::Expire)
+            .count();
+
+        self.4.scanned(expired, removed.len() - expired);
     }
 
     /// [`Self::scan_sync`] but async.
@@ -90,3 +289,112 @@ where
         self.scan_sync();
     }
 }
+
+#[cfg(all(test, not(feature = "sqlite_backend")))]
+mod test {
+    use crate::events::EventKind;
+    use crate::{Oyster, OysterOptions};
+
+    #[test]
+    fn expire() {
+        let mut map: Oyster<String, String> = Oyster::new();
+        let key = "a".to_string();
+
+        assert!(!map.expire(&key, 1000));
+        assert_eq!(map.ttl(&key), None);
+
+        map.insert(key.clone(), "1".to_string());
+        assert_eq!(map.ttl(&key), Some(None));
+
+        assert!(map.expire(&key, 60_000));
+        assert_eq!(map.incr(key.clone()).unwrap(), 2); // keeps the expiry time
+        assert!(map.ttl(&key).unwrap().unwrap() > 59_000);
+        assert_eq!(map.get(&key), Some("2".to_string()));
+
+        // expiry times have to fit in the dump file
+        assert!(!map.expire(&key, usize::MAX));
+        assert!(!map.expire(&key, i64::MAX as usize));
+        assert!(map.ttl(&key).unwrap().unwrap() > 59_000);
+
+        map.expire(&key, 0);
+        assert_eq!(map.get(&key), None);
+        assert!(map.filter_keys("*").is_empty());
+
+        map.scan_sync();
+        assert!(map.0.is_empty());
+
+        let metrics = map.metrics();
+        assert_eq!((metrics.scans, metrics.expirations), (1, 1));
+        assert_eq!(metrics.misses, 1);
+    }
+
+    #[test]
+    fn capacity() {
+        let mut map: Oyster<String, String> = Oyster::with_options(OysterOptions {
+            capacity: Some(2),
+            max_age: None,
+            ..Default::default()
+        });
+
+        map.insert("a".to_string(), "1".to_string());
+        map.insert("b".to_string(), "2".to_string());
+        map.0.get_mut("a").unwrap().1.used += 1; // `a` was used more recently
+
+        map.insert("c".to_string(), "3".to_string());
+        assert_eq!(map.get(&"b".to_string()), None);
+        assert_eq!(map.filter_keys("*").len(), 2);
+
+        // changing an existing key doesn't evict anything
+        map.insert("a".to_string(), "4".to_string());
+        assert_eq!(map.filter_keys("*").len(), 2);
+
+        // nothing is too old without a maximum age
+        map.0.get_mut("a").unwrap().1.used = 0;
+        map.scan_sync();
+        assert_eq!(map.get(&"a".to_string()), Some("4".to_string()));
+        assert_eq!(map.metrics().evictions, 1);
+        assert_eq!(map.metrics().expirations, 0);
+    }
+
+    #[test]
+    fn events() {
+        let mut map: Oyster<String, String> = Oyster::with_options(OysterOptions {
+            capacity: Some(2),
+            max_age: Some(60_000),
+            ..Default::default()
+        });
+        let mut events = map.subscribe();
+
+        map.insert("a".to_string(), "1".to_string());
+        map.insert("b".to_string(), "2".to_string());
+        map.0.get_mut("b").unwrap().1.used += 1; // `b` was used more recently
+        map.insert("c".to_string(), "3".to_string()); // evicts `a`
+        map.expire(&"b".to_string(), 0);
+        map.0.get_mut("c").unwrap().1.used = 0;
+        map.scan_sync();
+
+        let mut received = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            received.push((event.kind, event.key));
+        }
+
+        // scans go through the map in no particular order
+        received[4..].sort();
+        assert_eq!(
+            received,
+            [
+                (EventKind::Insert, "a".to_string()),
+                (EventKind::Insert, "b".to_string()),
+                (EventKind::Evict, "a".to_string()),
+                (EventKind::Insert, "c".to_string()),
+                (EventKind::Expire, "b".to_string()),
+                (EventKind::Evict, "c".to_string()),
+            ]
+        );
+
+        // keys evicted by the scan aren't counted as expired
+        let metrics = map.metrics();
+        assert_eq!((metrics.evictions, metrics.scan_evictions), (1, 1));
+        assert_eq!((metrics.expirations, metrics.last_scan_removed), (1, 2));
+    }
+}
@@ -0,0 +1,205 @@
+//! Counters describing what an [`Oyster`](crate::Oyster) has been doing.
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Counters for a single map. Every counter only ever goes up (except the ones
+/// describing the last dump).
+#[derive(Debug, Default)]
+pub struct Metrics {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+    expirations: AtomicU64,
+    scan_evictions: AtomicU64,
+    scans: AtomicU64,
+    dumps: AtomicU64,
+    last_dump_micros: AtomicU64,
+    last_dump_bytes: AtomicU64,
+    last_dump_at: AtomicU64,
+    last_dump_error: Mutex<Option<String>>,
+    last_scan_at: AtomicU64,
+    last_scan_removed: AtomicU64,
+}
+
+/// The current time (in milliseconds since the Unix epoch).
+#[cfg(any(feature = "lru", feature = "persistance"))]
+fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|x| x.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// A snapshot of [`Metrics`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MetricsReport {
+    /// Reads which found a value.
+    pub hits: u64,
+    /// Reads which didn't find a value.
+    pub misses: u64,
+    /// Items removed to make room for new items (see
+    /// [`OysterOptions::capacity`](crate::OysterOptions::capacity)).
+    pub evictions: u64,
+    /// Expired items removed by scans.
+    pub expirations: u64,
+    /// Items removed by scans because they went unused for too long (see
+    /// [`OysterOptions::max_age`](crate::OysterOptions::max_age)).
+    pub scan_evictions: u64,
+    /// The number of scans.
+    pub scans: u64,
+    /// The number of dumps.
+    pub dumps: u64,
+    /// How long the last dump took (in microseconds).
+    pub last_dump_micros: u64,
+    /// The size of the dump file after the last dump (in bytes).
+    pub last_dump_bytes: u64,
+    /// When the last dump finished (in milliseconds since the Unix epoch, `0` if
+    /// the map was never dumped).
+    pub last_dump_at: u64,
+    /// Why the last dump failed (`None` if it didn't).
+    pub last_dump_error: Option<String>,
+    /// When the last scan finished (in milliseconds since the Unix epoch, `0` if
+    /// the map was never scanned).
+    pub last_scan_at: u64,
+    /// The number of items removed by the last scan.
+    pub last_scan_removed: u64,
+}
+
+/// A summary of a map (see [`Oyster::stats`](crate::Oyster::stats)).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Stats {
+    /// Where the items are kept (`memory`, or `sqlite` with the `sqlite_backend`
+    /// feature).
+    pub backend: String,
+    /// The number of items (including expired items which haven't been removed
+    /// by a scan yet).
+    pub keys: usize,
+    /// Approximately how much memory (in bytes) the items use.
+    pub memory_bytes: usize,
+    /// The most common key prefixes (the part of the key before the first `:`)
+    /// and how many keys have them, most common first. Keys without a `:`
+    /// aren't counted.
+    pub prefixes: Vec<(String, usize)>,
+    /// What the map has been doing.
+    pub metrics: MetricsReport,
+}
+
+impl Metrics {
+    /// Count a read.
+    pub(crate) fn lookup(&self, found: bool) {
+        if found {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Count items removed to make room for new items.
+    pub(crate) fn evicted(&self, count: usize) {
+        self.evictions.fetch_add(count as u64, Ordering::Relaxed);
+    }
+
+    /// Count a scan (and the items it removed).
+    ///
+    /// # Arguments
+    /// * `expired` - the number of expired items removed
+    /// * `evicted` - the number of items removed because they went unused for
+    ///   too long
+    #[cfg(feature = "lru")]
+    pub(crate) fn scanned(&self, expired: usize, evicted: usize) {
+        self.scans.fetch_add(1, Ordering::Relaxed);
+        self.expirations
+            .fetch_add(expired as u64, Ordering::Relaxed);
+        self.scan_evictions
+            .fetch_add(evicted as u64, Ordering::Relaxed);
+        self.last_scan_removed
+            .store((expired + evicted) as u64, Ordering::Relaxed);
+        self.last_scan_at.store(now(), Ordering::Relaxed);
+    }
+
+    /// Count a dump (and whether it failed).
+    #[cfg(feature = "persistance")]
+    pub(crate) fn dumped(&self, took: Duration, bytes: u64, error: Option<String>) {
+        self.dumps.fetch_add(1, Ordering::Relaxed);
+        self.last_dump_micros
+            .store(took.as_micros() as u64, Ordering::Relaxed);
+        self.last_dump_bytes.store(bytes, Ordering::Relaxed);
+        self.last_dump_at.store(now(), Ordering::Relaxed);
+        *self.last_dump_error.lock().unwrap() = error;
+    }
+
+    /// Take a snapshot of the metrics.
+    pub fn report(&self) -> MetricsReport {
+        MetricsReport {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            expirations: self.expirations.load(Ordering::Relaxed),
+            scan_evictions: self.scan_evictions.load(Ordering::Relaxed),
+            scans: self.scans.load(Ordering::Relaxed),
+            dumps: self.dumps.load(Ordering::Relaxed),
+            last_dump_micros: self.last_dump_micros.load(Ordering::Relaxed),
+            last_dump_bytes: self.last_dump_bytes.load(Ordering::Relaxed),
+            last_dump_at: self.last_dump_at.load(Ordering::Relaxed),
+            last_dump_error: self.last_dump_error.lock().unwrap().clone(),
+            last_scan_at: self.last_scan_at.load(Ordering::Relaxed),
+            last_scan_removed: self.last_scan_removed.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Count how many of the given `keys` have each prefix (see [`Stats::prefixes`]),
+/// keeping the `top` most common prefixes.
+#[cfg(not(feature = "sqlite_backend"))]
+pub(crate) fn count_prefixes(
+    keys: impl Iterator<Item = String>,
+    top: usize,
+) -> Vec<(String, usize)> {
+    let mut counts: std::collections::HashMap<String, usize> = std::collections::HashMap::new();
+    for key in keys {
+        if let Some((prefix, _)) = key.split_once(':') {
+            *counts.entry(prefix.to_string()).or_default() += 1;
+        }
+    }
+
+    let mut counts: Vec<(String, usize)> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    counts.truncate(top);
+    counts
+}
+
+impl MetricsReport {
+    /// How long the last dump took.
+    pub fn last_dump_duration(&self) -> Duration {
+        Duration::from_micros(self.last_dump_micros)
+    }
+}
+
+#[cfg(all(test, not(feature = "sqlite_backend")))]
+mod test {
+    use crate::Oyster;
+
+    #[test]
+    fn stats() {
+        let mut map: Oyster<String, String> = Oyster::new();
+        for key in ["a:1", "a:2", "a:3", "b:1", "c:1", "c:2", "plain"] {
+            map.insert(key.to_string(), "x".to_string());
+        }
+
+        map.get(&"a:1".to_string());
+        map.get(&"missing".to_string());
+
+        let stats = map.stats(2);
+        assert_eq!(stats.backend, "memory");
+        assert_eq!(stats.keys, 7);
+        assert!(stats.memory_bytes > 0);
+        assert_eq!(stats.prefixes, [("a".to_string(), 3), ("c".to_string(), 2)]);
+        assert_eq!((stats.metrics.hits, stats.metrics.misses), (1, 1));
+    }
+}
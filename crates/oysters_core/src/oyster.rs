@@ -1,42 +1,298 @@
+use crate::collection::Collection;
+use crate::compression::{Compression, CompressionReport};
+use crate::events::{Event, EventKind};
+use crate::metrics::{Metrics, MetricsReport, Stats};
+use crate::pearl::{Pearl, ResourceDescriptor, ValueKind};
+use crate::value::{Value, pack, unpack};
 use std::hash::Hash;
-
-#[cfg(feature = "sqlite_backend")]
 use std::marker::PhantomData;
+use tokio::sync::broadcast;
 
 #[cfg(not(feature = "sqlite_backend"))]
-use crate::pearl::Pearl;
+use crate::collection::Stored;
+#[cfg(not(feature = "sqlite_backend"))]
+use crate::events;
+#[cfg(not(feature = "sqlite_backend"))]
+use crate::pearl::next_cas;
 #[cfg(not(feature = "sqlite_backend"))]
-use std::collections::{HashMap, hash_map::IntoIter};
+use crate::value::{add_to_number, missing_number};
+#[cfg(not(feature = "sqlite_backend"))]
+use std::collections::HashMap;
 
+/// General options for an [`Oyster`].
+#[derive(Clone, Debug)]
+pub struct OysterOptions {
+    /// How values are compressed (in memory, and in the dump file).
+    pub compression: Compression,
+    /// The name of the table the map is stored in (in the dump file, and the
+    /// staging database with the `sqlite_backend` feature). Every map sharing a
+    /// dump file needs its own table.
+    pub table: String,
+    /// The maximum number of items in the map (`None` for no limit). The least
+    /// recently used item is evicted to make room for new items.
+    pub capacity: Option<usize>,
+    /// How long (in milliseconds) an item can go unused before it's removed by a
+    /// scan (`None` to keep unused items forever).
+    pub max_age: Option<usize>,
+}
+
+impl Default for OysterOptions {
+    fn default() -> Self {
+        Self {
+            compression: Compression::default(),
+            table: "map".to_string(),
+            capacity: None,
+            max_age: Some(604800000), // 7 days
+        }
+    }
+}
+
+/// The map itself.
+///
+/// Values are stored encoded (see [`crate::compression`]), and are decoded again
+/// whenever they're read. This means reads always return owned values. Lists,
+/// sets and sorted sets are kept as data structures instead (see [`crate::collection`]).
 #[derive(Debug)]
 pub struct Oyster<K, V>(
-    #[cfg(not(feature = "sqlite_backend"))] pub(crate) HashMap<K, Pearl<V>>,
+    #[cfg(not(feature = "sqlite_backend"))] pub(crate) HashMap<K, Pearl<Stored>>,
     #[cfg(feature = "sqlite_backend")] pub(crate) PhantomData<K>,
-    #[cfg(feature = "sqlite_backend")] pub(crate) PhantomData<V>,
+    pub(crate) PhantomData<V>,
+    pub(crate) OysterOptions,
+    pub(crate) broadcast::Sender<Event>,
+    pub(crate) Metrics,
 )
 where
     K: Hash + Ord + Clone + Send + ToString + From<String>,
-    V: Clone + Send + ToString + From<String>;
+    V: Clone + Send + Value;
 
 impl<K, V> Default for Oyster<K, V>
 where
     K: Hash + Ord + Clone + Send + ToString + From<String>,
-    V: Clone + Send + ToString + From<String>,
+    V: Clone + Send + Value,
 {
     fn default() -> Self {
         Self::new()
     }
 }
 
+impl<K, V> Oyster<K, V>
+where
+    K: Hash + Ord + Clone + Send + ToString + From<String>,
+    V: Clone + Send + Value,
+{
+    /// The options of the map.
+    pub fn options(&self) -> &OysterOptions {
+        &self.2
+    }
+
+    /// Get statistics about how well values are being compressed.
+    pub fn compression_stats(&self) -> CompressionReport {
+        self.2.compression.stats.report()
+    }
+
+    /// Get the counters describing what the map has been doing (see
+    /// [`crate::metrics`]).
+    pub fn metrics(&self) -> MetricsReport {
+        self.4.report()
+    }
+
+    /// Get a summary of the map, with the `top` most common key prefixes (see
+    /// [`Stats::prefixes`]).
+    pub fn stats(&self, top: usize) -> Stats {
+        Stats {
+            backend: if cfg!(feature = "sqlite_backend") {
+                "sqlite"
+            } else {
+                "memory"
+            }
+            .to_string(),
+            keys: self.len(),
+            memory_bytes: self.memory_usage(),
+            prefixes: self.prefixes(top),
+            metrics: self.metrics(),
+        }
+    }
+
+    /// Encode a value so it can be stored (see [`OysterOptions::compression`]).
+    #[cfg(any(feature = "persistance", feature = "sqlite_backend"))]
+    pub(crate) fn encode(&self, value: &V) -> Vec<u8> {
+        self.2.compression.compress(&value.to_bytes())
+    }
+
+    /// Subscribe to changes to the map (see [`crate::events`]).
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.3.subscribe()
+    }
+
+    /// Tell every subscriber about a change to `key`.
+    pub(crate) fn notify(&self, kind: EventKind, key: &K) {
+        // sending only fails if nobody is subscribed
+        let _ = self.3.send(Event {
+            kind,
+            key: key.to_string(),
+        });
+    }
+
+    /// Decode a value created by [`Self::encode`].
+    #[cfg(feature = "sqlite_backend")]
+    pub(crate) fn decode(&self, bytes: &[u8]) -> Option<V> {
+        Some(V::from_bytes(self.2.compression.decompress(bytes).ok()?))
+    }
+
+    /// Read the collection of the given `kind` stored in `key` (an empty
+    /// collection if the key doesn't exist). Collections are stored as their
+    /// items [packed](crate::value) together.
+    ///
+    /// # Returns
+    /// `None` if the key holds something of another kind.
+    pub(crate) fn read_packed(
+        &self,
+        kind: ValueKind,
+        key: &K,
+    ) -> Option<(Vec<Vec<u8>>, ResourceDescriptor)> {
+        match self.get_full(key) {
+            Some(pearl) if pearl.1.kind == kind => Some((unpack(&pearl.0.to_bytes())?, pearl.1)),
+            Some(_) => None,
+            None => Some((
+                Vec::new(),
+                ResourceDescriptor {
+                    kind,
+                    ..Default::default()
+                },
+            )),
+        }
+    }
+
+    /// Store a collection read by [`Self::read_packed`] in `key` (keeping its
+    /// expiry time), or remove the key if the collection is empty.
+    pub(crate) fn write_packed(
+        &mut self,
+        key: K,
+        items: &[Vec<u8>],
+        mut descriptor: ResourceDescriptor,
+    ) {
+        if items.is_empty() {
+            self.remove(&key);
+            return;
+        }
+
+        descriptor.touch();
+        self.insert_full(key, Pearl(V::from_bytes(pack(items)), descriptor));
+    }
+
+    /// Read the collection stored in `key` (see [`crate::collection`]), or an
+    /// empty collection if the key doesn't exist.
+    ///
+    /// # Arguments
+    /// * `key` - the key the collection is stored in
+    /// * `f` - what to do with the collection
+    ///
+    /// # Returns
+    /// What `f` returned, or `None` if the key holds something of another kind.
+    #[cfg(feature = "sqlite_backend")]
+    pub(crate) fn read_collection<C: Collection, R>(
+        &self,
+        key: &K,
+        f: impl FnOnce(&C) -> R,
+    ) -> Option<R> {
+        let (items, _) = self.read_packed(C::KIND, key)?;
+        Some(f(&C::from_items(items)?))
+    }
+
+    /// Change the collection stored in `key` (see [`crate::collection`]). It's
+    /// created if it doesn't exist, keeps its expiry time, and is removed once
+    /// it's empty.
+    ///
+    /// # Arguments
+    /// * `key` - the key the collection is stored in
+    /// * `f` - what to do with the collection (returning whatever it wants to
+    ///   return, and if it changed the collection)
+    ///
+    /// # Returns
+    /// What `f` returned, or `None` if the key holds something of another kind.
+    #[cfg(feature = "sqlite_backend")]
+    pub(crate) fn update_collection<C: Collection, R>(
+        &mut self,
+        key: K,
+        f: impl FnOnce(&mut C) -> (R, bool),
+    ) -> Option<R> {
+        let (items, descriptor) = self.read_packed(C::KIND, &key)?;
+        let mut collection = C::from_items(items)?;
+        let (out, changed) = f(&mut collection);
+
+        if changed {
+            self.write_packed(key, &collection.to_items(), descriptor);
+        }
+
+        Some(out)
+    }
+}
+
 #[cfg(not(feature = "sqlite_backend"))]
 impl<K, V> Oyster<K, V>
 where
     K: Hash + Ord + Clone + Send + ToString + From<String>,
-    V: Clone + Send + ToString + From<String>,
+    V: Clone + Send + Value,
 {
     /// Create a new [`Oyster`].
     pub fn new() -> Self {
-        Self(HashMap::default())
+        Self::with_options(OysterOptions::default())
+    }
+
+    /// Create a new [`Oyster`] with the given `options`.
+    pub fn with_options(options: OysterOptions) -> Self {
+        Self(
+            HashMap::default(),
+            PhantomData,
+            options,
+            events::channel(),
+            Metrics::default(),
+        )
+    }
+
+    /// Decode a stored [`Pearl`].
+    pub(crate) fn decode_pearl(&self, pearl: &Pearl<Stored>) -> Option<Pearl<V>> {
+        let bytes = pearl.0.bytes(&self.2.compression)?;
+        Some(Pearl(V::from_bytes(bytes), pearl.1.clone()))
+    }
+
+    /// Get a stored [`Pearl`] given its `key`, as long as it hasn't expired.
+    ///
+    /// Expired items stay in the map until they're removed by a scan, but they
+    /// can't be read.
+    fn live(&self, key: &K) -> Option<&Pearl<Stored>> {
+        self.0.get(key).filter(|x| !x.1.is_expired())
+    }
+
+    /// The number of items in the map (including expired items which haven't
+    /// been removed by a scan yet).
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// If the map has no items.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Estimate how much memory (in bytes) the items in the map use.
+    pub fn memory_usage(&self) -> usize {
+        self.0
+            .iter()
+            .map(|(k, v)| {
+                k.to_string().len() + v.0.memory_usage() + std::mem::size_of::<(K, Pearl<Stored>)>()
+            })
+            .sum()
+    }
+
+    /// Count the `top` most common key prefixes (see [`Stats::prefixes`]).
+    pub fn prefixes(&self, top: usize) -> Vec<(String, usize)> {
+        crate::metrics::count_prefixes(self.0.keys().map(|x| x.to_string()), top)
+    }
+
+    /// Iterate over every item which hasn't expired.
+    fn live_iter(&self) -> impl Iterator<Item = (&K, &Pearl<Stored>)> {
+        self.0.iter().filter(|x| !x.1.1.is_expired())
     }
 
     /// Insert a value given its `key` and `value`.
@@ -45,8 +301,7 @@ where
     /// * `key` - the key to store the value in
     /// * `value` - the actual value
     pub fn insert(&mut self, key: K, value: V) -> Option<Pearl<V>> {
-        let v = Pearl::new(value);
-        self.0.insert(key, v)
+        self.insert_full(key, Pearl::new(value))
     }
 
     /// Insert a value given its `key` and `value`.
@@ -55,72 +310,202 @@ where
     /// * `key` - the key to store the value in
     /// * `value` - the actual value (as [`Pearl<V>`])
     pub fn insert_full(&mut self, key: K, value: Pearl<V>) -> Option<Pearl<V>> {
+        let stored = Stored::new(value.1.kind, &value.0.to_bytes(), &self.2.compression);
+        let old = self.insert_stored(key, Pearl(stored, value.1))?;
+        self.decode_pearl(&old)
+    }
+
+    /// [`Self::insert_full`] backend (for a value which is already stored).
+    fn insert_stored(&mut self, key: K, mut value: Pearl<Stored>) -> Option<Pearl<Stored>> {
+        if let Some(capacity) = self.2.capacity
+            && !self.0.contains_key(&key)
+        {
+            while self.0.len() >= capacity.max(1) {
+                self.evict();
+                self.4.evicted(1);
+            }
+        }
+
+        value.1.cas = next_cas();
+        self.notify(EventKind::Insert, &key);
         self.0.insert(key, value)
     }
 
-    /// Increment the value of a key.
+    /// Read the collection stored in `key` (see [`crate::collection`]), or an
+    /// empty collection if the key doesn't exist.
     ///
     /// # Arguments
-    /// * `key` - the key to store the value in
-    pub fn incr(&mut self, key: K) -> Option<Pearl<V>> {
-        let value = self.get(&key)?.to_string().parse::<usize>().unwrap();
-        self.insert(key, (value + 1).to_string().into())
+    /// * `key` - the key the collection is stored in
+    /// * `f` - what to do with the collection
+    ///
+    /// # Returns
+    /// What `f` returned, or `None` if the key holds something of another kind.
+    pub(crate) fn read_collection<C: Collection, R>(
+        &self,
+        key: &K,
+        f: impl FnOnce(&C) -> R,
+    ) -> Option<R> {
+        let found = self.live(key);
+        self.4.lookup(found.is_some());
+
+        match found {
+            Some(pearl) if pearl.1.kind == C::KIND => Some(f(C::stored(&pearl.0)?)),
+            Some(_) => None,
+            None => Some(f(&C::default())),
+        }
     }
 
-    /// Decrement the value of a key.
+    /// Change the collection stored in `key` (see [`crate::collection`]). It's
+    /// created if it doesn't exist, keeps its expiry time, and is removed once
+    /// it's empty.
     ///
     /// # Arguments
-    /// * `key` - the key to store the value in
-    pub fn decr(&mut self, key: K) -> Option<Pearl<V>> {
-        let mut value = self.get(&key)?.to_string().parse::<usize>().unwrap();
+    /// * `key` - the key the collection is stored in
+    /// * `f` - what to do with the collection (returning whatever it wants to
+    ///   return, and if it changed the collection)
+    ///
+    /// # Returns
+    /// What `f` returned, or `None` if the key holds something of another kind.
+    pub(crate) fn update_collection<C: Collection, R>(
+        &mut self,
+        key: K,
+        f: impl FnOnce(&mut C) -> (R, bool),
+    ) -> Option<R> {
+        let found = self.0.get_mut(&key).filter(|x| !x.1.is_expired());
+        self.4.lookup(found.is_some());
+
+        let Some(pearl) = found else {
+            let mut collection = C::default();
+            let (out, changed) = f(&mut collection);
+
+            if changed && !collection.is_empty() {
+                let descriptor = ResourceDescriptor {
+                    kind: C::KIND,
+                    ..Default::default()
+                };
+                self.insert_stored(key, Pearl(collection.into_stored(), descriptor));
+            }
+
+            return Some(out);
+        };
+
+        if pearl.1.kind != C::KIND {
+            return None;
+        }
+
+        let collection = C::stored_mut(&mut pearl.0)?;
+        let (out, changed) = f(collection);
+        if !changed {
+            return Some(out);
+        }
 
-        if value == 0 {
-            value += 1; // this will make the value just end up as 0
+        if collection.is_empty() {
+            self.remove(&key);
+        } else {
+            pearl.1.touch();
+            pearl.1.cas = next_cas();
+            self.notify(EventKind::Insert, &key);
+        }
+
+        Some(out)
+    }
+
+    /// Insert a value, but only if it hasn't been changed since it was read
+    /// (compare-and-swap).
+    ///
+    /// # Arguments
+    /// * `key` - the key to store the value in
+    /// * `cas` - the [`ResourceDescriptor::cas`](crate::pearl::ResourceDescriptor::cas)
+    ///   of the value when it was read
+    /// * `value` - the actual value (as [`Pearl<V>`])
+    ///
+    /// # Returns
+    /// `None` if the value doesn't exist, `Some(false)` if it has been changed.
+    pub fn compare_and_swap(&mut self, key: K, cas: u64, value: Pearl<V>) -> Option<bool> {
+        if self.live(&key)?.1.cas != cas {
+            return Some(false);
         }
 
-        self.insert(key, (value - 1).to_string().into())
+        self.insert_full(key, value);
+        Some(true)
+    }
+
+    /// Increment the number (a signed 64-bit integer) stored in a key. The key
+    /// keeps its expiry time.
+    ///
+    /// # Arguments
+    /// * `key` - the key the number is stored in
+    ///
+    /// # Returns
+    /// The new number. Fails with [`ErrorKind::NotFound`](std::io::ErrorKind)
+    /// if the key doesn't exist, `InvalidData` if it doesn't hold a number, or
+    /// `InvalidInput` if the number would overflow.
+    pub fn incr(&mut self, key: K) -> std::io::Result<i64> {
+        self.add(key, 1)
+    }
+
+    /// Decrement the number stored in a key (see [`Self::incr`]).
+    ///
+    /// # Arguments
+    /// * `key` - the key the number is stored in
+    pub fn decr(&mut self, key: K) -> std::io::Result<i64> {
+        self.add(key, -1)
+    }
+
+    /// [`Self::incr`] and [`Self::decr`] backend.
+    fn add(&mut self, key: K, delta: i64) -> std::io::Result<i64> {
+        let mut pearl = self.get_full(&key).ok_or_else(missing_number)?;
+        let number = add_to_number(&mut pearl, delta)?;
+        self.insert_full(key, pearl);
+        Ok(number)
     }
 
     /// Get a value given its `key`.
     ///
     /// # Arguments
     /// * `key` - the key the value is stored in
-    pub fn get(&self, key: &K) -> Option<&V> {
-        if let Some(v) = self.0.get(key) {
-            Some(&v.0)
-        } else {
-            None
-        }
+    pub fn get(&self, key: &K) -> Option<V> {
+        let found = self.live(key);
+        self.4.lookup(found.is_some());
+        Some(V::from_bytes(found?.0.bytes(&self.2.compression)?))
     }
 
     /// Get a full [`Pearl`] given its `key`.
     ///
     /// # Arguments
     /// * `key` - the key the value is stored in
-    pub fn get_full(&self, key: &K) -> Option<&Pearl<V>> {
-        self.0.get(key)
+    pub fn get_full(&self, key: &K) -> Option<Pearl<V>> {
+        let found = self.live(key);
+        self.4.lookup(found.is_some());
+        self.decode_pearl(found?)
     }
 
     /// Get all items where their key starts with the given `prefix`.
     ///
     /// # Arguments
     /// * `prefix` - the prefix to match keys against
-    pub fn starting_with(&self, prefix: &str) -> Vec<(&K, &Pearl<V>)> {
+    pub fn starting_with(&self, prefix: &str) -> Vec<(K, Pearl<V>)> {
         let matches = self
-            .0
-            .iter()
+            .live_iter()
             .filter(|x| x.0.to_string().starts_with(prefix));
 
-        matches.collect()
+        matches
+            .filter_map(|(k, v)| Some((k.clone(), self.decode_pearl(v)?)))
+            .collect()
     }
 
     /// Get all items where their key ends with the given `prefix`.
     ///
     /// # Arguments
     /// * `suffix` - the suffix to match keys against
-    pub fn ending_with(&self, suffix: &str) -> Vec<(&K, &Pearl<V>)> {
-        let matches = self.0.iter().filter(|x| x.0.to_string().ends_with(suffix));
-        matches.collect()
+    pub fn ending_with(&self, suffix: &str) -> Vec<(K, Pearl<V>)> {
+        let matches = self
+            .live_iter()
+            .filter(|x| x.0.to_string().ends_with(suffix));
+
+        matches
+            .filter_map(|(k, v)| Some((k.clone(), self.decode_pearl(v)?)))
+            .collect()
     }
 
     /// Selects either [`Self::starting_with`] or [`Self::ending_with`], depending on if the given
@@ -128,7 +513,7 @@ where
     ///
     /// # Arguments
     /// * `pattern` - the pattern to match keys against
-    pub fn filter(&self, pattern: &str) -> Vec<(&K, &Pearl<V>)> {
+    pub fn filter(&self, pattern: &str) -> Vec<(K, Pearl<V>)> {
         let pat = &pattern.replace("*", "");
         if pattern.starts_with("*") {
             self.ending_with(pat)
@@ -142,8 +527,11 @@ where
     /// # Arguments
     /// * `prefix` - the prefix to match keys against
     pub fn starting_with_keys(&self, prefix: &str) -> Vec<&K> {
-        let matches = self.0.keys().filter(|x| x.to_string().starts_with(prefix));
-        matches.collect()
+        let matches = self
+            .live_iter()
+            .filter(|x| x.0.to_string().starts_with(prefix));
+
+        matches.map(|x| x.0).collect()
     }
 
     /// Get all keys which end with the given `prefix`.
@@ -151,8 +539,11 @@ where
     /// # Arguments
     /// * `suffix` - the suffix to match keys against
     pub fn ending_with_keys(&self, suffix: &str) -> Vec<&K> {
-        let matches = self.0.keys().filter(|x| x.to_string().ends_with(suffix));
-        matches.collect()
+        let matches = self
+            .live_iter()
+            .filter(|x| x.0.to_string().ends_with(suffix));
+
+        matches.map(|x| x.0).collect()
     }
 
     /// Selects either [`Self::starting_with_keys`] or [`Self::ending_with_keys`], depending on if the given
@@ -169,15 +560,40 @@ where
         }
     }
 
+    /// Remove the item which should go first to make room for a new one (expired
+    /// items, then the least recently used item).
+    fn evict(&mut self) {
+        #[cfg(feature = "lru")]
+        let key = self
+            .0
+            .iter()
+            .min_by_key(|x| (!x.1.1.is_expired(), x.1.1.used))
+            .map(|x| x.0.clone());
+        #[cfg(not(feature = "lru"))]
+        let key = self.0.keys().next().cloned();
+
+        if let Some(key) = key {
+            self.remove_as(&key, EventKind::Evict);
+        }
+    }
+
     /// Remove a value given its `key`.
     ///
     /// # Arguments
     /// * `key` - the key the value is stored in
     pub fn remove(&mut self, key: &K) -> Option<Pearl<V>> {
+        self.remove_as(key, EventKind::Remove)
+    }
+
+    /// [`Self::remove`], telling subscribers the key was removed for the given
+    /// `kind` of reason.
+    fn remove_as(&mut self, key: &K, kind: EventKind) -> Option<Pearl<V>> {
         #[cfg(feature = "persistance")]
         self.remove_from_db(key).unwrap();
 
-        self.0.remove(key)
+        let old = self.0.remove(key)?;
+        self.notify(kind, key);
+        self.decode_pearl(&old)
     }
 }
 
@@ -185,12 +601,15 @@ where
 impl<K, V> IntoIterator for Oyster<K, V>
 where
     K: Hash + Ord + Clone + Send + ToString + From<String>,
-    V: Clone + Send + ToString + From<String>,
+    V: Clone + Send + Value,
 {
     type Item = (K, Pearl<V>);
-    type IntoIter = IntoIter<K, Pearl<V>>;
+    type IntoIter = std::vec::IntoIter<(K, Pearl<V>)>;
 
     fn into_iter(self) -> Self::IntoIter {
-        self.0.into_iter()
+        self.live_iter()
+            .filter_map(|(k, v)| Some((k.clone(), self.decode_pearl(v)?)))
+            .collect::<Vec<_>>()
+            .into_iter()
     }
 }
@@ -0,0 +1,110 @@
+//! The manifest stored next to dumped pages.
+//!
+//! The manifest is a plain text file:
+//!
+//! ```text
+//! oysters-pagemap 1
+//! page_size 16000
+//! pages 2
+//! keys 517
+//! page 0 3fa1c2d0
+//! page 1 0b9e4411
+//! ```
+//!
+//! Each `page` line holds the CRC32 of that page's file, so corrupt (or partially
+//! written) pages can be detected when the map is loaded.
+use std::io::{Error, ErrorKind, Result};
+
+/// The first line of every manifest.
+pub const HEADER: &str = "oysters-pagemap 1";
+/// The name of the manifest file.
+pub const FILE_NAME: &str = "manifest";
+
+/// Metadata about dumped pages.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Manifest {
+    /// The size of each page.
+    pub page_size: usize,
+    /// The number of keys across all pages.
+    pub keys: usize,
+    /// The CRC32 of each page file (in page order).
+    pub checksums: Vec<u32>,
+}
+
+/// Create an [`ErrorKind::InvalidData`] error.
+pub(crate) fn invalid(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+impl Manifest {
+    /// Compute the checksum of a page.
+    pub fn checksum(page: &[u8]) -> u32 {
+        crc32fast::hash(page)
+    }
+
+    /// Read a manifest from its text.
+    pub fn read(contents: &str) -> Result<Self> {
+        let mut lines = contents.lines();
+
+        if lines.next() != Some(HEADER) {
+            return Err(invalid("manifest has an unknown header".to_string()));
+        }
+
+        let mut manifest = Self::default();
+        let mut pages: Option<usize> = None;
+
+        for line in lines {
+            let mut parts = line.split_whitespace();
+            let field = parts.next();
+            let parse = |x: Option<&str>| -> Result<usize> {
+                x.and_then(|x| x.parse().ok())
+                    .ok_or_else(|| invalid(format!("malformed manifest line: {line}")))
+            };
+
+            match field {
+                Some("page_size") => manifest.page_size = parse(parts.next())?,
+                Some("pages") => pages = Some(parse(parts.next())?),
+                Some("keys") => manifest.keys = parse(parts.next())?,
+                Some("page") => {
+                    let page = parse(parts.next())?;
+                    let checksum = parts
+                        .next()
+                        .and_then(|x| u32::from_str_radix(x, 16).ok())
+                        .ok_or_else(|| invalid(format!("malformed manifest line: {line}")))?;
+
+                    if page != manifest.checksums.len() {
+                        return Err(invalid(format!("manifest is missing page {page}")));
+                    }
+
+                    manifest.checksums.push(checksum);
+                }
+                None => continue,
+                Some(_) => return Err(invalid(format!("malformed manifest line: {line}"))),
+            }
+        }
+
+        if pages != Some(manifest.checksums.len()) {
+            return Err(invalid(
+                "manifest page count doesn't match its pages".to_string(),
+            ));
+        }
+
+        Ok(manifest)
+    }
+
+    /// Write the manifest into its text.
+    pub fn write(&self) -> String {
+        let mut out = format!(
+            "{HEADER}\npage_size {}\npages {}\nkeys {}\n",
+            self.page_size,
+            self.checksums.len(),
+            self.keys
+        );
+
+        for (i, checksum) in self.checksums.iter().enumerate() {
+            out.push_str(&format!("page {i} {checksum:08x}\n"));
+        }
+
+        out
+    }
+}
@@ -1,6 +1,14 @@
 use pathbufd::PathBufD;
 
-use super::pagebook::PageBook;
+use super::manifest::{self, Manifest, invalid};
+use super::pagebook::{PageBook, PageStats};
+#[cfg(feature = "mmap")]
+use super::store::MmapPages;
+use super::store::PageStore;
+use crate::compression::{Compression, CompressionReport};
+use crate::value::Value;
+use std::fs;
+use std::io::Result;
 use std::marker::PhantomData;
 
 /// General options for a [`PageMap`].
@@ -9,28 +17,87 @@ pub struct PageMapOptions {
     pub pages: usize,
     /// The size of each page in the map.
     pub page_size: usize,
+    /// Automatically compact pages where more than this much (`0.0` to `1.0`) of the
+    /// page is taken up by removed records. `None` means pages are only compacted
+    /// when [`PageMap::compact`] is called.
+    pub compact_threshold: Option<f32>,
+    /// How values are compressed before they're written into a page.
+    pub compression: Compression,
 }
 
 /// A map which stores data as literal bytes split across multiple pages of the defined size.
-pub struct PageMap<K, V>
+pub struct PageMap<K, V, S = Vec<Vec<u8>>>
 where
     K: Ord + Clone + Send + ToString + From<String>,
-    V: Ord + Clone + Send + ToString + From<String>,
+    V: Ord + Clone + Send + Value,
+    S: PageStore,
 {
     /// The pagebook stores pages in the map.
-    pub pagebook: PageBook,
+    pub pagebook: PageBook<S>,
     /// The general options of the map.
     pub options: PageMapOptions,
     /// Phantom data. Should always be `None`.
     _phantoms: Option<(PhantomData<K>, PhantomData<V>)>,
 }
 
+impl<K, V, S> PageMap<K, V, S>
+where
+    K: Ord + Clone + Send + ToString + From<String>,
+    V: Ord + Clone + Send + Value,
+    S: PageStore,
+{
+    /// [`PageBook::get`]
+    pub fn get(&self, key: &K) -> Option<V> {
+        let x = self.pagebook.get(key.to_string().as_bytes())?;
+        Some(V::from_bytes(self.options.compression.decompress(&x).ok()?))
+    }
+
+    /// [`PageBook::insert`]
+    pub fn insert(&mut self, key: K, value: V) -> Result<()> {
+        // check if the value already exists
+        if self.get(&key).is_some() {
+            return Ok(());
+        }
+
+        // insert
+        let value = self.options.compression.compress(&value.to_bytes());
+
+        self.pagebook.insert(key.to_string().as_bytes(), &value)
+    }
+
+    /// [`PageBook::remove`]
+    pub fn remove(&mut self, key: &K) -> Option<()> {
+        self.pagebook.remove(key.to_string().as_bytes())?;
+
+        if let Some(threshold) = self.options.compact_threshold {
+            self.pagebook.compact_if(threshold);
+        }
+
+        Some(())
+    }
+
+    /// [`PageBook::compact`]
+    pub fn compact(&mut self) {
+        self.pagebook.compact()
+    }
+
+    /// [`PageBook::stats`]
+    pub fn stats(&self) -> Vec<PageStats> {
+        self.pagebook.stats()
+    }
+
+    /// Get statistics about how well values are being compressed.
+    pub fn compression_stats(&self) -> CompressionReport {
+        self.options.compression.stats.report()
+    }
+}
+
 impl<K, V> PageMap<K, V>
 where
     K: Ord + Clone + Send + ToString + From<String>,
-    V: Ord + Clone + Send + ToString + From<String>,
+    V: Ord + Clone + Send + Value,
 {
-    /// Create a new [`PageMap`].
+    /// Create a new [`PageMap`] (with pages stored in memory).
     pub fn new(options: PageMapOptions) -> Self {
         Self {
             pagebook: PageBook::new(options.pages, options.page_size),
@@ -39,39 +106,295 @@ where
         }
     }
 
-    /// [`PageBook::get`]
-    pub fn get(&self, key: &K) -> Option<V> {
-        let x = self.pagebook.get(key.to_string().as_bytes());
+    /// Write a single page into the given directory at `path`.
+    ///
+    /// Pages are written to a temporary file first, so a crash while writing can't
+    /// leave a half-written page behind.
+    fn write_page(&self, path: &PathBufD, page: usize) -> Result<()> {
+        let tmp = path.join(format!("{page}.page.tmp"));
+        fs::write(&tmp, &self.pagebook.0[page])?;
+        fs::rename(&tmp, path.join(format!("{page}.page")))
+    }
+
+    /// Write the manifest (see [`manifest`]) into the given directory at `path`.
+    fn write_manifest(&self, path: &PathBufD) -> Result<()> {
+        let manifest = Manifest {
+            page_size: self.options.page_size,
+            keys: self.pagebook.1.2,
+            checksums: self
+                .pagebook
+                .0
+                .iter()
+                .map(|x| Manifest::checksum(x))
+                .collect(),
+        };
 
-        if let Some(v) = x {
-            let string = String::from_utf8(v).unwrap();
-            Some(string.into())
-        } else {
-            None
+        let tmp = path.join(format!("{}.tmp", manifest::FILE_NAME));
+        fs::write(&tmp, manifest.write())?;
+        fs::rename(&tmp, path.join(manifest::FILE_NAME))
+    }
+
+    /// Dump every page of the map (and its manifest) into the given directory at `path`.
+    pub fn dump(&mut self, path: PathBufD) -> Result<()> {
+        fs::create_dir_all(&path)?;
+
+        for page in 0..self.pagebook.0.len() {
+            self.write_page(&path, page)?;
         }
+
+        self.write_manifest(&path)?;
+        self.pagebook.clear_dirty();
+
+        Ok(())
     }
 
-    /// [`PageBook::insert`]
-    pub fn insert(&mut self, key: K, value: V) -> () {
-        // check if the value already exists
-        if self.get(&key).is_some() {
-            return;
+    /// Write only the pages which have changed since the last [`Self::dump`] or
+    /// [`Self::flush`] (and the manifest) into the given directory at `path`.
+    ///
+    /// # Returns
+    /// The number of pages which were written.
+    pub fn flush(&mut self, path: PathBufD) -> Result<usize> {
+        fs::create_dir_all(&path)?;
+        let dirty = self.pagebook.dirty_pages();
+
+        for page in &dirty {
+            self.write_page(&path, *page)?;
         }
 
-        // insert
-        self.pagebook
-            .insert(key.to_string().as_bytes(), value.to_string().as_bytes())
+        self.write_manifest(&path)?;
+        self.pagebook.clear_dirty();
+
+        Ok(dirty.len())
     }
 
-    /// [`PageBook::remove`]
-    pub fn remove(&mut self, key: &K) -> Option<()> {
-        self.pagebook.remove(key.to_string().as_bytes())
+    /// Load a map which was dumped into the given directory at `path`.
+    ///
+    /// Values are decompressed using the given `compression` (this only matters for
+    /// values compressed with a zstd dictionary, every other codec is detected
+    /// automatically).
+    ///
+    /// Fails with [`std::io::ErrorKind::InvalidData`] if the manifest is malformed,
+    /// or if any page doesn't match the checksum in the manifest.
+    pub fn load(path: PathBufD, compression: Compression) -> Result<Self> {
+        let manifest = Manifest::read(&fs::read_to_string(path.join(manifest::FILE_NAME))?)?;
+        let mut pages = Vec::with_capacity(manifest.checksums.len());
+
+        for (i, checksum) in manifest.checksums.iter().enumerate() {
+            let page = fs::read(path.join(format!("{i}.page")))?;
+
+            if Manifest::checksum(&page) != *checksum {
+                return Err(invalid(format!("page {i} doesn't match its checksum")));
+            }
+
+            pages.push(page);
+        }
+
+        let pagebook = PageBook::from_pages(pages, manifest.page_size);
+
+        if pagebook.1.2 != manifest.keys {
+            return Err(invalid(format!(
+                "expected {} keys, found {}",
+                manifest.keys, pagebook.1.2
+            )));
+        }
+
+        Ok(Self {
+            options: PageMapOptions {
+                pages: pagebook.1.0,
+                page_size: manifest.page_size,
+                compact_threshold: None,
+                compression,
+            },
+            pagebook,
+            _phantoms: None,
+        })
+    }
+}
+
+#[cfg(feature = "mmap")]
+impl<K, V> PageMap<K, V, MmapPages>
+where
+    K: Ord + Clone + Send + ToString + From<String>,
+    V: Ord + Clone + Send + Value,
+{
+    /// Open (or create) a [`PageMap`] with pages stored in the memory-mapped data
+    /// file at `path` (see [`MmapPages`]).
+    ///
+    /// If the file already exists, its pages are read to rebuild the key index.
+    pub fn open(path: PathBufD, options: PageMapOptions) -> Result<Self> {
+        let store = MmapPages::open(&path, options.pages, options.page_size)?;
+
+        Ok(Self {
+            pagebook: PageBook::from_store(store, options.page_size),
+            options,
+            _phantoms: None,
+        })
+    }
+
+    /// Write every changed page back to the data file (`msync`).
+    pub fn dump(&mut self) -> Result<()> {
+        self.pagebook.0.sync()?;
+        self.pagebook.clear_dirty();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{PageMap, PageMapOptions};
+    use crate::compression::{Codec, Compression};
+    use pathbufd::PathBufD;
+
+    fn dir(name: &str) -> PathBufD {
+        let path = PathBufD::new()
+            .join(std::env::temp_dir())
+            .join(format!("oysters-{name}-{}", std::process::id()));
+
+        let _ = std::fs::remove_dir_all(&path);
+        path
+    }
+
+    #[test]
+    fn load() {
+        let path = dir("load");
+        let mut map: PageMap<String, String> = PageMap::new(PageMapOptions {
+            pages: 1,
+            page_size: 128,
+            compact_threshold: None,
+            compression: Compression::new(Codec::Lz4, 0),
+        });
+
+        for i in 0..10 {
+            map.insert(i.to_string(), format!("value {i}")).unwrap();
+        }
+
+        assert!(map.compression_stats().raw_bytes > 0);
+
+        map.dump(path.clone()).unwrap();
+        assert_eq!(map.pagebook.dirty_pages().len(), 0);
+
+        // only the page we change should be written again
+        map.remove(&"0".to_string());
+        assert_eq!(map.flush(path.clone()).unwrap(), 1);
+
+        let loaded: PageMap<String, String> =
+            PageMap::load(path.clone(), Compression::default()).unwrap();
+        assert_eq!(loaded.options.page_size, 128);
+        assert_eq!(loaded.pagebook.1.2, 9);
+        assert!(loaded.get(&"0".to_string()).is_none());
+
+        for i in 1..10 {
+            assert_eq!(loaded.get(&i.to_string()), Some(format!("value {i}")));
+        }
+
+        std::fs::remove_dir_all(&path).unwrap();
     }
 
-    /// Dump the map into the given file at `path`.
-    pub fn dump(&self, path: PathBufD) {
-        for (i, page) in self.pagebook.0.iter().enumerate() {
-            std::fs::write(path.join(format!("{i}.page")), page).unwrap()
+    #[test]
+    fn corrupt() {
+        let path = dir("corrupt");
+        let mut map: PageMap<String, String> = PageMap::new(PageMapOptions {
+            pages: 1,
+            page_size: 128,
+            compact_threshold: None,
+            compression: Compression::default(),
+        });
+
+        map.insert("a".to_string(), "Hello, world!".to_string())
+            .unwrap();
+        map.dump(path.clone()).unwrap();
+
+        let page = path.join("0.page");
+        let mut bytes = std::fs::read(&page).unwrap();
+        bytes[20] ^= 0xFF;
+        std::fs::write(&page, bytes).unwrap();
+
+        let err = PageMap::<String, String>::load(path.clone(), Compression::default())
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn mmap() {
+        let path = dir("mmap");
+        let options = || PageMapOptions {
+            pages: 1,
+            page_size: 128,
+            compact_threshold: None,
+            compression: Compression::default(),
+        };
+
+        let mut map: PageMap<String, String, _> = PageMap::open(path.clone(), options()).unwrap();
+
+        for i in 0..20 {
+            map.insert(i.to_string(), format!("value {i}")).unwrap();
+        }
+
+        map.remove(&"0".to_string());
+        assert!(map.pagebook.1.0 > 1);
+        map.dump().unwrap();
+        drop(map);
+
+        let map: PageMap<String, String, _> = PageMap::open(path.clone(), options()).unwrap();
+        assert_eq!(map.pagebook.1.2, 19);
+        assert!(map.get(&"0".to_string()).is_none());
+
+        for i in 1..20 {
+            assert_eq!(map.get(&i.to_string()), Some(format!("value {i}")));
         }
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn mmap_invalid() {
+        use std::io::ErrorKind;
+
+        let path = dir("mmap-invalid");
+        let options = |page_size| PageMapOptions {
+            pages: 1,
+            page_size,
+            compact_threshold: None,
+            compression: Compression::default(),
+        };
+
+        let mut map: PageMap<String, String, _> =
+            PageMap::open(path.clone(), options(128)).unwrap();
+
+        // records can't be larger than a page
+        let err = map.insert("a".to_string(), "a".repeat(256)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        assert!(map.get(&"a".to_string()).is_none());
+
+        map.insert("b".to_string(), "b".to_string()).unwrap();
+        map.dump().unwrap();
+        drop(map);
+
+        // opening with another page size shouldn't touch the file
+        let len = std::fs::metadata(&path).unwrap().len();
+        let err = PageMap::<String, String, _>::open(path.clone(), options(64))
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), len);
+
+        // neither should opening a file which isn't a whole number of pages
+        let file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap();
+        file.set_len(len + 3).unwrap();
+        let err = PageMap::<String, String, _>::open(path.clone(), options(128))
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), len + 3);
+
+        std::fs::remove_file(&path).unwrap();
     }
 }
@@ -1,4 +1,7 @@
+pub mod manifest;
 pub mod map;
 pub mod pagebook;
+pub mod record;
+pub mod store;
 
 pub use map::*;
@@ -1,87 +1,211 @@
-use lz4_flex::{compress_prepend_size as compress, decompress_size_prepended as decompress};
+use super::record::{self, RecordHeader};
+use super::store::PageStore;
+use std::collections::{BTreeSet, HashMap};
+use std::io::{Error, ErrorKind, Result};
 use std::ops::Range;
-use std::ptr::{read as ptrread, write as ptrwrite};
 
-pub struct PageBook(pub Vec<Vec<u8>>, pub (usize, usize, usize), (usize, usize));
+/// The location of a record in the pagebook.
+///
+/// Stored as a tuple of the page number, the offset of the record in that page,
+/// and the length of the record (header included).
+pub type IndexEntry = (usize, usize, usize);
+
+/// Space usage of a single page (see [`PageBook::stats`]).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PageStats {
+    /// Bytes used by live records.
+    pub live_bytes: usize,
+    /// Bytes used by removed records (tombstones) which haven't been reused yet.
+    pub dead_bytes: usize,
+    /// Bytes which have never been used (or have been reclaimed).
+    pub free_bytes: usize,
+    /// How scattered the reusable space (dead and free bytes) is. `0.0` means all
+    /// of it is in one range, values close to `1.0` mean it's split into lots of
+    /// small holes.
+    pub fragmentation: f32,
+}
+
+/// A book of fixed-size pages, each storing [`record`]s.
+///
+/// Pages are kept in memory by default, but can live anywhere which implements
+/// [`PageStore`].
+///
+/// Fields are the pages, `(page count, page size, key count)`, the free space of
+/// each page (sorted, non-overlapping ranges), the key index, and the pages which
+/// have changed since they were last written to disk.
+pub struct PageBook<S: PageStore = Vec<Vec<u8>>>(
+    pub S,
+    pub (usize, usize, usize),
+    Vec<Vec<Range<usize>>>,
+    pub(crate) HashMap<Vec<u8>, IndexEntry>,
+    BTreeSet<usize>,
+);
 
 impl PageBook {
-    /// Create a new [`PageBook`].
+    /// Create a new [`PageBook`] (with pages stored in memory).
     ///
     /// The given `count` is just the _initial_ number of pages in the pagebook.
     /// The book will grow if it runs out of room.
     pub fn new(count: usize, size: usize) -> Self {
         let mut pagebook = Vec::with_capacity(count);
+        let mut free = Vec::with_capacity(count);
 
         for _ in 0..count {
             pagebook.push(vec![0; size]);
+            free.push(Self::free_list(0..size));
         }
 
-        Self(pagebook, (count, size, 0), (0, 0))
+        Self(
+            pagebook,
+            (count, size, 0),
+            free,
+            HashMap::new(),
+            (0..count).collect(),
+        )
+    }
+
+    /// Create a [`PageBook`] from existing `pages` (e.g. pages loaded from disk).
+    ///
+    /// The key index is rebuilt from the contents of the given pages.
+    pub fn from_pages(pages: Vec<Vec<u8>>, size: usize) -> Self {
+        Self::from_store(pages, size)
+    }
+}
+
+impl<S: PageStore> PageBook<S> {
+    /// Create a [`PageBook`] from an existing page `store`.
+    ///
+    /// The key index is rebuilt from the contents of the store.
+    pub fn from_store(store: S, size: usize) -> Self {
+        let count = store.count();
+        let mut pagebook = Self(
+            store,
+            (count, size, 0),
+            Vec::new(),
+            HashMap::new(),
+            BTreeSet::new(),
+        );
+        pagebook.rebuild_index();
+        pagebook
+    }
+
+    /// Rebuild the key index (and free space) by reading every record in every page.
+    ///
+    /// Records which fail their checksum are skipped.
+    pub fn rebuild_index(&mut self) {
+        self.3.clear();
+        self.2.clear();
+
+        for i in 0..self.0.count() {
+            let page = self.0.page(i).unwrap();
+            let mut free: Vec<Range<usize>> = Vec::new();
+            let mut cursor: usize = 0;
+
+            while cursor < page.len() {
+                let (len, live) = match record::decode(&page[cursor..]) {
+                    Some((header, key, _)) => {
+                        if !header.is_tombstone() {
+                            self.3.insert(key, (i, cursor, header.record_len()));
+                        }
+
+                        (header.record_len(), !header.is_tombstone())
+                    }
+                    // free space (or a corrupt record)
+                    None => (1, false),
+                };
+
+                if !live {
+                    Self::release(&mut free, cursor..cursor + len);
+                }
+
+                cursor += len;
+            }
+
+            self.2.push(free);
+        }
+
+        self.1.2 = self.3.len();
     }
 
     /// Create a new page under self.
-    pub fn create_page(&mut self) -> Option<&Vec<u8>> {
-        self.0.insert(0, vec![0; self.1.1]); // insert page TO THE BEGINNING (so it is filled ASAP)
+    ///
+    /// # Returns
+    /// An error if the page store can't grow.
+    pub fn create_page(&mut self) -> Result<&[u8]> {
+        self.0.create_page(self.1.1)?;
+        self.2.push(Self::free_list(0..self.1.1));
+        self.4.insert(self.0.count() - 1);
         self.1.0 += 1; // incr page count
-        self.0.get(self.1.0 - 1)
+        Ok(self.0.page(self.0.count() - 1).unwrap())
     }
 
-    /// Check if the window contains all null bytes.
-    pub(crate) fn check_window_for_not_null(window: &[u8]) -> bool {
-        let sub_1 = window.len() - 1;
-        let null = &0_u8;
+    /// Create a free list containing only the given `range`.
+    fn free_list(range: Range<usize>) -> Vec<Range<usize>> {
+        std::iter::once(range).collect()
+    }
 
-        let first_byte = window.get(0).unwrap() == null;
-        let last_byte = window.get(sub_1).unwrap() == null;
-        let middle_byte = window.get(sub_1 / 2).unwrap() == null;
+    /// Add the given `range` to a free list, merging it with its neighbours.
+    fn release(free: &mut Vec<Range<usize>>, range: Range<usize>) {
+        let i = free.partition_point(|x| x.start < range.start);
+        free.insert(i, range);
 
-        first_byte && middle_byte && last_byte
-    }
+        // merge with next
+        if i + 1 < free.len() && free[i].end >= free[i + 1].start {
+            free[i].end = free[i].end.max(free[i + 1].end);
+            free.remove(i + 1);
+        }
 
-    /// Get the next free (\0) range of the given size in the given page.
-    pub fn find_free_range(page: &[u8], size: usize) -> Option<Range<usize>> {
-        let mut windows = page.windows(size);
+        // merge with previous
+        if i > 0 && free[i - 1].end >= free[i].start {
+            free[i - 1].end = free[i - 1].end.max(free[i].end);
+            free.remove(i);
+        }
+    }
 
-        if let Some(i) = windows.position(|x| PageBook::check_window_for_not_null(x)) {
-            return Some(i..i + size);
+    /// Get the first free range of the given size in any page.
+    ///
+    /// # Returns
+    /// The page number and the index of the free range in that page's free list.
+    pub fn find_free_range(&self, size: usize) -> Option<(usize, usize)> {
+        for (i, free) in self.2.iter().enumerate() {
+            if let Some(j) = free.iter().position(|x| x.len() >= size) {
+                return Some((i, j));
+            }
         }
 
         None
     }
 
+    /// Take `size` bytes from the start of the given free range. The whole free
+    /// range is zeroed, so any tombstones in it are gone for good.
+    fn allocate(&mut self, page: usize, free_index: usize, size: usize) -> Range<usize> {
+        let free = &mut self.2[page];
+        let range = free[free_index].clone();
+
+        self.0.page_mut(page).unwrap()[range.clone()].fill(0);
+        self.4.insert(page);
+
+        if range.len() == size {
+            free.remove(free_index);
+        } else {
+            free[free_index].start += size;
+        }
+
+        range.start..range.start + size
+    }
+
     /// Find the page the given `key` blongs to.
     ///
-    /// Note that this will always return the **first** match. The same key _could_
-    /// exist in other pages!
+    /// This is a single lookup in the key index, so it doesn't need to read
+    /// any pages.
     ///
     /// # Returns
     /// Will return on option containing a tuple of the page number, followed by
-    /// the window number.
+    /// the offset of the record in that page.
     ///
     /// If the key doesn't exist in any page, `None` will be returned.
     pub fn find_page(&self, key: &[u8]) -> Option<(usize, usize)> {
-        let key = compress(key);
-        for (i, page) in self.0.iter().enumerate() {
-            let mut windows = page.windows(key.len()); // + 1 to include sep byte
-            let mut window_num: usize = 0;
-            while let Some(chunk) = windows.next() {
-                let mut chunk: Vec<u8> = chunk.to_vec();
-
-                if chunk.get(0).unwrap() == &2_u8 {
-                    // chunks _might_ start with \2 because of being RIGHT after
-                    // a value chunk... we should just go ahead and remove it and ignore
-                    chunk.remove(0);
-                }
-
-                if chunk == key {
-                    return Some((i, window_num));
-                }
-
-                window_num += 1;
-            }
-        }
-
-        None
+        self.3.get(key).map(|(page, offset, _)| (*page, *offset))
     }
 
     /// Get a value from the pagebook (assuming we **don't** know the page number).
@@ -89,13 +213,8 @@ impl PageBook {
     /// # Arguments
     /// * `key` - the key as bytes
     pub fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
-        self.get_known(
-            match self.find_page(&key) {
-                Some((page, _)) => page,
-                None => return None,
-            },
-            key,
-        )
+        let (page, _) = self.find_page(key)?;
+        self.get_known(page, key)
     }
 
     /// Get a value from the pagebook (assuming we **don't** know the page number).
@@ -105,54 +224,24 @@ impl PageBook {
     /// # Arguments
     /// * `key` - the key as bytes
     pub fn get_full(&self, key: &[u8]) -> Option<(usize, Vec<u8>)> {
-        let page = match self.find_page(&key) {
-            Some((page, _)) => page,
-            None => return None,
-        };
-
-        if let Some(v) = self.get_known(page, key) {
-            Some((page, v))
-        } else {
-            None
-        }
+        let (page, _) = self.find_page(key)?;
+        self.get_known(page, key).map(|v| (page, v))
     }
 
-    /// Get the range of a key in the pagebook. Range includes from the start of
-    /// the key, to the end of the value (with terminating byte).
+    /// Get the range of a key in the pagebook. Range includes the entire record
+    /// (header, key, and value).
     ///
     /// # Arguments
     /// * `page` - the page the key exists in
     /// * `key` - the key as bytes
     pub fn get_key_range(&self, page: usize, key: &[u8]) -> Option<Range<usize>> {
-        // get page
-        let page = match self.0.get(page) {
-            Some(p) => p,
-            None => return None,
-        };
-
-        // get value
-        let mut windows = page.windows(key.len());
-        let pos = match windows.position(|x| x == key) {
-            Some(p) => p,
-            None => return None,
-        };
-
-        let key_end = pos + key.len() + 1;
-
-        // collect bytes to terminating byte
-        let mut count: usize = 0;
-        let sub: &[u8] = &page[key_end..page.len()];
-
-        for byte in sub {
-            count += 1;
+        let (entry_page, offset, len) = self.3.get(key)?;
 
-            if byte == &2_u8 {
-                break;
-            }
+        if *entry_page != page {
+            return None;
         }
 
-        // return
-        Some(pos..count)
+        Some(*offset..*offset + *len)
     }
 
     /// Get a value from the pagebook (assuming we know the page number).
@@ -161,103 +250,72 @@ impl PageBook {
     /// * `page` - the page the key is contained in
     /// * `key` - the key as bytes
     pub fn get_known(&self, page: usize, key: &[u8]) -> Option<Vec<u8>> {
-        let key = &compress(key);
+        let range = self.get_key_range(page, key)?;
 
         // get page
-        let page = match self.0.get(page) {
-            Some(p) => p,
-            None => return None,
-        };
-
-        // get value
-        let mut windows = page.windows(key.len());
-        let pos = match windows.position(|x| x == key) {
-            Some(p) => p,
-            None => return None,
-        };
-
-        // now that we know the position, the actual STARTING INDEX of the window
-        // is `pos + key.len()` ... this means that the ending pos is `pos + key.len() + 1`
-        // (+ 1 for the \1 byte after the key)
-        let key_end = pos + key.len() + 1;
+        let page = self.0.page(page)?;
 
-        // we can get the actual data by getting every byte UNTIL a null byte is found
-        // (\2 terminates values, \1 terminates keys)
-        let mut out: Vec<u8> = Vec::new();
-        let sub: &[u8] = &page[key_end..page.len()];
-
-        for byte in sub {
-            if byte == &2_u8 {
-                break;
-            }
-
-            out.push(byte.to_owned());
-        }
-
-        Some(decompress(&out).unwrap())
+        // the index tells us exactly where the record is, and the record header
+        // tells us exactly how long the value is
+        let (_, _, value) = record::decode(page.get(range)?)?;
+        Some(value)
     }
 
     /// Insert a value into the book at the **first available** location.
     ///
+    /// If the key already exists, its old value is removed once the new value has
+    /// been written.
+    ///
     /// # Arguments
     /// * `key` - the key as bytes
     /// * `value` - the value as bytes
-    pub fn insert(&mut self, key: &[u8], value: &[u8]) {
-        let key = &compress(key);
-        let value = &compress(value);
-
-        // find good page
-        let page: (usize, Option<Range<usize>>) = {
-            let mut num: usize = 0;
-            let mut range: Option<Range<usize>> = None;
-
-            for (i, page) in self.0.iter().skip(self.2.0).enumerate() {
-                let free_range = PageBook::find_free_range(page, key.len() + value.len() + 2);
+    ///
+    /// # Returns
+    /// An [`ErrorKind::InvalidInput`] error if the record is larger than a page and
+    /// the page store doesn't support larger pages, or an error if the page store
+    /// can't grow. The book isn't changed if this fails.
+    pub fn insert(&mut self, key: &[u8], value: &[u8]) -> Result<()> {
+        let out = record::encode(key, value);
+
+        if out.len() > self.1.1 && !self.0.can_resize() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "record is larger than the page size",
+            ));
+        }
 
-                if free_range.is_none() {
-                    // page is full
-                    continue;
+        // find good page (the old record keeps its space until the new one is
+        // written, so it's still there if the store can't grow)
+        let (page, free_index) = match self.find_free_range(out.len()) {
+            Some(x) => x,
+            None => {
+                self.create_page()?;
+                let page = self.1.0 - 1;
+
+                // records bigger than a page get a page of their own
+                if out.len() > self.1.1 {
+                    self.0.resize_page(page, out.len());
+                    self.2[page] = Self::free_list(0..out.len());
                 }
 
-                num = i;
-                range = free_range;
+                (page, 0)
             }
-
-            self.2.0 = num; // store the last page we had to check as the skip value
-
-            if range.is_none() {
-                if self.create_page().is_some() {
-                    // immediately set range to 0..(minimum needed)
-                    // we're skipping `find_free_range` here because that will actually
-                    // spend resources looking for the value, meanwhile we know the page
-                    // is empty
-                    range = Some(0..(key.len() + value.len() + 2));
-                }
-            }
-
-            (num, range)
         };
 
-        // select page as mutable
-        let free_range = page.1.unwrap();
-        let page = self.0.get_mut(page.0).unwrap();
-
-        // push data
-        let out: Vec<u8> = [key.as_slice(), &[1_u8], value.as_slice(), &[2_u8]].concat();
-
-        // swap data
-        let mut idx: usize = 0;
-        for i in free_range {
-            unsafe {
-                let mut b = *out.get_unchecked(idx);
-                ptrwrite(&mut page[i], ptrread(&mut b));
-            }
+        // write data
+        let range = self.allocate(page, free_index, out.len());
+        self.0.page_mut(page).unwrap()[range.clone()].copy_from_slice(&out);
 
-            idx += 1;
+        if self.3.contains_key(key) {
+            self.remove(key);
         }
 
+        // index key
+        self.3.insert(key.to_vec(), (page, range.start, out.len()));
+
         // incr key count
         self.1.2 += 1;
+        Ok(())
     }
 
     /// Remove a value from the pagebook (assuming we **don't** know the page number).
@@ -265,39 +323,27 @@ impl PageBook {
     /// # Arguments
     /// * `key` - the key as bytes
     pub fn remove(&mut self, key: &[u8]) -> Option<()> {
-        self.remove_known(
-            match self.find_page(&key) {
-                Some((page, _)) => page,
-                None => return None,
-            },
-            key,
-        )
+        let (page, _) = self.find_page(key)?;
+        self.remove_known(page, key)
     }
 
     /// Remove a key (and its value) from the given page.
     ///
+    /// The record is only marked as a tombstone. Its space is added to the page's
+    /// free list, and will be zeroed once something else is written there.
+    ///
     /// # Arguments
     /// * `page` - the page the key exists in
     /// * `key` - the key as bytes
     pub fn remove_known(&mut self, page: usize, key: &[u8]) -> Option<()> {
         // get range
-        let range = match self.get_key_range(page, key) {
-            Some(r) => r,
-            None => return None,
-        };
-
-        // get mut page
-        let page = match self.0.get_mut(page) {
-            Some(p) => p,
-            None => return None,
-        };
+        let range = self.get_key_range(page, key)?;
 
-        // remove
-        let mut removed_bytes: usize = 0;
-        for i in range {
-            page.remove(i - removed_bytes); // subtract the number of bytes we've already removed to account for changing len
-            removed_bytes += 1;
-        }
+        // mark as removed
+        record::tombstone(&mut self.0.page_mut(page)?[range.clone()])?;
+        Self::release(&mut self.2[page], range);
+        self.3.remove(key);
+        self.4.insert(page);
 
         // decr key count
         self.1.2 -= 1;
@@ -305,4 +351,325 @@ impl PageBook {
         // return
         Some(())
     }
+
+    /// Get the pages which have changed since [`Self::clear_dirty`] was last called.
+    pub fn dirty_pages(&self) -> Vec<usize> {
+        self.4.iter().copied().collect()
+    }
+
+    /// Mark every page as clean (e.g. after writing them to disk).
+    pub fn clear_dirty(&mut self) {
+        self.4.clear();
+    }
+
+    /// Get the space usage of every page.
+    pub fn stats(&self) -> Vec<PageStats> {
+        let mut out = Vec::with_capacity(self.0.count());
+
+        for (i, free) in self.2.iter().enumerate() {
+            let page = self.0.page(i).unwrap();
+            let mut stats = PageStats::default();
+            let mut cursor: usize = 0;
+
+            while cursor < page.len() {
+                match RecordHeader::read(&page[cursor..]) {
+                    Some(header) if !header.is_tombstone() => {
+                        stats.live_bytes += header.record_len();
+                        cursor += header.record_len();
+                    }
+                    Some(header) => {
+                        stats.dead_bytes += header.record_len();
+                        cursor += header.record_len();
+                    }
+                    None => cursor += 1,
+                }
+            }
+
+            stats.free_bytes = page.len() - stats.live_bytes - stats.dead_bytes;
+
+            let reusable: usize = free.iter().map(|x| x.len()).sum();
+            let largest = free.iter().map(|x| x.len()).max().unwrap_or(0);
+
+            if reusable > 0 {
+                stats.fragmentation = 1.0 - (largest as f32 / reusable as f32);
+            }
+
+            out.push(stats);
+        }
+
+        out
+    }
+
+    /// Defragment the given page by moving every live record to the start of the
+    /// page (in their current order). Tombstones are dropped.
+    ///
+    /// # Arguments
+    /// * `page` - the page to compact
+    pub fn compact_page(&mut self, page: usize) {
+        let Some(bytes) = self.0.page(page) else {
+            return;
+        };
+
+        // collect live records in the order they appear
+        let mut entries: Vec<(Vec<u8>, usize, usize)> = self
+            .3
+            .iter()
+            .filter(|(_, x)| x.0 == page)
+            .map(|(k, x)| (k.clone(), x.1, x.2))
+            .collect();
+
+        entries.sort_by_key(|x| x.1);
+
+        let mut compacted = vec![0; bytes.len()];
+        let mut cursor: usize = 0;
+
+        for (key, offset, len) in entries {
+            compacted[cursor..cursor + len].copy_from_slice(&bytes[offset..offset + len]);
+            self.3.insert(key, (page, cursor, len));
+            cursor += len;
+        }
+
+        self.2[page] = if cursor < compacted.len() {
+            Self::free_list(cursor..compacted.len())
+        } else {
+            Vec::new()
+        };
+
+        self.0.page_mut(page).unwrap().copy_from_slice(&compacted);
+        self.4.insert(page);
+    }
+
+    /// Defragment every page (see [`Self::compact_page`]).
+    pub fn compact(&mut self) {
+        for page in 0..self.0.count() {
+            self.compact_page(page);
+        }
+    }
+
+    /// Compact every page where more than `threshold` (`0.0` to `1.0`) of the
+    /// page is taken up by tombstones.
+    ///
+    /// # Returns
+    /// The number of pages which were compacted.
+    pub fn compact_if(&mut self, threshold: f32) -> usize {
+        let mut count: usize = 0;
+
+        for (page, stats) in self.stats().into_iter().enumerate() {
+            let size = self.0.page(page).unwrap().len() as f32;
+
+            if size > 0.0 && (stats.dead_bytes as f32 / size) > threshold {
+                self.compact_page(page);
+                count += 1;
+            }
+        }
+
+        count
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::PageBook;
+    use crate::pagemap::record;
+    use crate::pagemap::store::PageStore;
+    use proptest::collection::{hash_map, vec};
+    use proptest::prelude::*;
+    use std::io::{Error, Result};
+
+    #[test]
+    fn index() {
+        let mut book = PageBook::new(1, 128);
+
+        book.insert(b"a", b"Hello, world!").unwrap();
+        book.insert(b"b", b"Hello, world! 1").unwrap();
+        book.insert(b"c", b"Hello, world! 2").unwrap();
+
+        book.remove(b"a");
+        assert!(book.get(b"a").is_none());
+        assert_eq!(book.get(b"b").unwrap(), b"Hello, world! 1");
+        assert_eq!(book.get(b"c").unwrap(), b"Hello, world! 2");
+
+        // rebuilding the index from the raw pages should find the same values
+        let book = PageBook::from_pages(book.0, 128);
+        assert_eq!(book.1.2, 2);
+        assert_eq!(book.get(b"b").unwrap(), b"Hello, world! 1");
+        assert_eq!(book.get(b"c").unwrap(), b"Hello, world! 2");
+    }
+
+    /// Pages in memory which can't grow (like a full disk).
+    struct Full(Vec<Vec<u8>>);
+
+    impl PageStore for Full {
+        fn count(&self) -> usize {
+            self.0.len()
+        }
+
+        fn page(&self, page: usize) -> Option<&[u8]> {
+            self.0.page(page)
+        }
+
+        fn page_mut(&mut self, page: usize) -> Option<&mut [u8]> {
+            self.0.page_mut(page)
+        }
+
+        fn create_page(&mut self, _: usize) -> Result<()> {
+            Err(Error::other("full"))
+        }
+
+        fn can_resize(&self) -> bool {
+            false
+        }
+
+        fn resize_page(&mut self, _: usize, _: usize) -> bool {
+            false
+        }
+    }
+
+    #[test]
+    fn failed_overwrite() {
+        let mut book = PageBook::from_store(Full(vec![vec![0; 64]]), 64);
+        book.insert(b"a", b"Hello, world!").unwrap();
+
+        // there's no room for the new value, so the old one is kept
+        assert!(book.insert(b"a", &[1; 40]).is_err());
+        assert_eq!(book.get(b"a").unwrap(), b"Hello, world!");
+        assert_eq!(book.1.2, 1);
+    }
+
+    #[test]
+    fn delimiter_bytes() {
+        let mut book = PageBook::new(1, 256);
+
+        // these used to be record delimiters
+        book.insert(&[0, 1, 2], &[2, 1, 0, 0, 0]).unwrap();
+        book.insert(&[1], &[0; 32]).unwrap();
+        book.insert(&[2, 2], &[]).unwrap();
+
+        assert_eq!(book.get(&[0, 1, 2]).unwrap(), [2, 1, 0, 0, 0]);
+        assert_eq!(book.get(&[1]).unwrap(), [0; 32]);
+        assert_eq!(book.get(&[2, 2]).unwrap(), [0u8; 0]);
+    }
+
+    #[test]
+    fn compaction() {
+        let mut book = PageBook::new(1, 256);
+
+        for i in 0..5_u8 {
+            book.insert(&[i], b"Hello, world!").unwrap();
+        }
+
+        book.remove(&[1]);
+        book.remove(&[3]);
+
+        let stats = &book.stats()[0];
+        assert!(stats.dead_bytes > 0);
+        assert!(stats.fragmentation > 0.0);
+        assert_eq!(stats.live_bytes + stats.dead_bytes + stats.free_bytes, 256);
+
+        // holes are reused before creating a new page
+        book.insert(&[1], b"Hello!").unwrap();
+        assert_eq!(book.1.0, 1);
+        assert_eq!(
+            book.find_page(&[1]).unwrap().1,
+            book.get_key_range(0, &[0]).unwrap().end
+        );
+
+        book.compact();
+
+        let stats = &book.stats()[0];
+        assert_eq!(stats.dead_bytes, 0);
+        assert_eq!(stats.fragmentation, 0.0);
+        assert_eq!(book.2[0].len(), 1);
+
+        for i in [0_u8, 2, 4] {
+            assert_eq!(book.get(&[i]).unwrap(), b"Hello, world!");
+        }
+
+        assert_eq!(book.get(&[1]).unwrap(), b"Hello!");
+        assert!(book.get(&[3]).is_none());
+    }
+
+    #[test]
+    fn checksum() {
+        let mut out = record::encode(b"a", b"Hello, world!");
+        assert!(record::decode(&out).is_some());
+
+        let last = out.len() - 1;
+        out[last] ^= 0xFF;
+        assert!(record::decode(&out).is_none());
+    }
+
+    proptest! {
+        #[test]
+        fn record_round_trip(key in vec(any::<u8>(), 0..64), value in vec(any::<u8>(), 0..512)) {
+            let out = record::encode(&key, &value);
+            let (header, k, v) = record::decode(&out).unwrap();
+
+            prop_assert_eq!(header.record_len(), out.len());
+            prop_assert_eq!(k, key);
+            prop_assert_eq!(v, value);
+        }
+
+        #[test]
+        fn pagebook_round_trip(
+            entries in hash_map(vec(any::<u8>(), 0..32), vec(any::<u8>(), 0..128), 0..64),
+            page_size in 64_usize..512,
+        ) {
+            let mut book = PageBook::new(1, page_size);
+
+            for (k, v) in &entries {
+                book.insert(k, v).unwrap();
+            }
+
+            for (k, v) in &entries {
+                prop_assert_eq!(book.get(k), Some(v.clone()));
+            }
+
+            // remove every other key
+            let mut kept = Vec::new();
+            for (i, (k, v)) in entries.iter().enumerate() {
+                if i % 2 == 0 {
+                    prop_assert!(book.remove(k).is_some());
+                    prop_assert!(book.get(k).is_none());
+                } else {
+                    kept.push((k, v));
+                }
+            }
+
+            for (k, v) in &kept {
+                prop_assert_eq!(book.get(k), Some(v.to_vec()));
+            }
+
+            // compacting shouldn't lose anything
+            let mut compacted = PageBook::from_pages(book.0.clone(), page_size);
+            compacted.compact();
+
+            for stats in compacted.stats() {
+                prop_assert_eq!(stats.dead_bytes, 0);
+            }
+
+            for (k, v) in &kept {
+                prop_assert_eq!(compacted.get(k), Some(v.to_vec()));
+            }
+
+            // the pages alone should be enough to find everything again
+            let book = PageBook::from_pages(book.0, page_size);
+            prop_assert_eq!(book.1.2, kept.len());
+
+            for (k, v) in &kept {
+                prop_assert_eq!(book.get(k), Some(v.to_vec()));
+            }
+        }
+
+        #[test]
+        fn pagebook_overwrite(key in vec(any::<u8>(), 0..32), a in vec(any::<u8>(), 0..128), b in vec(any::<u8>(), 0..128)) {
+            let mut book = PageBook::new(1, 128);
+
+            book.insert(&key, &a).unwrap();
+            book.insert(&key, &b).unwrap();
+
+            prop_assert_eq!(book.get(&key), Some(b));
+            prop_assert_eq!(book.1.2, 1);
+        }
+    }
 }
@@ -0,0 +1,135 @@
+//! Length-prefixed records stored in pages.
+//!
+//! Every record looks like this:
+//!
+//! ```text
+//! | magic (2) | flags (1) | key len (4) | value len (4) | checksum (4) | key | value |
+//! ```
+//!
+//! All integers are little endian. The checksum is the CRC32 of everything in
+//! the record after the magic bytes (excluding the checksum itself). Because the
+//! header tells us exactly how long the key and value are, keys and values can
+//! contain any bytes (including the null bytes used for free space).
+//!
+//! Records don't compress anything themselves. [`PageMap`](super::PageMap) compresses
+//! values (see [`crate::compression`]) before they're written into a record.
+
+/// The bytes every record begins with.
+pub const MAGIC: [u8; 2] = [0xB7, 0x0E];
+/// The length of a record header.
+pub const HEADER_LEN: usize = 15;
+
+/// The record has been removed. Its space can be reused by other records.
+pub const FLAG_TOMBSTONE: u8 = 0b1000_0000;
+
+/// The header of a record.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RecordHeader {
+    /// Record flags (see `FLAG_*`).
+    pub flags: u8,
+    /// The length of the key (as stored).
+    pub key_len: u32,
+    /// The length of the value (as stored).
+    pub value_len: u32,
+    /// CRC32 of the flags, lengths, key, and value.
+    pub checksum: u32,
+}
+
+impl RecordHeader {
+    /// The length of the entire record (header included).
+    pub fn record_len(&self) -> usize {
+        HEADER_LEN + self.key_len as usize + self.value_len as usize
+    }
+
+    /// If the record has been removed.
+    pub fn is_tombstone(&self) -> bool {
+        self.flags & FLAG_TOMBSTONE != 0
+    }
+
+    /// Read a header from the start of `bytes`.
+    ///
+    /// Returns `None` if `bytes` doesn't start with [`MAGIC`], or is too short to
+    /// hold a header.
+    pub fn read(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < HEADER_LEN || bytes[0..2] != MAGIC {
+            return None;
+        }
+
+        Some(Self {
+            flags: bytes[2],
+            key_len: u32::from_le_bytes(bytes[3..7].try_into().unwrap()),
+            value_len: u32::from_le_bytes(bytes[7..11].try_into().unwrap()),
+            checksum: u32::from_le_bytes(bytes[11..15].try_into().unwrap()),
+        })
+    }
+
+    /// Write the header into a new [`Vec`].
+    pub fn write(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(HEADER_LEN);
+        out.extend_from_slice(&MAGIC);
+        out.push(self.flags);
+        out.extend_from_slice(&self.key_len.to_le_bytes());
+        out.extend_from_slice(&self.value_len.to_le_bytes());
+        out.extend_from_slice(&self.checksum.to_le_bytes());
+        out
+    }
+}
+
+/// Compute the checksum of a record.
+fn checksum(flags: u8, key: &[u8], value: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(&[flags]);
+    hasher.update(&(key.len() as u32).to_le_bytes());
+    hasher.update(&(value.len() as u32).to_le_bytes());
+    hasher.update(key);
+    hasher.update(value);
+    hasher.finalize()
+}
+
+/// Encode a `key` and `value` into a record.
+pub fn encode(key: &[u8], value: &[u8]) -> Vec<u8> {
+    let header = RecordHeader {
+        flags: 0,
+        key_len: key.len() as u32,
+        value_len: value.len() as u32,
+        checksum: checksum(0, key, value),
+    };
+
+    [header.write().as_slice(), key, value].concat()
+}
+
+/// Mark the record at the start of `bytes` as removed (see [`FLAG_TOMBSTONE`]).
+///
+/// The record keeps its length, so anything reading the page can still skip over it.
+pub fn tombstone(bytes: &mut [u8]) -> Option<()> {
+    let mut header = RecordHeader::read(bytes)?;
+    let record = bytes.get(HEADER_LEN..header.record_len())?;
+    let (key, value) = record.split_at(header.key_len as usize);
+
+    header.flags |= FLAG_TOMBSTONE;
+    header.checksum = checksum(header.flags, key, value);
+    bytes[..HEADER_LEN].copy_from_slice(&header.write());
+
+    Some(())
+}
+
+/// Read the record at the start of `bytes`.
+///
+/// Tombstones are returned like any other record, check [`RecordHeader::is_tombstone`].
+///
+/// Returns `None` if there is no valid record (bad magic, truncated, or the
+/// checksum doesn't match).
+///
+/// # Returns
+/// The record header, the key, and the value.
+pub fn decode(bytes: &[u8]) -> Option<(RecordHeader, Vec<u8>, Vec<u8>)> {
+    let header = RecordHeader::read(bytes)?;
+    let record = bytes.get(HEADER_LEN..header.record_len())?;
+    let (key, value) = record.split_at(header.key_len as usize);
+
+    if checksum(header.flags, key, value) != header.checksum {
+        return None;
+    }
+
+    Some((header, key.to_vec(), value.to_vec()))
+}
@@ -0,0 +1,233 @@
+//! Where the pages of a [`PageBook`](super::pagebook::PageBook) live.
+#[cfg(feature = "mmap")]
+use super::manifest::invalid;
+#[cfg(feature = "mmap")]
+use memmap2::MmapMut;
+#[cfg(feature = "mmap")]
+use std::fs::{File, OpenOptions};
+use std::io::Result;
+#[cfg(feature = "mmap")]
+use std::io::{Error, ErrorKind};
+#[cfg(feature = "mmap")]
+use std::path::Path;
+
+/// Storage for the pages of a pagebook.
+pub trait PageStore {
+    /// The number of pages in the store.
+    fn count(&self) -> usize;
+
+    /// Get the bytes of a page.
+    fn page(&self, page: usize) -> Option<&[u8]>;
+
+    /// Get the bytes of a page as mutable.
+    fn page_mut(&mut self, page: usize) -> Option<&mut [u8]>;
+
+    /// Add a new (zeroed) page of the given `size` to the end of the store.
+    fn create_page(&mut self, size: usize) -> Result<()>;
+
+    /// If the store supports pages of different sizes (see [`Self::resize_page`]).
+    fn can_resize(&self) -> bool;
+
+    /// Change the size of a page. Returns `false` if the store doesn't support
+    /// pages of different sizes.
+    fn resize_page(&mut self, page: usize, size: usize) -> bool;
+}
+
+/// Pages stored entirely in memory.
+impl PageStore for Vec<Vec<u8>> {
+    fn count(&self) -> usize {
+        self.len()
+    }
+
+    fn page(&self, page: usize) -> Option<&[u8]> {
+        self.get(page).map(|x| x.as_slice())
+    }
+
+    fn page_mut(&mut self, page: usize) -> Option<&mut [u8]> {
+        self.get_mut(page).map(|x| x.as_mut_slice())
+    }
+
+    fn create_page(&mut self, size: usize) -> Result<()> {
+        self.push(vec![0; size]);
+        Ok(())
+    }
+
+    fn can_resize(&self) -> bool {
+        true
+    }
+
+    fn resize_page(&mut self, page: usize, size: usize) -> bool {
+        match self.get_mut(page) {
+            Some(p) => {
+                p.resize(size, 0);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Pages stored in a single memory-mapped data file.
+///
+/// The file starts with a [`MMAP_HEADER_LEN`] byte header holding the page size,
+/// and page `i` lives at `MMAP_HEADER_LEN + i * page_size`. Pages are only read
+/// into memory when they're touched (and can be dropped again by the OS), so the
+/// store can hold more data than fits in RAM.
+///
+/// Every page in the file has the same size, so records can't be larger than a page.
+#[cfg(feature = "mmap")]
+pub struct MmapPages {
+    file: File,
+    map: Option<MmapMut>,
+    page_size: usize,
+    count: usize,
+}
+
+/// The first bytes of every data file.
+#[cfg(feature = "mmap")]
+const MMAP_MAGIC: &[u8; 8] = b"OYSTPAGE";
+
+/// The length of the header at the start of every data file (the magic bytes,
+/// followed by the page size as a little-endian `u64`).
+#[cfg(feature = "mmap")]
+pub const MMAP_HEADER_LEN: usize = 16;
+
+#[cfg(feature = "mmap")]
+impl MmapPages {
+    /// Open (or create) the data file at `path`.
+    ///
+    /// If the file is new, it's created with `count` empty pages. Otherwise, the
+    /// number of pages is taken from the length of the file.
+    ///
+    /// Fails with [`std::io::ErrorKind::InvalidData`] if the file wasn't created
+    /// with the same `page_size`, or its length isn't a whole number of pages.
+    pub fn open(path: impl AsRef<Path>, count: usize, page_size: usize) -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+
+        let len = file.metadata()?.len() as usize;
+
+        let mut store = Self {
+            file,
+            map: None,
+            page_size,
+            count,
+        };
+
+        if len == 0 {
+            store.remap()?;
+
+            if let Some(ref mut map) = store.map {
+                map[..8].copy_from_slice(MMAP_MAGIC);
+                map[8..MMAP_HEADER_LEN].copy_from_slice(&(page_size as u64).to_le_bytes());
+            }
+
+            store.sync()?;
+            return Ok(store);
+        }
+
+        if len < MMAP_HEADER_LEN {
+            return Err(invalid("data file is missing its header".to_string()));
+        }
+
+        store.count = (len - MMAP_HEADER_LEN) / page_size;
+        store.remap()?;
+
+        let header = &store.map.as_ref().unwrap()[..MMAP_HEADER_LEN];
+        if &header[..8] != MMAP_MAGIC {
+            return Err(invalid("data file has an unknown header".to_string()));
+        }
+
+        let stored = u64::from_le_bytes(header[8..].try_into().unwrap()) as usize;
+        if stored != page_size {
+            return Err(invalid(format!(
+                "data file has a page size of {stored}, expected {page_size}"
+            )));
+        }
+
+        if !(len - MMAP_HEADER_LEN).is_multiple_of(page_size) {
+            return Err(invalid(format!(
+                "data file length isn't a multiple of the page size ({page_size})"
+            )));
+        }
+
+        Ok(store)
+    }
+
+    /// Map the file again. If `count` is larger than the number of pages in the
+    /// file, the file is grown first (it's never shrunk).
+    fn remap(&mut self) -> Result<()> {
+        let len = (MMAP_HEADER_LEN + self.count * self.page_size) as u64;
+        if self.file.metadata()?.len() < len {
+            self.file.set_len(len)?;
+        }
+
+        self.map = Some(unsafe { MmapMut::map_mut(&self.file)? });
+        Ok(())
+    }
+
+    /// Write every change back to the data file (`msync`).
+    pub fn sync(&self) -> Result<()> {
+        match self.map {
+            Some(ref map) => map.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(feature = "mmap")]
+impl PageStore for MmapPages {
+    fn count(&self) -> usize {
+        self.count
+    }
+
+    fn page(&self, page: usize) -> Option<&[u8]> {
+        if page >= self.count {
+            return None;
+        }
+
+        let start = MMAP_HEADER_LEN + page * self.page_size;
+        self.map.as_ref()?.get(start..start + self.page_size)
+    }
+
+    fn page_mut(&mut self, page: usize) -> Option<&mut [u8]> {
+        if page >= self.count {
+            return None;
+        }
+
+        let start = MMAP_HEADER_LEN + page * self.page_size;
+        self.map.as_mut()?.get_mut(start..start + self.page_size)
+    }
+
+    fn create_page(&mut self, size: usize) -> Result<()> {
+        if size != self.page_size {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "mmap pages must all be the same size",
+            ));
+        }
+
+        // flush what we have before the old mapping is dropped
+        self.sync()?;
+        self.count += 1;
+
+        if let Err(e) = self.remap() {
+            self.count -= 1;
+            return Err(e);
+        }
+
+        Ok(())
+    }
+
+    fn can_resize(&self) -> bool {
+        false
+    }
+
+    fn resize_page(&mut self, _: usize, _: usize) -> bool {
+        false
+    }
+}
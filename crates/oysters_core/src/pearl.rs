@@ -2,15 +2,95 @@ pub const EPOCH_YEAR: u16 = 2025;
 #[cfg(feature = "lru")]
 use crate::time::epoch_timestamp;
 
+use std::sync::atomic::{AtomicU64, Ordering};
+
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
+/// The last [`ResourceDescriptor::cas`] value which was handed out.
+static CAS: AtomicU64 = AtomicU64::new(0);
+
+/// Get a new, unique [`ResourceDescriptor::cas`] value.
+pub fn next_cas() -> u64 {
+    CAS.fetch_add(1, Ordering::Relaxed) + 1
+}
+
+/// Make sure [`next_cas`] never hands out a value which is already in use (for
+/// values which were restored from a dump).
+#[cfg(any(feature = "persistance", feature = "sqlite_backend"))]
+pub(crate) fn observe_cas(cas: u64) {
+    CAS.fetch_max(cas, Ordering::Relaxed);
+}
+
+/// The type of a value. Every value is stored as bytes, but some types (like
+/// lists) have their own layout (see [`crate::list`]).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum ValueKind {
+    /// A plain value.
+    #[default]
+    String,
+    /// A list of values (see [`crate::list`]).
+    List,
+    /// Values stored by field (see [`crate::hash`]).
+    Hash,
+    /// Unique values (see [`crate::set`]).
+    Set,
+    /// Unique values ordered by score (see [`crate::sorted_set`]).
+    SortedSet,
+    /// A JSON document (see `crate::json`, which needs the `json` feature).
+    Json,
+}
+
+#[cfg(any(feature = "persistance", feature = "sqlite_backend"))]
+impl ValueKind {
+    /// The number the kind is stored as (in the `kind` column).
+    pub(crate) fn code(self) -> u8 {
+        match self {
+            Self::String => 0,
+            Self::List => 1,
+            Self::Hash => 2,
+            Self::Set => 3,
+            Self::SortedSet => 4,
+            Self::Json => 5,
+        }
+    }
+
+    /// Read a kind stored by [`Self::code`] (values stored before kinds existed
+    /// are strings).
+    pub(crate) fn from_code(code: Option<u8>) -> Self {
+        match code {
+            Some(1) => Self::List,
+            Some(2) => Self::Hash,
+            Some(3) => Self::Set,
+            Some(4) => Self::SortedSet,
+            Some(5) => Self::Json,
+            _ => Self::String,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct ResourceDescriptor {
     /// The timestamp of the last time this resource was used.
     #[cfg(feature = "lru")]
     pub used: usize,
+    /// The timestamp this resource expires at (`None` if it never expires).
+    #[cfg(feature = "lru")]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub expires: Option<usize>,
+    /// Opaque flags stored alongside the value by clients (e.g. memcached flags).
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub flags: u32,
+    /// A unique value which changes every time the value is written (used for
+    /// compare-and-swap, see [`next_cas`]).
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub cas: u64,
+    /// The type of the value.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub kind: ValueKind,
 }
 
 impl Default for ResourceDescriptor {
@@ -18,10 +98,35 @@ impl Default for ResourceDescriptor {
         Self {
             #[cfg(feature = "lru")]
             used: epoch_timestamp(EPOCH_YEAR),
+            #[cfg(feature = "lru")]
+            expires: None,
+            flags: 0,
+            cas: 0,
+            kind: ValueKind::String,
         }
     }
 }
 
+impl ResourceDescriptor {
+    /// Mark the resource as used (without changing when it expires).
+    pub fn touch(&mut self) {
+        #[cfg(feature = "lru")]
+        {
+            self.used = epoch_timestamp(EPOCH_YEAR);
+        }
+    }
+
+    /// If the resource has expired. Resources never expire without the `lru` feature.
+    pub fn is_expired(&self) -> bool {
+        #[cfg(feature = "lru")]
+        if let Some(expires) = self.expires {
+            return expires <= epoch_timestamp(EPOCH_YEAR);
+        }
+
+        false
+    }
+}
+
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Pearl<V: Clone>(pub V, pub ResourceDescriptor);
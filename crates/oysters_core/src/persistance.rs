@@ -1,44 +1,65 @@
+use crate::db::{create_table, drop_table, quote};
 use crate::oyster::Oyster;
+use crate::value::Value;
 use pathbufd::PathBufD;
 use rusqlite::{Connection, Result};
 use std::hash::Hash;
+use std::time::Instant;
 use std::{fs, sync::LazyLock};
 
 #[cfg(not(feature = "sqlite_backend"))]
-use crate::pearl::{Pearl, ResourceDescriptor};
+use crate::collection::Stored;
+#[cfg(not(feature = "sqlite_backend"))]
+use crate::db::column_bytes;
+#[cfg(not(feature = "sqlite_backend"))]
+use crate::pearl::{Pearl, ResourceDescriptor, ValueKind, next_cas, observe_cas};
 
+/// The dump file (`dump.db` in the working directory).
 pub static PATH: LazyLock<PathBufD> = LazyLock::new(|| PathBufD::current().extend(&["dump.db"]));
 
+/// The size of the dump file (and its write-ahead log).
+fn dump_size() -> u64 {
+    let size = |path: String| fs::metadata(path).map(|x| x.len()).unwrap_or(0);
+    size(PATH.to_string()) + size(format!("{}-wal", *PATH))
+}
+
 impl<K, V> Oyster<K, V>
 where
     K: Hash + Ord + Clone + Send + ToString + From<String>,
-    V: Clone + Send + ToString + From<String>,
+    V: Clone + Send + Value,
 {
     /// Dump the cache into the dump file ([`PATH`]).
-    #[cfg(not(feature = "sqlite_backend"))]
     pub fn dump(&self) -> Result<()> {
+        let started = Instant::now();
+        let result = self.dump_tables();
+        self.4.dumped(
+            started.elapsed(),
+            dump_size(),
+            result.as_ref().err().map(|e| e.to_string()),
+        );
+
+        result
+    }
+
+    /// [`Self::dump`] backend.
+    #[cfg(not(feature = "sqlite_backend"))]
+    fn dump_tables(&self) -> Result<()> {
         // create database file
-        if !fs::exists(PATH.as_ref()).unwrap_or(false) {
-            if let Err(e) = fs::write(PATH.to_string(), []) {
-                panic!("{}", e);
-            };
+        if !fs::exists(PATH.as_ref()).unwrap_or(false)
+            && let Err(e) = fs::write(PATH.to_string(), [])
+        {
+            panic!("{}", e);
         }
 
         // create database
         let conn = Connection::open(PATH.as_ref())?;
         conn.pragma_update(None, "journal_mode", "WAL")?;
 
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS map (
-                key   TEXT NOT NULL,
-                value TEXT NOT NULL,
-                used  INT
-            )",
-            (),
-        )?;
+        create_table(&conn, &self.2.table)?;
 
         for (k, v) in &self.0 {
-            self.write_into_db(k, v).unwrap();
+            let value = v.0.encoded(&self.2.compression);
+            Self::write_encoded(&conn, &self.2.table, k, &value, &v.1)?;
         }
 
         // return
@@ -55,26 +76,44 @@ where
 
         // get database connection
         let conn = Connection::open(PATH.as_ref())?;
+        create_table(&conn, &self.2.table)?;
 
         // pull data
-        let mut query = conn.prepare("SELECT \"key\", \"value\", \"used\" FROM \"map\"")?;
+        let mut query = conn.prepare(&format!(
+            "SELECT \"key\", \"value\", \"used\", \"expires\", \"flags\", \"cas\", \"kind\" FROM {}",
+            quote(&self.2.table)
+        ))?;
         let iter = query.query_map([], |row| {
             Ok((
                 row.get::<usize, String>(0)?,
-                row.get::<usize, String>(1)?,
+                column_bytes(row.get_ref(1)?),
                 row.get::<usize, usize>(2)?,
+                row.get::<usize, Option<usize>>(3)?,
+                row.get::<usize, Option<u32>>(4)?,
+                row.get::<usize, Option<u64>>(5)?,
+                row.get::<usize, Option<u8>>(6)?,
             ))
         })?;
 
         for i in iter {
-            let (key, value, used) = i.unwrap();
-            self.insert_full(
+            let (key, value, used, expires, flags, cas, kind) = i?;
+            let cas = cas.unwrap_or_else(next_cas);
+            observe_cas(cas);
+
+            // values are already encoded, so most go straight into the map
+            let kind = ValueKind::from_code(kind);
+            self.0.insert(
                 key.into(),
                 Pearl(
-                    value.into(),
+                    Stored::from_encoded(kind, value, &self.2.compression),
                     ResourceDescriptor {
                         #[cfg(feature = "lru")]
                         used,
+                        #[cfg(feature = "lru")]
+                        expires,
+                        flags: flags.unwrap_or(0),
+                        cas,
+                        kind,
                     },
                 ),
             );
@@ -88,29 +127,57 @@ where
     #[cfg(not(feature = "sqlite_backend"))]
     pub fn write_into_db(&self, k: &K, v: &Pearl<V>) -> Result<()> {
         let conn = Connection::open(PATH.as_ref())?;
+        Self::write_encoded(&conn, &self.2.table, k, &self.encode(&v.0), &v.1)
+    }
+
+    /// Write an (already encoded) key into the database.
+    #[cfg(not(feature = "sqlite_backend"))]
+    fn write_encoded(
+        conn: &Connection,
+        table: &str,
+        k: &K,
+        value: &[u8],
+        descriptor: &ResourceDescriptor,
+    ) -> Result<()> {
+        let table = quote(table);
 
         // check if key exists
-        let mut query = conn.prepare("SELECT * FROM \"map\" WHERE \"key\" = ?")?;
+        let mut query = conn.prepare(&format!("SELECT * FROM {table} WHERE \"key\" = ?"))?;
         let selected = query.query_row([k.to_string()], |row| {
             Ok((
                 row.get::<usize, String>(0)?,
-                row.get::<usize, String>(1)?,
+                column_bytes(row.get_ref(1)?),
                 row.get::<usize, usize>(2)?,
+                row.get::<usize, Option<usize>>(3)?,
+                row.get::<usize, Option<u64>>(5)?,
             ))
         });
 
         if selected.is_err() {
             // doesn't exist yet
             conn.execute(
-                "INSERT INTO \"map\" VALUES (?, ?, ?)",
-                (k.to_string(), v.0.to_string(), v.1.used),
+                &format!("INSERT INTO {table} VALUES (?, ?, ?, ?, ?, ?, ?)"),
+                (
+                    k.to_string(),
+                    value,
+                    descriptor.used,
+                    descriptor.expires,
+                    descriptor.flags,
+                    descriptor.cas,
+                    descriptor.kind.code(),
+                ),
             )?;
         } else if let Ok(existing) = selected {
-            // exists; update only if changed
-            if (existing.1 != v.0.to_string()) | (existing.2 != v.1.used) {
+            // exists; update only if changed (the cas changes whenever the flags or
+            // kind do)
+            if (existing.1 != value)
+                | (existing.2 != descriptor.used)
+                | (existing.3 != descriptor.expires)
+                | (existing.4 != Some(descriptor.cas))
+            {
                 conn.execute(
-                    "UPDATE \"map\" SET \"value\" = ?, \"used\" = ? WHERE \"key\" = ?",
-                    (v.0.to_string(), v.1.used, k.to_string()),
+                    &format!("UPDATE {table} SET \"value\" = ?, \"used\" = ?, \"expires\" = ?, \"flags\" = ?, \"cas\" = ?, \"kind\" = ? WHERE \"key\" = ?"),
+                    (value, descriptor.used, descriptor.expires, descriptor.flags, descriptor.cas, descriptor.kind.code(), k.to_string()),
                 )?;
             }
         }
@@ -126,17 +193,21 @@ where
             return Ok(());
         }
 
-        // remove key
+        // remove key (the map might not have been dumped yet)
         let conn = Connection::open(PATH.as_ref())?;
-        conn.execute("DELETE FROM \"map\" WHERE \"key\" = ?", [key.to_string()])?;
+        create_table(&conn, &self.2.table)?;
+        conn.execute(
+            &format!("DELETE FROM {} WHERE \"key\" = ?", quote(&self.2.table)),
+            [key.to_string()],
+        )?;
 
         // return
         Ok(())
     }
 
-    /// Dump the cache into the dump file ([`PATH`]).
+    /// [`Self::dump`] backend.
     #[cfg(feature = "sqlite_backend")]
-    pub fn dump(&self) -> Result<()> {
+    fn dump_tables(&self) -> Result<()> {
         if !fs::exists(PATH.as_ref()).unwrap_or(false) {
             if let Err(e) = fs::write(PATH.to_string(), []) {
                 panic!("{}", e);
@@ -146,17 +217,14 @@ where
         let conn = Connection::open(PATH.as_ref())?;
         conn.pragma_update(None, "journal_mode", "WAL")?;
 
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS map (
-                key   TEXT NOT NULL,
-                value TEXT NOT NULL,
-                used  INT
-            )",
-            (),
-        )?;
+        create_table(&conn, &self.2.table)?;
 
+        let table = quote(&self.2.table);
         conn.execute("ATTACH DATABASE 'staging.db' AS staging_db", [])?;
-        conn.execute("INSERT INTO map SELECT * FROM staging_db.map", [])?;
+        conn.execute(
+            &format!("INSERT INTO {table} SELECT * FROM staging_db.{table}"),
+            [],
+        )?;
 
         Ok(())
     }
@@ -168,24 +236,75 @@ where
             if let Err(e) = fs::write(PATH.to_string(), []) {
                 panic!("{}", e);
             };
-
-            let conn = Connection::open(PATH.as_ref())?;
-            conn.pragma_update(None, "journal_mode", "WAL")?;
-
-            conn.execute(
-                "CREATE TABLE IF NOT EXISTS map (
-                    key   TEXT NOT NULL,
-                    value TEXT NOT NULL,
-                    used  INT
-                )",
-                (),
-            )?;
         }
 
+        // make sure the dump file has a (migrated) table to copy from
+        let disk = Connection::open(PATH.as_ref())?;
+        disk.pragma_update(None, "journal_mode", "WAL")?;
+        create_table(&disk, &self.2.table)?;
+        drop(disk);
+
         let conn = crate::sqlite_backend::connect()?;
+        let table = quote(&self.2.table);
         conn.execute("ATTACH DATABASE 'dump.db' AS disk_db", [])?;
-        conn.execute("INSERT INTO map SELECT * FROM disk_db.map", [])?;
+        conn.execute(
+            &format!("INSERT INTO {table} SELECT * FROM disk_db.{table}"),
+            [],
+        )?;
 
         Ok(())
     }
+
+    /// Remove the map's table from the dump file ([`PATH`]) (and the staging
+    /// database with the `sqlite_backend` feature).
+    pub fn drop_tables(&self) -> Result<()> {
+        #[cfg(feature = "sqlite_backend")]
+        drop_table(&crate::sqlite_backend::connect()?, &self.2.table)?;
+
+        if !fs::exists(PATH.as_ref()).unwrap_or(false) {
+            return Ok(());
+        }
+
+        let conn = Connection::open(PATH.as_ref())?;
+        drop_table(&conn, &self.2.table)
+    }
+}
+
+#[cfg(all(test, not(feature = "sqlite_backend")))]
+mod test {
+    use super::PATH;
+    use crate::{Oyster, OysterOptions};
+    use rusqlite::Connection;
+    use std::fs;
+
+    #[test]
+    fn restore_corrupt() {
+        let existed = fs::exists(PATH.as_ref()).unwrap_or(false);
+        let map = || -> Oyster<String, String> {
+            Oyster::with_options(OysterOptions {
+                table: "test_restore_corrupt".to_string(),
+                ..Default::default()
+            })
+        };
+
+        let mut dumped = map();
+        dumped.insert("a".to_string(), "b".to_string());
+        dumped.dump().unwrap();
+
+        Connection::open(PATH.as_ref())
+            .unwrap()
+            .execute("UPDATE \"test_restore_corrupt\" SET \"used\" = 'soon'", [])
+            .unwrap();
+
+        // a malformed row fails the restore instead of panicking
+        let mut restored = map();
+        assert!(restored.restore().is_err());
+
+        restored.drop_tables().unwrap();
+        if !existed {
+            for suffix in ["", "-wal", "-shm"] {
+                let _ = fs::remove_file(format!("{}{suffix}", *PATH));
+            }
+        }
+    }
 }
@@ -0,0 +1,231 @@
+//! Sets (unique values, in no particular order).
+//!
+//! A set's [`ResourceDescriptor::kind`](crate::pearl::ResourceDescriptor::kind)
+//! is [`ValueKind::Set`]. Sets are kept as a [`HashSet`] in memory (see
+//! [`crate::collection`]), and their value is every member packed together (see
+//! [`crate::value`]) when they're read whole. Members are always returned sorted
+//! (by their bytes). Sets are created by adding to them, and removed once they're
+//! empty.
+use crate::collection::Collection;
+use crate::oyster::Oyster;
+use crate::pearl::ValueKind;
+use crate::value::Value;
+use std::collections::{BTreeSet, HashSet};
+use std::hash::Hash;
+
+#[cfg(not(feature = "sqlite_backend"))]
+use crate::collection::Stored;
+
+/// The members of a set.
+pub(crate) type Members = HashSet<Vec<u8>>;
+
+impl Collection for Members {
+    const KIND: ValueKind = ValueKind::Set;
+
+    fn from_items(items: Vec<Vec<u8>>) -> Option<Self> {
+        Some(items.into_iter().collect())
+    }
+
+    fn to_items(&self) -> Vec<Vec<u8>> {
+        let mut items: Vec<Vec<u8>> = self.iter().cloned().collect();
+        items.sort_unstable();
+        items
+    }
+
+    #[cfg(not(feature = "sqlite_backend"))]
+    fn is_empty(&self) -> bool {
+        HashSet::is_empty(self)
+    }
+
+    #[cfg(not(feature = "sqlite_backend"))]
+    fn memory_usage(&self) -> usize {
+        self.iter()
+            .map(|x| x.capacity() + std::mem::size_of::<Vec<u8>>())
+            .sum()
+    }
+
+    #[cfg(not(feature = "sqlite_backend"))]
+    fn stored(stored: &Stored) -> Option<&Self> {
+        match stored {
+            Stored::Set(x) => Some(x),
+            _ => None,
+        }
+    }
+
+    #[cfg(not(feature = "sqlite_backend"))]
+    fn stored_mut(stored: &mut Stored) -> Option<&mut Self> {
+        match stored {
+            Stored::Set(x) => Some(x),
+            _ => None,
+        }
+    }
+
+    #[cfg(not(feature = "sqlite_backend"))]
+    fn into_stored(self) -> Stored {
+        Stored::Set(self)
+    }
+}
+
+impl<K, V> Oyster<K, V>
+where
+    K: Hash + Ord + Clone + Send + ToString + From<String>,
+    V: Clone + Send + Value,
+{
+    /// Add values to a set (which is created if it doesn't exist). The set keeps
+    /// its expiry time.
+    ///
+    /// # Arguments
+    /// * `key` - the key the set is stored in
+    /// * `values` - the values to add
+    ///
+    /// # Returns
+    /// The number of values which weren't in the set yet, or `None` if the key
+    /// holds something which isn't a set.
+    pub fn set_add(&mut self, key: K, values: Vec<V>) -> Option<usize> {
+        self.update_collection(key, |members: &mut Members| {
+            let added = values
+                .iter()
+                .filter(|x| members.insert(x.to_bytes()))
+                .count();
+            (added, added > 0)
+        })
+    }
+
+    /// Remove values from a set (the set is removed once it's empty).
+    ///
+    /// # Returns
+    /// The number of values which were in the set, or `None` if the key holds
+    /// something which isn't a set.
+    pub fn set_remove(&mut self, key: &K, values: &[V]) -> Option<usize> {
+        self.update_collection(key.clone(), |members: &mut Members| {
+            let removed = values
+                .iter()
+                .filter(|x| members.remove(&x.to_bytes()))
+                .count();
+            (removed, removed > 0)
+        })
+    }
+
+    /// Get every member of a set (none if it doesn't exist).
+    ///
+    /// # Returns
+    /// `None` if the key holds something which isn't a set.
+    pub fn set_members(&self, key: &K) -> Option<Vec<V>> {
+        let items = self.read_collection(key, |members: &Members| members.to_items())?;
+        Some(items.into_iter().map(V::from_bytes).collect())
+    }
+
+    /// If `value` is a member of a set.
+    ///
+    /// # Returns
+    /// `None` if the key holds something which isn't a set.
+    pub fn set_is_member(&self, key: &K, value: &V) -> Option<bool> {
+        self.read_collection(key, |members: &Members| members.contains(&value.to_bytes()))
+    }
+
+    /// Get the number of members in a set (`0` if it doesn't exist).
+    ///
+    /// # Returns
+    /// `None` if the key holds something which isn't a set.
+    pub fn set_len(&self, key: &K) -> Option<usize> {
+        self.read_collection(key, |members: &Members| members.len())
+    }
+
+    /// Get every value which is a member of any of the sets stored in `keys`.
+    ///
+    /// # Returns
+    /// `None` if any of the keys holds something which isn't a set.
+    pub fn set_union(&self, keys: &[K]) -> Option<Vec<V>> {
+        let mut union = BTreeSet::new();
+        for key in keys {
+            self.read_collection(key, |members: &Members| {
+                union.extend(members.iter().cloned())
+            })?;
+        }
+
+        Some(union.into_iter().map(V::from_bytes).collect())
+    }
+
+    /// Get every value which is a member of all of the sets stored in `keys`
+    /// (none if there are no keys).
+    ///
+    /// # Returns
+    /// `None` if any of the keys holds something which isn't a set.
+    pub fn set_intersect(&self, keys: &[K]) -> Option<Vec<V>> {
+        let mut lens = Vec::with_capacity(keys.len());
+        for key in keys {
+            lens.push((self.set_len(key)?, key));
+        }
+
+        // start with the smallest set, since the intersection can't be bigger
+        lens.sort_by_key(|x| x.0);
+        let mut keys = lens.into_iter().map(|x| x.1);
+        let Some(first) = keys.next() else {
+            return Some(Vec::new());
+        };
+
+        let mut intersection =
+            self.read_collection(first, |members: &Members| members.to_items())?;
+        for key in keys {
+            self.read_collection(key, |members: &Members| {
+                intersection.retain(|x| members.contains(x))
+            })?;
+        }
+
+        Some(intersection.into_iter().map(V::from_bytes).collect())
+    }
+}
+
+#[cfg(all(test, not(feature = "sqlite_backend")))]
+mod test {
+    use crate::Oyster;
+
+    #[test]
+    fn set() {
+        let mut map: Oyster<String, String> = Oyster::new();
+        let strings = |x: &[&str]| x.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+        let (a, b, c) = (
+            "tags:a".to_string(),
+            "tags:b".to_string(),
+            "tags:c".to_string(),
+        );
+
+        assert_eq!(map.set_add(a.clone(), strings(&["x", "y", "x"])), Some(2));
+        assert_eq!(map.set_add(a.clone(), strings(&["y", "z"])), Some(1));
+        assert_eq!(map.set_members(&a), Some(strings(&["x", "y", "z"])));
+        assert_eq!(map.set_is_member(&a, &"y".to_string()), Some(true));
+        assert_eq!(map.set_is_member(&a, &"w".to_string()), Some(false));
+
+        map.set_add(b.clone(), strings(&["y", "z", "w"]));
+        assert_eq!(
+            map.set_union(&[a.clone(), b.clone()]),
+            Some(strings(&["w", "x", "y", "z"]))
+        );
+        assert_eq!(
+            map.set_intersect(&[a.clone(), b.clone()]),
+            Some(strings(&["y", "z"]))
+        );
+        // missing sets are empty
+        assert_eq!(map.set_intersect(&[a.clone(), c.clone()]), Some(vec![]));
+        assert_eq!(map.set_union(&[a.clone(), c.clone()]).unwrap().len(), 3);
+
+        // empty sets are removed
+        assert_eq!(map.set_remove(&a, &strings(&["x", "y", "q"])), Some(2));
+        assert_eq!(map.set_remove(&a, &strings(&["z"])), Some(1));
+        assert_eq!(map.get(&a), None);
+        assert_eq!(map.set_len(&a), Some(0));
+
+        // sets are packed when they're read whole, and unpacked when they're written
+        let pearl = map.get_full(&b).unwrap();
+        assert_eq!(pearl.0, "1:w1:y1:z");
+        map.insert_full(c.clone(), pearl);
+        assert_eq!(map.set_is_member(&c, &"w".to_string()), Some(true));
+        assert_eq!(map.set_add(c.clone(), strings(&["v"])), Some(1));
+        assert_eq!(map.get(&c), Some("1:v1:w1:y1:z".to_string()));
+
+        // other values aren't sets
+        map.insert("plain".to_string(), "1".to_string());
+        assert_eq!(map.set_add("plain".to_string(), strings(&["a"])), None);
+        assert_eq!(map.set_union(&[b, "plain".to_string()]), None);
+    }
+}
@@ -0,0 +1,323 @@
+//! Sorted sets (unique values ordered by a score), for things like leaderboards.
+//!
+//! A sorted set's
+//! [`ResourceDescriptor::kind`](crate::pearl::ResourceDescriptor::kind) is
+//! [`ValueKind::SortedSet`]. Sorted sets are kept as [`Members`] in memory (see
+//! [`crate::collection`]): every member in order, and the score of every member,
+//! so scores are found without a scan and ranges by score are found with a
+//! lookup. When a sorted set is read whole, its value is every member followed
+//! by its score (as a decimal string), packed together in order (see
+//! [`crate::value`]).
+//!
+//! Members are ordered by score, then by their bytes (for members with the same
+//! score). Ranks start at `0` (the member with the lowest score). The order
+//! doesn't know how many members come before a member, so finding a rank (or the
+//! members between two ranks) walks the set from the lowest score, and takes
+//! longer the higher the rank is.
+use crate::collection::Collection;
+use crate::list::span;
+use crate::oyster::Oyster;
+use crate::pearl::ValueKind;
+use crate::value::Value;
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap};
+use std::hash::Hash;
+
+#[cfg(not(feature = "sqlite_backend"))]
+use crate::collection::Stored;
+
+/// A score, ordered with [`f64::total_cmp`].
+#[derive(Clone, Copy, Debug)]
+struct Score(f64);
+
+impl PartialEq for Score {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for Score {}
+
+impl PartialOrd for Score {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Score {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+/// The members of a sorted set.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Members {
+    /// Every member (with its score), in order.
+    order: BTreeSet<(Score, Vec<u8>)>,
+    /// The score of every member.
+    scores: HashMap<Vec<u8>, f64>,
+}
+
+impl Members {
+    /// Set the score of a member.
+    ///
+    /// # Returns
+    /// The old score of the member (`None` if it's new).
+    fn insert(&mut self, member: Vec<u8>, score: f64) -> Option<f64> {
+        let old = self.scores.insert(member.clone(), score);
+        if let Some(old) = old {
+            self.order.remove(&(Score(old), member.clone()));
+        }
+
+        self.order.insert((Score(score), member));
+        old
+    }
+
+    /// Remove a member.
+    ///
+    /// # Returns
+    /// If the member was in the set.
+    fn remove(&mut self, member: &[u8]) -> bool {
+        match self.scores.remove_entry(member) {
+            Some((member, score)) => self.order.remove(&(Score(score), member)),
+            None => false,
+        }
+    }
+
+    /// Get the rank of a member. Every member before it is counted, so this is
+    /// `O(rank)`.
+    fn rank(&self, member: &[u8]) -> Option<usize> {
+        let (member, score) = self.scores.get_key_value(member)?;
+        Some(self.order.range(..(Score(*score), member.clone())).count())
+    }
+
+    /// Get the members with a score between `min` and `max` (both included).
+    fn range_by_score(&self, min: f64, max: f64) -> impl Iterator<Item = &(Score, Vec<u8>)> {
+        self.order
+            .range((Score(min), Vec::new())..)
+            .take_while(move |x| x.0.0 <= max)
+    }
+}
+
+impl Collection for Members {
+    const KIND: ValueKind = ValueKind::SortedSet;
+
+    fn from_items(items: Vec<Vec<u8>>) -> Option<Self> {
+        if !items.len().is_multiple_of(2) {
+            return None;
+        }
+
+        let mut members = Self::default();
+        let mut items = items.into_iter();
+        while let (Some(member), Some(score)) = (items.next(), items.next()) {
+            members.insert(member, String::from_utf8_lossy(&score).parse().ok()?);
+        }
+
+        Some(members)
+    }
+
+    fn to_items(&self) -> Vec<Vec<u8>> {
+        self.order
+            .iter()
+            .flat_map(|(score, member)| [member.clone(), score.0.to_string().into_bytes()])
+            .collect()
+    }
+
+    #[cfg(not(feature = "sqlite_backend"))]
+    fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+
+    #[cfg(not(feature = "sqlite_backend"))]
+    fn memory_usage(&self) -> usize {
+        self.order
+            .iter()
+            .map(|x| 2 * (x.1.capacity() + std::mem::size_of::<(Score, Vec<u8>)>()))
+            .sum()
+    }
+
+    #[cfg(not(feature = "sqlite_backend"))]
+    fn stored(stored: &Stored) -> Option<&Self> {
+        match stored {
+            Stored::SortedSet(x) => Some(x),
+            _ => None,
+        }
+    }
+
+    #[cfg(not(feature = "sqlite_backend"))]
+    fn stored_mut(stored: &mut Stored) -> Option<&mut Self> {
+        match stored {
+            Stored::SortedSet(x) => Some(x),
+            _ => None,
+        }
+    }
+
+    #[cfg(not(feature = "sqlite_backend"))]
+    fn into_stored(self) -> Stored {
+        Stored::SortedSet(self)
+    }
+}
+
+/// Turn members of a sorted set into values (with their scores).
+fn scored<'a, V: Value>(members: impl Iterator<Item = &'a (Score, Vec<u8>)>) -> Vec<(V, f64)> {
+    members
+        .map(|(score, member)| (V::from_bytes(member.clone()), score.0))
+        .collect()
+}
+
+impl<K, V> Oyster<K, V>
+where
+    K: Hash + Ord + Clone + Send + ToString + From<String>,
+    V: Clone + Send + Value,
+{
+    /// Set the score of a member of a sorted set (which is created if it doesn't
+    /// exist). The set keeps its expiry time.
+    ///
+    /// # Arguments
+    /// * `key` - the key the set is stored in
+    /// * `value` - the member
+    /// * `score` - the score of the member
+    ///
+    /// # Returns
+    /// If the member is new, or `None` if the key holds something which isn't a
+    /// sorted set.
+    pub fn sorted_add(&mut self, key: K, value: V, score: f64) -> Option<bool> {
+        self.update_collection(key, |members: &mut Members| {
+            let old = members.insert(value.to_bytes(), score);
+            (
+                old.is_none(),
+                old.is_none_or(|x| x.total_cmp(&score).is_ne()),
+            )
+        })
+    }
+
+    /// Add `by` to the score of a member (which is `0` if it isn't in the set).
+    ///
+    /// # Returns
+    /// The new score, or `None` if the key holds something which isn't a sorted
+    /// set.
+    pub fn sorted_incr(&mut self, key: K, value: V, by: f64) -> Option<f64> {
+        self.update_collection(key, |members: &mut Members| {
+            let value = value.to_bytes();
+            let old = members.scores.get(&value).copied();
+            let score = old.unwrap_or(0.0) + by;
+            members.insert(value, score);
+            (score, old != Some(score))
+        })
+    }
+
+    /// Remove a member from a sorted set (the set is removed once it's empty).
+    ///
+    /// # Returns
+    /// If the member was in the set, or `None` if the key holds something which
+    /// isn't a sorted set.
+    pub fn sorted_remove(&mut self, key: &K, value: &V) -> Option<bool> {
+        self.update_collection(key.clone(), |members: &mut Members| {
+            let removed = members.remove(&value.to_bytes());
+            (removed, removed)
+        })
+    }
+
+    /// Get the score of a member.
+    ///
+    /// # Returns
+    /// `None` if the member isn't in the set, or the key holds something which
+    /// isn't a sorted set.
+    pub fn sorted_score(&self, key: &K, value: &V) -> Option<f64> {
+        self.read_collection(key, |members: &Members| {
+            members.scores.get(&value.to_bytes()).copied()
+        })?
+    }
+
+    /// Get the rank of a member (its position in the set, from `0`). This counts
+    /// every member with a lower rank, so it's slower for members with higher
+    /// ranks in large sets.
+    ///
+    /// # Returns
+    /// `None` if the member isn't in the set, or the key holds something which
+    /// isn't a sorted set.
+    pub fn sorted_rank(&self, key: &K, value: &V) -> Option<usize> {
+        self.read_collection(key, |members: &Members| members.rank(&value.to_bytes()))?
+    }
+
+    /// Get the members between two ranks (both included), with their scores.
+    /// Negative ranks count from the end of the set (`-1` is the member with the
+    /// highest score).
+    ///
+    /// # Returns
+    /// `None` if the key holds something which isn't a sorted set.
+    pub fn sorted_range(&self, key: &K, start: isize, stop: isize) -> Option<Vec<(V, f64)>> {
+        self.read_collection(key, |members: &Members| {
+            let span = span(members.order.len(), start, stop);
+            scored(members.order.iter().skip(span.start).take(span.len()))
+        })
+    }
+
+    /// Get the members with a score between `min` and `max` (both included),
+    /// with their scores.
+    ///
+    /// # Returns
+    /// `None` if the key holds something which isn't a sorted set.
+    pub fn sorted_range_by_score(&self, key: &K, min: f64, max: f64) -> Option<Vec<(V, f64)>> {
+        self.read_collection(key, |members: &Members| {
+            scored(members.range_by_score(min, max))
+        })
+    }
+
+    /// Get the number of members in a sorted set (`0` if it doesn't exist).
+    ///
+    /// # Returns
+    /// `None` if the key holds something which isn't a sorted set.
+    pub fn sorted_len(&self, key: &K) -> Option<usize> {
+        self.read_collection(key, |members: &Members| members.scores.len())
+    }
+}
+
+#[cfg(all(test, not(feature = "sqlite_backend")))]
+mod test {
+    use crate::Oyster;
+
+    #[test]
+    fn sorted_set() {
+        let mut map: Oyster<String, String> = Oyster::new();
+        let key = "leaderboard".to_string();
+        let s = |x: &str| x.to_string();
+
+        assert_eq!(map.sorted_add(key.clone(), s("b"), 20.0), Some(true));
+        assert_eq!(map.sorted_add(key.clone(), s("a"), 10.0), Some(true));
+        assert_eq!(map.sorted_add(key.clone(), s("c"), 10.0), Some(true));
+        assert_eq!(map.sorted_add(key.clone(), s("a"), 30.0), Some(false));
+        assert_eq!(map.sorted_len(&key), Some(3));
+
+        assert_eq!(
+            map.sorted_range(&key, 0, -1),
+            Some(vec![(s("c"), 10.0), (s("b"), 20.0), (s("a"), 30.0)])
+        );
+        assert_eq!(map.sorted_range(&key, -1, -1), Some(vec![(s("a"), 30.0)]));
+        assert_eq!(
+            map.sorted_range_by_score(&key, 15.0, 30.0),
+            Some(vec![(s("b"), 20.0), (s("a"), 30.0)])
+        );
+        assert_eq!(map.sorted_range_by_score(&key, 40.0, 10.0), Some(vec![]));
+
+        assert_eq!(map.sorted_incr(key.clone(), s("c"), 15.5), Some(25.5));
+        assert_eq!(map.sorted_incr(key.clone(), s("d"), -1.0), Some(-1.0));
+        assert_eq!(map.sorted_rank(&key, &s("d")), Some(0));
+        assert_eq!(map.sorted_rank(&key, &s("c")), Some(2));
+        assert_eq!(map.sorted_score(&key, &s("c")), Some(25.5));
+        assert_eq!(map.sorted_rank(&key, &s("z")), None);
+
+        // empty sorted sets are removed
+        for member in ["a", "b", "c", "d"] {
+            assert_eq!(map.sorted_remove(&key, &s(member)), Some(true));
+        }
+        assert_eq!(map.sorted_remove(&key, &s("a")), Some(false));
+        assert_eq!(map.get(&key), None);
+
+        // sets aren't sorted sets
+        map.set_add(s("set"), vec![s("a")]);
+        assert_eq!(map.sorted_add(s("set"), s("a"), 1.0), None);
+        assert_eq!(map.sorted_len(&s("set")), None);
+    }
+}
@@ -1,6 +1,12 @@
-use crate::oyster::Oyster;
-use crate::{Pearl, pearl::ResourceDescriptor};
-use rusqlite::{Connection, Result};
+use crate::Pearl;
+use crate::db::{column_bytes, create_table, quote};
+use crate::events::{self, EventKind};
+use crate::metrics::Metrics;
+use crate::oyster::{Oyster, OysterOptions};
+use crate::pearl::{EPOCH_YEAR, ResourceDescriptor, ValueKind, next_cas, observe_cas};
+use crate::time::epoch_timestamp;
+use crate::value::{Value, add_to_number, missing_number};
+use rusqlite::{Connection, Params, Result};
 use std::hash::Hash;
 use std::marker::PhantomData;
 
@@ -9,26 +15,57 @@ pub(crate) fn connect() -> Result<Connection> {
     Ok(Connection::open("staging.db")?)
 }
 
+/// Run a `DELETE` statement, and get the keys of the rows it removed.
+///
+/// # Arguments
+/// * `conn` - the connection to run the statement on
+/// * `sql` - the statement (without a `RETURNING` clause)
+/// * `params` - the parameters of the statement
+pub(crate) fn delete_returning_keys(
+    conn: &Connection,
+    sql: &str,
+    params: impl Params,
+) -> Result<Vec<String>> {
+    let mut query = conn.prepare(&format!("{sql} RETURNING \"key\""))?;
+    query
+        .query_map(params, |row| row.get::<usize, String>(0))?
+        .collect()
+}
+
 impl<K, V> Oyster<K, V>
 where
     K: Hash + Ord + Clone + Send + ToString + From<String>,
-    V: Clone + Send + ToString + From<String>,
+    V: Clone + Send + Value,
 {
+    /// Create a new [`Oyster`].
     pub fn new() -> Self {
+        Self::with_options(OysterOptions::default())
+    }
+
+    /// Create a new [`Oyster`] with the given `options`.
+    pub fn with_options(options: OysterOptions) -> Self {
         let conn = connect().unwrap();
         conn.pragma_update(None, "journal_mode", "WAL").unwrap();
 
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS map (
-                key   TEXT NOT NULL,
-                value TEXT NOT NULL,
-                used  INT
-            )",
-            (),
+        create_table(&conn, &options.table).unwrap();
+
+        // don't hand out cas values which are already in the table
+        let cas: Option<u64> = conn
+            .query_row(
+                &format!("SELECT MAX(\"cas\") FROM {}", quote(&options.table)),
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        observe_cas(cas.unwrap_or(0));
+
+        Self(
+            PhantomData,
+            PhantomData,
+            options,
+            events::channel(),
+            Metrics::default(),
         )
-        .unwrap();
-
-        Self(PhantomData, PhantomData)
     }
 
     /// Insert a value given its `key` and `value`.
@@ -45,81 +82,129 @@ where
     /// # Arguments
     /// * `key` - the key to store the value in
     /// * `value` - the actual value (as [`Pearl<V>`])
-    pub fn insert_full(&self, key: K, value: Pearl<V>) -> Option<()> {
+    pub fn insert_full(&self, key: K, mut value: Pearl<V>) -> Option<()> {
+        value.1.cas = next_cas();
+        let value = Pearl(self.encode(&value.0), value.1);
         let conn = match connect() {
             Ok(c) => c,
             Err(_) => return None,
         };
 
-        if let Err(_) = conn.execute(
-            "CREATE TABLE IF NOT EXISTS map (
-                key   TEXT NOT NULL,
-                value TEXT NOT NULL,
-                used  INT
-            )",
-            (),
-        ) {
+        if create_table(&conn, &self.2.table).is_err() {
             return None;
         };
 
+        let table = quote(&self.2.table);
+
         // check if key exists
-        let mut query = match conn.prepare("SELECT * FROM \"map\" WHERE \"key\" = ?") {
+        let mut query = match conn.prepare(&format!("SELECT * FROM {table} WHERE \"key\" = ?")) {
             Ok(q) => q,
             Err(_) => return None,
         };
 
-        let selected = query.query_row([key.to_string()], |row| {
-            Ok((
-                row.get::<usize, String>(0)?,
-                row.get::<usize, String>(1)?,
-                row.get::<usize, usize>(2)?,
-            ))
-        });
+        let selected = query.query_row([key.to_string()], |row| row.get::<usize, String>(0));
 
         if selected.is_err() {
-            // doesn't exist yet
+            // doesn't exist yet; make room for it first
+            if let Some(capacity) = self.2.capacity
+                && let Ok(evicted) = delete_returning_keys(
+                    &conn,
+                    &format!(
+                        "DELETE FROM {table} WHERE \"key\" IN (SELECT \"key\" FROM {table} ORDER BY \"used\" LIMIT MAX(0, (SELECT COUNT(*) FROM {table}) - ?))"
+                    ),
+                    [capacity.max(1) - 1],
+                )
+            {
+                self.4.evicted(evicted.len());
+                for evicted in evicted {
+                    self.notify(EventKind::Evict, &evicted.into());
+                }
+            }
+
             if let Err(_) = conn.execute(
-                "INSERT INTO \"map\" VALUES (?, ?, ?)",
-                (key.to_string(), value.0.to_string(), value.1.used),
+                &format!("INSERT INTO {table} VALUES (?, ?, ?, ?, ?, ?, ?)"),
+                (
+                    key.to_string(),
+                    &value.0,
+                    value.1.used,
+                    value.1.expires,
+                    value.1.flags,
+                    value.1.cas,
+                    value.1.kind.code(),
+                ),
             ) {
                 return None;
             };
-        } else if let Ok(existing) = selected {
-            // exists; update only if changed
-            if (existing.1 != value.0.to_string()) | (existing.2 != value.1.used) {
-                if let Err(_) = conn.execute(
-                    "UPDATE \"map\" SET \"value\" = ?, \"used\" = ? WHERE \"key\" = ?",
-                    (value.0.to_string(), value.1.used, key.to_string()),
-                ) {
-                    return None;
-                };
-            }
+        } else {
+            // exists; update (the cas always changes)
+            if conn
+                .execute(
+                &format!("UPDATE {table} SET \"value\" = ?, \"used\" = ?, \"expires\" = ?, \"flags\" = ?, \"cas\" = ?, \"kind\" = ? WHERE \"key\" = ?"),
+                (
+                    &value.0,
+                    value.1.used,
+                    value.1.expires,
+                    value.1.flags,
+                    value.1.cas,
+                    value.1.kind.code(),
+                    key.to_string(),
+                ),
+            ).is_err() {
+                return None;
+            };
         }
 
+        self.notify(EventKind::Insert, &key);
         Some(())
     }
 
-    /// Increment the value of a key.
+    /// Insert a value, but only if it hasn't been changed since it was read
+    /// (compare-and-swap).
     ///
     /// # Arguments
     /// * `key` - the key to store the value in
-    pub fn incr(&mut self, key: K) -> Option<()> {
-        let value = self.get(&key)?.to_string().parse::<usize>().unwrap();
-        self.insert(key, (value + 1).to_string().into())
+    /// * `cas` - the [`ResourceDescriptor::cas`] of the value when it was read
+    /// * `value` - the actual value (as [`Pearl<V>`])
+    ///
+    /// # Returns
+    /// `None` if the value doesn't exist, `Some(false)` if it has been changed.
+    pub fn compare_and_swap(&mut self, key: K, cas: u64, value: Pearl<V>) -> Option<bool> {
+        if self.get_full(&key)?.1.cas != cas {
+            return Some(false);
+        }
+
+        self.insert_full(key, value)?;
+        Some(true)
     }
 
-    /// Decrement the value of a key.
+    /// Increment the number (a signed 64-bit integer) stored in a key. The key
+    /// keeps its expiry time.
     ///
     /// # Arguments
-    /// * `key` - the key to store the value in
-    pub fn decr(&mut self, key: K) -> Option<()> {
-        let mut value = self.get(&key)?.to_string().parse::<usize>().unwrap();
+    /// * `key` - the key the number is stored in
+    ///
+    /// # Returns
+    /// The new number. Fails with [`ErrorKind::NotFound`](std::io::ErrorKind)
+    /// if the key doesn't exist, `InvalidData` if it doesn't hold a number, or
+    /// `InvalidInput` if the number would overflow.
+    pub fn incr(&mut self, key: K) -> std::io::Result<i64> {
+        self.add(key, 1)
+    }
 
-        if value == 0 {
-            value += 1; // this will make the value just end up as 0
-        }
+    /// Decrement the number stored in a key (see [`Self::incr`]).
+    ///
+    /// # Arguments
+    /// * `key` - the key the number is stored in
+    pub fn decr(&mut self, key: K) -> std::io::Result<i64> {
+        self.add(key, -1)
+    }
 
-        self.insert(key, (value - 1).to_string().into())
+    /// [`Self::incr`] and [`Self::decr`] backend.
+    fn add(&mut self, key: K, delta: i64) -> std::io::Result<i64> {
+        let mut pearl = self.get_full(&key).ok_or_else(missing_number)?;
+        let number = add_to_number(&mut pearl, delta)?;
+        self.insert_full(key, pearl);
+        Ok(number)
     }
 
     /// Get a value given its `key`.
@@ -139,12 +224,22 @@ where
     /// # Arguments
     /// * `key` - the key the value is stored in
     pub fn get_full(&self, key: &K) -> Option<Pearl<V>> {
+        let found = self.get_stored(key);
+        self.4.lookup(found.is_some());
+        found
+    }
+
+    /// [`Self::get_full`] backend (without counting the read).
+    fn get_stored(&self, key: &K) -> Option<Pearl<V>> {
         let conn = match connect() {
             Ok(c) => c,
             Err(_) => return None,
         };
 
-        let mut query = match conn.prepare("SELECT * FROM \"map\" WHERE \"key\" = ? LIMIT 1") {
+        let mut query = match conn.prepare(&format!(
+            "SELECT * FROM {} WHERE \"key\" = ? LIMIT 1",
+            quote(&self.2.table)
+        )) {
             Ok(q) => q,
             Err(_) => return None,
         };
@@ -152,15 +247,81 @@ where
         match query.query_row([key.to_string()], |row| {
             Ok((
                 row.get::<usize, String>(0)?,
-                row.get::<usize, String>(1)?,
+                column_bytes(row.get_ref(1)?),
                 row.get::<usize, usize>(2)?,
+                row.get::<usize, Option<usize>>(3)?,
+                row.get::<usize, Option<u32>>(4)?,
+                row.get::<usize, Option<u64>>(5)?,
+                row.get::<usize, Option<u8>>(6)?,
             ))
         }) {
-            Ok(r) => Some(Pearl(r.1.into(), ResourceDescriptor { used: r.2 })),
+            Ok(r) => Some(Pearl(
+                self.decode(&r.1)?,
+                ResourceDescriptor {
+                    used: r.2,
+                    expires: r.3,
+                    flags: r.4.unwrap_or(0),
+                    cas: r.5.unwrap_or(0),
+                    kind: ValueKind::from_code(r.6),
+                },
+            ))
+            .filter(|x| !x.1.is_expired()),
             Err(_) => None,
         }
     }
 
+    /// The number of items in the map (including expired items which haven't
+    /// been removed by a scan yet).
+    pub fn len(&self) -> usize {
+        connect()
+            .and_then(|conn| {
+                conn.query_row(
+                    &format!("SELECT COUNT(*) FROM {}", quote(&self.2.table)),
+                    [],
+                    |row| row.get(0),
+                )
+            })
+            .unwrap_or(0)
+    }
+
+    /// If the map has no items.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Estimate how much space (in bytes) the items in the map use.
+    pub fn memory_usage(&self) -> usize {
+        connect()
+            .and_then(|conn| {
+                conn.query_row(
+                    &format!(
+                        "SELECT COALESCE(SUM(LENGTH(\"key\") + LENGTH(\"value\") + 32), 0) FROM {}",
+                        quote(&self.2.table)
+                    ),
+                    [],
+                    |row| row.get(0),
+                )
+            })
+            .unwrap_or(0)
+    }
+
+    /// Count the `top` most common key prefixes (see
+    /// [`Stats::prefixes`](crate::metrics::Stats::prefixes)).
+    pub fn prefixes(&self, top: usize) -> Vec<(String, usize)> {
+        let count = |conn: Connection| -> Result<Vec<(String, usize)>> {
+            let mut query = conn.prepare(&format!(
+                "SELECT SUBSTR(\"key\", 1, INSTR(\"key\", ':') - 1) AS \"prefix\", COUNT(*) AS \"count\" FROM {} WHERE INSTR(\"key\", ':') > 0 GROUP BY \"prefix\" ORDER BY \"count\" DESC, \"prefix\" LIMIT ?",
+                quote(&self.2.table)
+            ))?;
+
+            query
+                .query_map([top], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect()
+        };
+
+        connect().and_then(count).unwrap_or_default()
+    }
+
     /// Get all items where their key starts with the given `prefix`.
     ///
     /// # Arguments
@@ -171,7 +332,10 @@ where
             Err(_) => return Vec::new(),
         };
 
-        let mut query = match conn.prepare("SELECT * FROM \"map\" WHERE \"key\" LIKE ? LIMIT 1") {
+        let mut query = match conn.prepare(&format!(
+            "SELECT * FROM {} WHERE \"key\" LIKE ?",
+            quote(&self.2.table)
+        )) {
             Ok(q) => q,
             Err(_) => return Vec::new(),
         };
@@ -180,18 +344,35 @@ where
             .query_map([format!("{prefix}%")], |row| {
                 Ok((
                     row.get::<usize, String>(0)?,
-                    row.get::<usize, String>(1)?,
+                    column_bytes(row.get_ref(1)?),
                     row.get::<usize, usize>(2)?,
+                    row.get::<usize, Option<usize>>(3)?,
+                    row.get::<usize, Option<u32>>(4)?,
+                    row.get::<usize, Option<u64>>(5)?,
+                    row.get::<usize, Option<u8>>(6)?,
                 ))
             })
             .expect("failed to query rows");
 
+        let now = epoch_timestamp(EPOCH_YEAR);
         let mut out = Vec::new();
         for row in selected {
-            if let Ok(row) = row {
+            if let Ok(row) = row
+                && row.3.is_none_or(|x| x > now)
+                && let Some(value) = self.decode(&row.1)
+            {
                 out.push((
                     row.0.into(),
-                    Pearl(row.1.into(), ResourceDescriptor { used: row.2 }),
+                    Pearl(
+                        value,
+                        ResourceDescriptor {
+                            used: row.2,
+                            expires: row.3,
+                            flags: row.4.unwrap_or(0),
+                            cas: row.5.unwrap_or(0),
+                            kind: ValueKind::from_code(row.6),
+                        },
+                    ),
                 ))
             }
         }
@@ -209,7 +390,10 @@ where
             Err(_) => return Vec::new(),
         };
 
-        let mut query = match conn.prepare("SELECT * FROM \"map\" WHERE \"key\" LIKE ? LIMIT 1") {
+        let mut query = match conn.prepare(&format!(
+            "SELECT * FROM {} WHERE \"key\" LIKE ?",
+            quote(&self.2.table)
+        )) {
             Ok(q) => q,
             Err(_) => return Vec::new(),
         };
@@ -218,18 +402,35 @@ where
             .query_map([format!("%{suffix}")], |row| {
                 Ok((
                     row.get::<usize, String>(0)?,
-                    row.get::<usize, String>(1)?,
+                    column_bytes(row.get_ref(1)?),
                     row.get::<usize, usize>(2)?,
+                    row.get::<usize, Option<usize>>(3)?,
+                    row.get::<usize, Option<u32>>(4)?,
+                    row.get::<usize, Option<u64>>(5)?,
+                    row.get::<usize, Option<u8>>(6)?,
                 ))
             })
             .expect("failed to query rows");
 
+        let now = epoch_timestamp(EPOCH_YEAR);
         let mut out = Vec::new();
         for row in selected {
-            if let Ok(row) = row {
+            if let Ok(row) = row
+                && row.3.is_none_or(|x| x > now)
+                && let Some(value) = self.decode(&row.1)
+            {
                 out.push((
                     row.0.into(),
-                    Pearl(row.1.into(), ResourceDescriptor { used: row.2 }),
+                    Pearl(
+                        value,
+                        ResourceDescriptor {
+                            used: row.2,
+                            expires: row.3,
+                            flags: row.4.unwrap_or(0),
+                            cas: row.5.unwrap_or(0),
+                            kind: ValueKind::from_code(row.6),
+                        },
+                    ),
                 ))
             }
         }
@@ -297,13 +498,22 @@ where
             Err(_) => return None,
         };
 
-        let mut query = match conn.prepare("DELETE FROM \"map\" WHERE \"key\" = ?") {
+        let mut query = match conn.prepare(&format!(
+            "DELETE FROM {} WHERE \"key\" = ?",
+            quote(&self.2.table)
+        )) {
             Ok(q) => q,
             Err(_) => return None,
         };
 
         match query.execute([key.to_string()]) {
-            Ok(_) => Some(()),
+            Ok(removed) => {
+                if removed > 0 {
+                    self.notify(EventKind::Remove, key);
+                }
+
+                Some(())
+            }
             Err(_) => None,
         }
     }
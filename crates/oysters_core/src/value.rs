@@ -0,0 +1,169 @@
+//! Values which can be stored in an [`Oyster`](crate::Oyster).
+//!
+//! Every value is stored as bytes, so anything which can be turned into bytes (and
+//! back again) can be stored. This includes binary data, like images or serialized
+//! structs, which isn't valid UTF-8.
+use crate::pearl::{Pearl, ValueKind};
+use std::io::{Error, ErrorKind, Result};
+
+/// A value which can be stored as bytes.
+pub trait Value {
+    /// Get the bytes of the value.
+    fn to_bytes(&self) -> Vec<u8>;
+
+    /// Create a value from bytes created by [`Self::to_bytes`].
+    fn from_bytes(bytes: Vec<u8>) -> Self;
+}
+
+/// Strings are stored as UTF-8. Invalid UTF-8 is replaced when the value is read.
+impl Value for String {
+    fn to_bytes(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+
+    fn from_bytes(bytes: Vec<u8>) -> Self {
+        match String::from_utf8(bytes) {
+            Ok(s) => s,
+            Err(e) => String::from_utf8_lossy(e.as_bytes()).to_string(),
+        }
+    }
+}
+
+/// Byte arrays are stored as-is.
+impl Value for Vec<u8> {
+    fn to_bytes(&self) -> Vec<u8> {
+        self.clone()
+    }
+
+    fn from_bytes(bytes: Vec<u8>) -> Self {
+        bytes
+    }
+}
+
+/// Add `delta` to a value holding a number (a signed 64-bit integer), and touch
+/// it (used by `incr` and `decr`).
+///
+/// # Returns
+/// The new number. Fails with [`ErrorKind::InvalidData`] if the value isn't a
+/// number, or [`ErrorKind::InvalidInput`] if the number would overflow.
+pub(crate) fn add_to_number<V: Clone + Value>(pearl: &mut Pearl<V>, delta: i64) -> Result<i64> {
+    let number: i64 = match pearl.1.kind {
+        ValueKind::String => String::from_utf8_lossy(&pearl.0.to_bytes())
+            .parse()
+            .map_err(|_| Error::new(ErrorKind::InvalidData, "value is not an integer"))?,
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "value is not an integer",
+            ));
+        }
+    };
+
+    let number = number.checked_add(delta).ok_or_else(|| {
+        Error::new(
+            ErrorKind::InvalidInput,
+            "increment or decrement would overflow",
+        )
+    })?;
+
+    pearl.0 = V::from_bytes(number.to_string().into_bytes());
+    pearl.1.touch();
+    Ok(number)
+}
+
+/// The error returned by `incr` and `decr` for keys which don't exist.
+pub(crate) fn missing_number() -> Error {
+    Error::new(ErrorKind::NotFound, "key doesn't exist")
+}
+
+/// Pack several values into one (`{length}:{bytes}` for every value), so a
+/// collection can be stored as a single value. Packed values are valid UTF-8 as
+/// long as every value is.
+pub(crate) fn pack(values: &[Vec<u8>]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for value in values {
+        out.extend_from_slice(value.len().to_string().as_bytes());
+        out.push(b':');
+        out.extend_from_slice(value);
+    }
+
+    out
+}
+
+/// Unpack values packed by [`pack`].
+///
+/// # Returns
+/// `None` if the bytes weren't created by [`pack`].
+pub(crate) fn unpack(mut bytes: &[u8]) -> Option<Vec<Vec<u8>>> {
+    let mut out = Vec::new();
+    while !bytes.is_empty() {
+        let colon = bytes.iter().position(|x| *x == b':')?;
+        let len: usize = std::str::from_utf8(&bytes[..colon]).ok()?.parse().ok()?;
+        let value = bytes.get(colon + 1..colon + 1 + len)?;
+
+        out.push(value.to_vec());
+        bytes = &bytes[colon + 1 + len..];
+    }
+
+    Some(out)
+}
+
+#[cfg(all(test, not(feature = "sqlite_backend")))]
+mod test {
+    use crate::compression::{Codec, Compression};
+    use crate::list::End;
+    use crate::{Oyster, OysterOptions};
+    use std::io::ErrorKind;
+
+    #[test]
+    fn binary() {
+        let value: Vec<u8> = (0..=255).chain(0..=255).collect();
+        let mut map: Oyster<String, Vec<u8>> = Oyster::with_options(OysterOptions {
+            compression: Compression::new(Codec::Lz4, 0),
+            ..Default::default()
+        });
+
+        map.insert("a".to_string(), value.clone());
+        map.insert("b".to_string(), vec![0xFF, 0x00, 0xFE]);
+        map.insert("c".to_string(), b"41".to_vec());
+        assert_eq!(map.incr("c".to_string()).unwrap(), 42);
+
+        assert_eq!(map.get(&"a".to_string()), Some(value));
+        assert_eq!(map.get(&"b".to_string()), Some(vec![0xFF, 0x00, 0xFE]));
+        assert_eq!(map.get(&"c".to_string()), Some(b"42".to_vec()));
+    }
+
+    #[test]
+    fn count() {
+        let mut map: Oyster<String, String> = Oyster::new();
+        map.insert("a".to_string(), "0".to_string());
+        assert_eq!(map.decr("a".to_string()).unwrap(), -1);
+        assert_eq!(map.get(&"a".to_string()), Some("-1".to_string()));
+
+        map.insert("max".to_string(), i64::MAX.to_string());
+        let e = map.incr("max".to_string()).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidInput);
+        assert_eq!(map.get(&"max".to_string()), Some(i64::MAX.to_string()));
+
+        map.insert("b".to_string(), "x".to_string());
+        let e = map.incr("b".to_string()).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+
+        map.push("list".to_string(), vec!["1".to_string()], End::Back);
+        let e = map.incr("list".to_string()).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+
+        let e = map.incr("missing".to_string()).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn pack() {
+        let values = vec![b"a:b".to_vec(), Vec::new(), vec![0xFF, b':', b'1']];
+        let packed = super::pack(&values);
+        assert_eq!(super::unpack(&packed), Some(values));
+        assert_eq!(super::unpack(b""), Some(Vec::new()));
+        assert_eq!(super::unpack(b"5:abc"), None);
+        assert_eq!(super::unpack(b"hello"), None);
+    }
+}